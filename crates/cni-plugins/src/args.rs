@@ -0,0 +1,20 @@
+/*
+Arguments which plugins take from both capabilities and `CNI_ARGS`.
+ */
+use libcni::schema::args::CniAddContext;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::MacAddress;
+
+/// The MAC address for the container interface, from the `mac` capability or the `MAC` argument.
+pub fn requested_mac(args: &CniAddContext) -> CniResult<Option<MacAddress>> {
+  let capability = args
+    .config
+    .plugin
+    .runtime_config
+    .as_ref()
+    .and_then(|config| config.mac.clone());
+  if capability.is_some() {
+    return Ok(capability);
+  }
+  args.args.mac()
+}
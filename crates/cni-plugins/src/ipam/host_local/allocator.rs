@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::Ips;
+
+use crate::config::Range;
+use crate::config::RangeSet;
+use crate::ip;
+use crate::store::Store;
+
+/// Allocates a single address from a range set.
+pub struct IpAllocator<'a> {
+  range_set: &'a RangeSet,
+  store:     &'a Store,
+  range_id:  usize,
+}
+
+impl<'a> IpAllocator<'a> {
+  pub fn new(range_set: &'a RangeSet, store: &'a Store, range_id: usize) -> Self {
+    Self {
+      range_set,
+      store,
+      range_id,
+    }
+  }
+
+  /// Reserves `requested` for the attachment, or else the first free address following the last
+  /// reserved one, so that addresses are not reused right after being released. An attachment
+  /// holds at most one address of the range set.
+  pub fn get(
+    &self,
+    container_id: &str,
+    if_name: &str,
+    requested: Option<IpAddr>,
+  ) -> CniResult<Ips> {
+    if let Some(ip) = self
+      .store
+      .get_by_id(container_id, if_name)?
+      .into_iter()
+      .find(|ip| self.range_set.contains(*ip))
+    {
+      return Err(CniErrorCode::Internal(
+        format!(
+          "{} has been allocated to {}, duplicate allocation is not allowed",
+          ip, container_id
+        )
+        .into(),
+      ));
+    }
+
+    let (ip, range) = match requested {
+      Some(ip) => {
+        let range = self.range_set.range_for(ip).ok_or_else(|| {
+          CniErrorCode::Internal(format!("{} not in range set {}", ip, self.range_set).into())
+        })?;
+        if range.gateway == Some(ip) {
+          return Err(CniErrorCode::Internal(
+            format!("requested ip {} is subnet's gateway", ip).into(),
+          ));
+        }
+        if !self
+          .store
+          .reserve(container_id, if_name, ip, self.range_id)?
+        {
+          return Err(CniErrorCode::Internal(
+            format!(
+              "requested IP address {} is not available in range set {}",
+              ip, self.range_set
+            )
+            .into(),
+          ));
+        }
+        (ip, range)
+      }
+      None => self.next_free(container_id, if_name)?,
+    };
+
+    let address = IpNetwork::new(ip, range.subnet.prefix())
+      .map_err(|e| CniErrorCode::Internal(e.to_string().into()))?;
+    Ok(Ips {
+      address:   address.to_string(),
+      gateway:   range.gateway,
+      interface: None,
+    })
+  }
+
+  fn next_free(&self, container_id: &str, if_name: &str) -> CniResult<(IpAddr, &'a Range)> {
+    let ranges = &self.range_set.ranges;
+    let next = |(index, ip): (usize, u128)| {
+      if ip < ip::to_u128(ranges[index].end) {
+        (index, ip + 1)
+      } else {
+        let index = (index + 1) % ranges.len();
+        (index, ip::to_u128(ranges[index].start))
+      }
+    };
+
+    let start = self
+      .store
+      .last_reserved_ip(self.range_id)
+      .and_then(|last| {
+        ranges
+          .iter()
+          .position(|range| range.contains(last))
+          .map(|index| next((index, ip::to_u128(last))))
+      })
+      .unwrap_or((0, ip::to_u128(ranges[0].start)));
+
+    let mut current = start;
+    loop {
+      let range = &ranges[current.0];
+      let ip = ip::from_u128(current.1, range.start);
+      if range.gateway != Some(ip)
+        && self
+          .store
+          .reserve(container_id, if_name, ip, self.range_id)?
+      {
+        return Ok((ip, range));
+      }
+
+      current = next(current);
+      if current == start {
+        return Err(CniErrorCode::Internal(
+          format!("no IP addresses available in range set: {}", self.range_set).into(),
+        ));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::config::HostLocalConfig;
+
+  #[test]
+  fn duplicate_allocation() {
+    let config = serde_json::from_value::<HostLocalConfig>(json!({ "subnet": "10.0.0.0/29" }))
+      .unwrap()
+      .range_sets(None)
+      .unwrap();
+    let dir = std::env::temp_dir().join(format!("host-local-allocator-{}", std::process::id()));
+    let store = Store::open(dir.clone()).unwrap();
+    let allocator = IpAllocator::new(&config[0], &store, 0);
+
+    let first = allocator.get("c1", "eth0", None).unwrap();
+    assert_eq!(first.address, "10.0.0.2/29");
+    let error = allocator.get("c1", "eth0", None).unwrap_err();
+    assert!(libcni::schema::error::CniError::from(error)
+      .message
+      .contains("10.0.0.2 has been allocated to c1, duplicate allocation is not allowed"));
+    assert_eq!(store.get_by_id("c1", "eth0").unwrap().len(), 1);
+    assert_eq!(
+      allocator.get("c1", "eth1", None).unwrap().address,
+      "10.0.0.3/29"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,275 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use ipnetwork::IpNetwork;
+use libcni::schema::config::DNSConfig;
+use libcni::schema::config::IPRange;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::config::RuntimeConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::Route;
+use libcni::schema::DEFAULT_NET_CACHE_DIR;
+use serde::Deserialize;
+
+use crate::ip;
+
+/// The `ipam` dictionary of the network configuration.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostLocalConfig {
+  /// A list of range sets, each allocating one address of a single IP version.
+  #[serde(default)]
+  pub ranges:      Vec<Vec<IPRange>>,
+  /// A single range, in the format preceding `ranges`.
+  #[serde(default)]
+  pub subnet:      Option<IpNetwork>,
+  #[serde(default)]
+  pub range_start: Option<IpAddr>,
+  #[serde(default)]
+  pub range_end:   Option<IpAddr>,
+  #[serde(default)]
+  pub gateway:     Option<IpAddr>,
+  /// Routes to return in the result.
+  #[serde(default)]
+  pub routes:      Vec<Route>,
+  /// Where allocations are stored, defaults to `/var/lib/cni/networks`.
+  #[serde(default)]
+  pub data_dir:    Option<PathBuf>,
+  /// A `resolv.conf` file to read the DNS configuration of the result from.
+  #[serde(default)]
+  pub resolv_conf: Option<PathBuf>,
+}
+
+impl HostLocalConfig {
+  pub fn load(config: &NetworkConfig) -> CniResult<Self> {
+    config
+      .plugin
+      .ipam
+      .as_ref()
+      .ok_or_else(|| {
+        CniErrorCode::InvalidNetworkConfig("missing IPAM configuration in network config".into())
+      })?
+      .parse()
+  }
+
+  /// The directory storing the allocations of network `network`.
+  pub fn data_dir(&self, network: &str) -> PathBuf {
+    self
+      .data_dir
+      .clone()
+      .unwrap_or_else(|| Path::new(DEFAULT_NET_CACHE_DIR).join("networks"))
+      .join(network)
+  }
+
+  /// The validated range sets to allocate from. Ranges passed by the `ipRanges` capability
+  /// replace the configured ones.
+  pub fn range_sets(&self, runtime_config: Option<&RuntimeConfig>) -> CniResult<Vec<RangeSet>> {
+    let ranges = match runtime_config.and_then(|config| config.ip_ranges.as_ref()) {
+      Some(ranges) if !ranges.is_empty() => ranges.clone(),
+      _ => {
+        let mut ranges = self.ranges.clone();
+        if let Some(subnet) = self.subnet {
+          ranges.insert(
+            0,
+            vec![IPRange {
+              subnet,
+              range_start: self.range_start,
+              range_end: self.range_end,
+              gateway: self.gateway,
+            }],
+          );
+        }
+        ranges
+      }
+    };
+
+    if ranges.is_empty() {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "no IP ranges specified".into(),
+      ));
+    }
+
+    let range_sets = ranges
+      .iter()
+      .map(|ranges| RangeSet::new(ranges))
+      .collect::<CniResult<Vec<_>>>()?;
+
+    for (i, set) in range_sets.iter().enumerate() {
+      for (j, other) in range_sets.iter().enumerate().skip(i + 1) {
+        if set.overlaps(other) {
+          return Err(CniErrorCode::InvalidNetworkConfig(
+            format!("range set {} overlaps with {}", i, j).into(),
+          ));
+        }
+      }
+    }
+    Ok(range_sets)
+  }
+}
+
+/// A range of addresses to allocate from, with defaults applied.
+#[derive(Clone, Debug)]
+pub struct Range {
+  pub subnet:  IpNetwork,
+  pub start:   IpAddr,
+  pub end:     IpAddr,
+  pub gateway: Option<IpAddr>,
+}
+
+impl Range {
+  /// Defaults to the whole subnet without its network address, and its broadcast address for
+  /// IPv4. The gateway defaults to the first address of the subnet.
+  fn new(range: &IPRange) -> CniResult<Self> {
+    let invalid = |message: String| CniErrorCode::InvalidNetworkConfig(message.into());
+
+    let subnet = IpNetwork::new(range.subnet.network(), range.subnet.prefix())
+      .map_err(|e| invalid(e.to_string()))?;
+    let (first, last) = (ip::first(&subnet), ip::last(&subnet));
+    if last - first < 3 {
+      return Err(invalid(format!(
+        "network {} too small to allocate from",
+        subnet
+      )));
+    }
+
+    let family = subnet.network();
+    let in_subnet = |ip: IpAddr, name: &str| {
+      if subnet.contains(ip) {
+        Ok(ip)
+      } else {
+        Err(invalid(format!(
+          "{} {} not in network {}",
+          name, ip, subnet
+        )))
+      }
+    };
+
+    let start = match range.range_start {
+      Some(start) => in_subnet(start, "rangeStart")?,
+      None => ip::from_u128(first + 1, family),
+    };
+    let end = match range.range_end {
+      Some(end) => in_subnet(end, "rangeEnd")?,
+      None if subnet.is_ipv4() => ip::from_u128(last - 1, family),
+      None => ip::from_u128(last, family),
+    };
+    if ip::to_u128(start) > ip::to_u128(end) {
+      return Err(invalid(format!(
+        "rangeStart {} is after rangeEnd {}",
+        start, end
+      )));
+    }
+
+    let gateway = match range.gateway {
+      Some(gateway) => Some(in_subnet(gateway, "gateway")?),
+      None => Some(ip::from_u128(first + 1, family)),
+    };
+
+    Ok(Self {
+      subnet,
+      start,
+      end,
+      gateway,
+    })
+  }
+
+  pub fn contains(&self, ip: IpAddr) -> bool {
+    self.subnet.contains(ip)
+      && ip::to_u128(self.start) <= ip::to_u128(ip)
+      && ip::to_u128(ip) <= ip::to_u128(self.end)
+  }
+
+  fn overlaps(&self, other: &Range) -> bool {
+    self.subnet.is_ipv4() == other.subnet.is_ipv4()
+      && ip::to_u128(self.start) <= ip::to_u128(other.end)
+      && ip::to_u128(other.start) <= ip::to_u128(self.end)
+  }
+}
+
+impl Display for Range {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}-{}", self.start, self.end)
+  }
+}
+
+/// Ranges of the same IP version, from which a single address is allocated.
+#[derive(Clone, Debug)]
+pub struct RangeSet {
+  pub ranges: Vec<Range>,
+}
+
+impl RangeSet {
+  fn new(ranges: &[IPRange]) -> CniResult<Self> {
+    let ranges = ranges
+      .iter()
+      .map(Range::new)
+      .collect::<CniResult<Vec<_>>>()?;
+    let Some(first) = ranges.first() else {
+      return Err(CniErrorCode::InvalidNetworkConfig("empty range set".into()));
+    };
+
+    if ranges
+      .iter()
+      .any(|range| range.subnet.is_ipv4() != first.subnet.is_ipv4())
+    {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "mixed address families in range set".into(),
+      ));
+    }
+    for (i, range) in ranges.iter().enumerate() {
+      for other in ranges.iter().skip(i + 1) {
+        if range.overlaps(other) {
+          return Err(CniErrorCode::InvalidNetworkConfig(
+            format!("subnets {} and {} overlap", range.subnet, other.subnet).into(),
+          ));
+        }
+      }
+    }
+    Ok(Self { ranges })
+  }
+
+  /// The range containing `ip`.
+  pub fn range_for(&self, ip: IpAddr) -> Option<&Range> {
+    self.ranges.iter().find(|range| range.contains(ip))
+  }
+
+  pub fn contains(&self, ip: IpAddr) -> bool {
+    self.range_for(ip).is_some()
+  }
+
+  fn overlaps(&self, other: &RangeSet) -> bool {
+    self
+      .ranges
+      .iter()
+      .any(|range| other.ranges.iter().any(|other| range.overlaps(other)))
+  }
+}
+
+impl Display for RangeSet {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let ranges = self.ranges.iter().map(Range::to_string).collect::<Vec<_>>();
+    f.write_str(&ranges.join(","))
+  }
+}
+
+/// Reads the DNS configuration of the result from a `resolv.conf` file.
+pub fn parse_resolv_conf(path: &Path) -> CniResult<DNSConfig> {
+  let content = std::fs::read_to_string(path).map_err(CniErrorCode::IOFailure)?;
+
+  let mut dns = DNSConfig::default();
+  for line in content.lines() {
+    let mut fields = line.split_whitespace();
+    match fields.next() {
+      Some("nameserver") => dns.nameservers.extend(fields.next().map(str::to_string)),
+      Some("domain") => dns.domain = fields.next().unwrap_or_default().to_string(),
+      Some("search") => dns.search.extend(fields.map(str::to_string)),
+      Some("options") => dns.options.extend(fields.map(str::to_string)),
+      _ => {}
+    }
+  }
+  Ok(dns)
+}
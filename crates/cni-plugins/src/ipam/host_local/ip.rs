@@ -0,0 +1,36 @@
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+
+use ipnetwork::IpNetwork;
+
+/// The address as an integer, to walk through ranges of either IP version alike.
+pub fn to_u128(ip: IpAddr) -> u128 {
+  match ip {
+    IpAddr::V4(ip) => u32::from(ip) as u128,
+    IpAddr::V6(ip) => u128::from(ip),
+  }
+}
+
+/// The address `value` of the same IP version as `family`.
+pub fn from_u128(value: u128, family: IpAddr) -> IpAddr {
+  match family {
+    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(value as u32)),
+    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(value)),
+  }
+}
+
+/// The first address of the subnet.
+pub fn first(subnet: &IpNetwork) -> u128 {
+  to_u128(subnet.network())
+}
+
+/// The last address of the subnet, which is the broadcast address for IPv4.
+pub fn last(subnet: &IpNetwork) -> u128 {
+  let bits = if subnet.is_ipv4() { 32 } else { 128 };
+  let host_bits = bits - subnet.prefix() as u32;
+  let host_mask = 1u128
+    .checked_shl(host_bits)
+    .map_or(u128::MAX, |bit| bit - 1);
+  first(subnet) | host_mask
+}
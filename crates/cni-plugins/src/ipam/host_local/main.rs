@@ -0,0 +1,169 @@
+/*
+[host-local IP address management plugin](https://www.cni.dev/plugins/current/ipam/host-local/)
+
+Allocates addresses out of a set of ranges, keeping track of them in a local directory.
+ */
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use libcni::api::CniPlugin;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniArgs;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniError;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::Ips;
+use libcni::version;
+
+use crate::allocator::IpAllocator;
+use crate::config::parse_resolv_conf;
+use crate::config::HostLocalConfig;
+use crate::store::Store;
+
+mod allocator;
+mod config;
+mod ip;
+mod store;
+
+struct HostLocal;
+
+impl CniPlugin for HostLocal {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = HostLocalConfig::load(&args.config)?;
+    let range_sets = config.range_sets(args.config.plugin.runtime_config.as_ref())?;
+    let mut requested = requested_ips(&args.config, &args.args)?;
+
+    let store = Store::open(config.data_dir(&args.config.name))?;
+    let mut ips = Vec::with_capacity(range_sets.len());
+    for (range_id, range_set) in range_sets.iter().enumerate() {
+      let requested = requested
+        .iter()
+        .position(|ip| range_set.contains(*ip))
+        .map(|index| requested.remove(index));
+
+      let allocator = IpAllocator::new(range_set, &store, range_id);
+      match allocator.get(&args.container_id, &args.if_name, requested) {
+        Ok(ip) => ips.push(ip),
+        Err(e) => {
+          release(&store, &ips)?;
+          return Err(e);
+        }
+      }
+    }
+
+    if !requested.is_empty() {
+      release(&store, &ips)?;
+      return Err(CniErrorCode::Internal(
+        format!(
+          "failed to allocate all requested IPs: {}",
+          requested
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+        )
+        .into(),
+      ));
+    }
+
+    let dns = config
+      .resolv_conf
+      .as_deref()
+      .map(parse_resolv_conf)
+      .transpose()?
+      .unwrap_or_default();
+
+    Ok(AddReply {
+      cni_version: args.config.cni_version,
+      interfaces: Vec::new(),
+      ips,
+      routes: config.routes,
+      dns,
+    })
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    let config = HostLocalConfig::load(&args.config)?;
+    let store = Store::open(config.data_dir(&args.config.name))?;
+    store.release_by_id(&args.container_id, &args.if_name)
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = HostLocalConfig::load(&args.config)?;
+    let store = Store::open(config.data_dir(&args.config.name))?;
+    if store
+      .get_by_id(&args.container_id, &args.if_name)?
+      .is_empty()
+    {
+      return Err(CniErrorCode::Internal(
+        format!(
+          "host-local: Failed to find address added by container {}",
+          args.container_id
+        )
+        .into(),
+      ));
+    }
+    Ok(())
+  }
+
+  /// Releases the addresses of every attachment the runtime no longer knows about.
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    let config = HostLocalConfig::load(&args.config)?;
+    let store = Store::open(config.data_dir(&args.config.name))?;
+    let stale = args.stale_attachments(store.allocations()?, |allocation| {
+      (&allocation.container_id, &allocation.if_name)
+    });
+    for allocation in stale {
+      store.release(allocation.ip)?;
+    }
+    Ok(())
+  }
+
+  /// Ready as long as the configuration is valid and its store can be opened.
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    let config = HostLocalConfig::load(&args.config)?;
+    config.range_sets(args.config.plugin.runtime_config.as_ref())?;
+    Store::open(config.data_dir(&args.config.name))
+      .map(|_| ())
+      .map_err(|e| {
+        let error = CniError::from(e);
+        CniErrorCode::PluginNotAvailable(error.details.unwrap_or(error.message))
+      })
+  }
+}
+
+/// Releases the addresses reserved by this `ADD`, leaving those of earlier ones alone.
+fn release(store: &Store, ips: &[Ips]) -> CniResult<()> {
+  for ips in ips {
+    if let Ok(address) = ips.address.parse::<IpNetwork>() {
+      store.release(address.ip())?;
+    }
+  }
+  Ok(())
+}
+
+/// Addresses requested with the `ips` capability, or with the `IP` argument as a comma separated
+/// list. Prefix lengths are left to the ranges of the addresses.
+fn requested_ips(config: &NetworkConfig, args: &CniArgs) -> CniResult<Vec<IpAddr>> {
+  let mut ips = config
+    .plugin
+    .runtime_config
+    .as_ref()
+    .and_then(|config| config.ips.as_ref())
+    .map(|ips| ips.iter().map(|ip| ip.ip()).collect::<Vec<_>>())
+    .unwrap_or_default();
+
+  ips.extend(args.ip()?.iter().map(IpNetwork::ip));
+  Ok(ips)
+}
+
+fn main() {
+  plugin_main_entrypoint(HostLocal, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use nix::fcntl::Flock;
+use nix::fcntl::FlockArg;
+
+/// Separates the container ID from the interface name in allocation files.
+const LINE_BREAK: &str = "\r\n";
+/// Prefix of the files remembering the last address allocated from each range set.
+const LAST_IP_FILE_PREFIX: &str = "last_reserved_ip.";
+
+/// An address reserved for an attachment.
+#[derive(Clone, Debug)]
+pub struct Allocation {
+  pub ip:           IpAddr,
+  pub container_id: String,
+  pub if_name:      String,
+}
+
+/// Allocations of a network, kept as one file per address named after it, in the same layout as
+/// the reference plugin. The directory is locked for as long as the store is open.
+pub struct Store {
+  dir:   PathBuf,
+  _lock: Flock<File>,
+}
+
+impl Store {
+  pub fn open(dir: PathBuf) -> CniResult<Self> {
+    std::fs::create_dir_all(&dir).map_err(CniErrorCode::IOFailure)?;
+    let file = File::open(&dir).map_err(CniErrorCode::IOFailure)?;
+    let lock = Flock::lock(file, FlockArg::LockExclusive)
+      .map_err(|(_, errno)| CniErrorCode::IOFailure(errno.into()))?;
+    Ok(Self { dir, _lock: lock })
+  }
+
+  /// Reserves `ip` for the attachment, returning `false` if it is already taken.
+  pub fn reserve(
+    &self,
+    container_id: &str,
+    if_name: &str,
+    ip: IpAddr,
+    range_id: usize,
+  ) -> CniResult<bool> {
+    let path = self.dir.join(ip.to_string());
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+      Ok(file) => file,
+      Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+      Err(e) => return Err(CniErrorCode::IOFailure(e)),
+    };
+
+    let owner = format!("{}{}{}", container_id.trim(), LINE_BREAK, if_name);
+    if let Err(e) = file.write_all(owner.as_bytes()) {
+      let _ = std::fs::remove_file(&path);
+      return Err(CniErrorCode::IOFailure(e));
+    }
+
+    std::fs::write(self.last_ip_file(range_id), ip.to_string()).map_err(CniErrorCode::IOFailure)?;
+    Ok(true)
+  }
+
+  /// The address last reserved from range set `range_id`.
+  pub fn last_reserved_ip(&self, range_id: usize) -> Option<IpAddr> {
+    std::fs::read_to_string(self.last_ip_file(range_id))
+      .ok()
+      .and_then(|ip| ip.trim().parse().ok())
+  }
+
+  /// Every address reserved in the network.
+  pub fn allocations(&self) -> CniResult<Vec<Allocation>> {
+    let mut allocations = Vec::new();
+    for entry in std::fs::read_dir(&self.dir).map_err(CniErrorCode::IOFailure)? {
+      let path = entry.map_err(CniErrorCode::IOFailure)?.path();
+      let Some(ip) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.parse::<IpAddr>().ok())
+      else {
+        continue;
+      };
+
+      let content = std::fs::read_to_string(&path).map_err(CniErrorCode::IOFailure)?;
+      let (container_id, if_name) = content.split_once(LINE_BREAK).unwrap_or((&content, ""));
+      allocations.push(Allocation {
+        ip,
+        container_id: container_id.trim().to_string(),
+        if_name: if_name.trim().to_string(),
+      });
+    }
+    Ok(allocations)
+  }
+
+  /// The addresses reserved for the attachment.
+  pub fn get_by_id(&self, container_id: &str, if_name: &str) -> CniResult<Vec<IpAddr>> {
+    Ok(
+      self
+        .allocations()?
+        .into_iter()
+        .filter(|allocation| {
+          allocation.container_id == container_id && allocation.if_name == if_name
+        })
+        .map(|allocation| allocation.ip)
+        .collect(),
+    )
+  }
+
+  /// Releases every address reserved for the attachment.
+  pub fn release_by_id(&self, container_id: &str, if_name: &str) -> CniResult<()> {
+    for ip in self.get_by_id(container_id, if_name)? {
+      self.release(ip)?;
+    }
+    Ok(())
+  }
+
+  pub fn release(&self, ip: IpAddr) -> CniResult<()> {
+    match std::fs::remove_file(self.dir.join(ip.to_string())) {
+      Err(e) if e.kind() != ErrorKind::NotFound => Err(CniErrorCode::IOFailure(e)),
+      _ => Ok(()),
+    }
+  }
+
+  fn last_ip_file(&self, range_id: usize) -> PathBuf {
+    self
+      .dir
+      .join(format!("{}{}", LAST_IP_FILE_PREFIX, range_id))
+  }
+}
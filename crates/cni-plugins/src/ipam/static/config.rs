@@ -0,0 +1,143 @@
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use libcni::schema::args::CniArgs;
+use libcni::schema::config::DNSConfig;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::Route;
+use serde::Deserialize;
+
+/// The `ipam` dictionary of the network configuration.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaticConfig {
+  #[serde(default)]
+  pub addresses: Vec<Address>,
+  /// Routes to return in the result.
+  #[serde(default)]
+  pub routes:    Vec<Route>,
+  /// DNS configuration to return in the result.
+  #[serde(default)]
+  pub dns:       DNSConfig,
+}
+
+/// An address of the container, with the gateway of its network.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+  pub address: IpNetwork,
+  #[serde(default)]
+  pub gateway: Option<IpAddr>,
+}
+
+/// The `CNI_ARGS` of the static plugin, lists separated by commas.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct StaticArgs {
+  /// Parsed by [`CniArgs::ip`].
+  #[serde(default)]
+  #[serde(rename = "IP")]
+  _ip:     Option<String>,
+  #[serde(default)]
+  gateway: Vec<IpAddr>,
+}
+
+/// Addresses passed in the `args` of the network configuration, as `{"cni": {"ips": [...]}}`.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigArgs {
+  #[serde(default)]
+  ips: Vec<IpNetwork>,
+}
+
+impl StaticConfig {
+  /// Loads the configuration with the addresses of the runtime. The `IP` argument adds addresses
+  /// to the configured ones, and the `GATEWAY` argument sets the gateway of those in its networks.
+  /// Addresses in the `args` of the network configuration replace those, and addresses passed by
+  /// the `ips` capability replace them all.
+  pub fn load(config: &NetworkConfig, args: &CniArgs) -> CniResult<Self> {
+    let mut loaded = config
+      .plugin
+      .ipam
+      .as_ref()
+      .ok_or_else(|| {
+        CniErrorCode::InvalidNetworkConfig("missing IPAM configuration in network config".into())
+      })?
+      .parse::<Self>()?;
+
+    loaded
+      .addresses
+      .extend(args.ip()?.into_iter().map(Address::new));
+    for gateway in args.parse::<StaticArgs>()?.gateway {
+      for address in loaded.addresses.iter_mut() {
+        if address.address.contains(gateway) {
+          address.gateway = Some(gateway);
+        }
+      }
+    }
+
+    let config_args = match config.plugin.args.as_ref().and_then(|args| args.get("cni")) {
+      Some(args) => serde_json::from_value::<ConfigArgs>(args.clone())
+        .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))?,
+      None => ConfigArgs::default(),
+    };
+    if !config_args.ips.is_empty() {
+      loaded.addresses = config_args.ips.into_iter().map(Address::new).collect();
+    }
+    if let Some(ips) = config
+      .plugin
+      .runtime_config
+      .as_ref()
+      .and_then(|config| config.ips.as_ref())
+      .filter(|ips| !ips.is_empty())
+    {
+      loaded.addresses = ips.iter().copied().map(Address::new).collect();
+    }
+
+    loaded.validate()?;
+    Ok(loaded)
+  }
+
+  fn validate(&self) -> CniResult<()> {
+    if self.addresses.is_empty() {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "no addresses given".into(),
+      ));
+    }
+    for address in self.addresses.iter() {
+      if let Some(gateway) = address.gateway {
+        if gateway.is_ipv4() != address.address.is_ipv4() {
+          return Err(CniErrorCode::InvalidNetworkConfig(
+            format!(
+              "gateway {} of address {} is of another IP version",
+              gateway, address.address
+            )
+            .into(),
+          ));
+        }
+      }
+    }
+    for route in self.routes.iter() {
+      if !self
+        .addresses
+        .iter()
+        .any(|address| address.address.is_ipv4() == route.dst.is_ipv4())
+      {
+        return Err(CniErrorCode::InvalidNetworkConfig(
+          format!("no address of the IP version of route {}", route.dst).into(),
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Address {
+  fn new(address: IpNetwork) -> Self {
+    Self {
+      address,
+      gateway: None,
+    }
+  }
+}
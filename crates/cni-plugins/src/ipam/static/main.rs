@@ -0,0 +1,66 @@
+/*
+[static IP address management plugin](https://www.cni.dev/plugins/current/ipam/static/)
+
+Assigns fixed addresses, given by the network configuration or the runtime, and keeps no state.
+ */
+use libcni::api::CniPlugin;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::Ips;
+use libcni::version;
+
+use crate::config::StaticConfig;
+
+mod config;
+
+struct Static;
+
+impl CniPlugin for Static {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = StaticConfig::load(&args.config, &args.args)?;
+    let ips = config
+      .addresses
+      .iter()
+      .map(|address| Ips {
+        address:   address.address.to_string(),
+        gateway:   address.gateway,
+        interface: None,
+      })
+      .collect();
+
+    Ok(AddReply {
+      cni_version: args.config.cni_version,
+      interfaces: Vec::new(),
+      ips,
+      routes: config.routes,
+      dns: config.dns,
+    })
+  }
+
+  /// Nothing was allocated, so there is nothing to release.
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    StaticConfig::load(&args.config, &args.args).map(|_| ())
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    StaticConfig::load(&args.config, &args.args).map(|_| ())
+  }
+
+  fn gc(&self, _args: CniGcContext) -> CniResult<()> {
+    Ok(())
+  }
+
+  fn status(&self, _args: CniStatusContext) -> CniResult<()> {
+    Ok(())
+  }
+}
+
+fn main() {
+  plugin_main_entrypoint(Static, version::All, semver::Version::new(1, 1, 0))
+}
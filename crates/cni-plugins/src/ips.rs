@@ -0,0 +1,104 @@
+/*
+Addresses allocated by the IPAM plugin, as given to the interfaces of containers.
+ */
+use std::future::Future;
+
+use ipnetwork::IpNetwork;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::plugin::ipam;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+
+/// Delegates to the IPAM plugin, and hands its result over to `configure`. The allocation is
+/// released if the container cannot use it.
+pub async fn delegate<F, Fut>(args: &CniAddContext, configure: F) -> CniResult<AddReply>
+where
+  F: FnOnce(AddReply) -> Fut,
+  Fut: Future<Output = CniResult<AddReply>>,
+{
+  let result = ipam::exec_add(args)?;
+  match configure(result).await {
+    Ok(result) => Ok(result),
+    Err(e) => {
+      let _ = ipam::exec_del(&args.config, &args.path);
+      Err(e)
+    }
+  }
+}
+
+/// Delegates to the IPAM plugin as [`delegate`] if the network has one. Link `index` of the
+/// container is only brought up otherwise, and the result has no addresses.
+pub async fn delegate_or_up<F, Fut>(
+  args: &CniAddContext,
+  container: &Netlink,
+  index: u32,
+  configure: F,
+) -> CniResult<AddReply>
+where
+  F: FnOnce(AddReply) -> Fut,
+  Fut: Future<Output = CniResult<AddReply>>,
+{
+  if args.config.plugin.ipam.is_some() {
+    return delegate(args, configure).await;
+  }
+  container.set_up(index).await?;
+  Ok(AddReply {
+    cni_version: args.config.cni_version.clone(),
+    interfaces:  Vec::new(),
+    ips:         Vec::new(),
+    routes:      Vec::new(),
+    dns:         Default::default(),
+  })
+}
+
+/// Fails on a result of the IPAM plugin without any address.
+pub fn require_ips(result: &AddReply) -> CniResult<()> {
+  if result.ips.is_empty() {
+    return Err(CniErrorCode::Internal(
+      "IPAM plugin returned missing IP config".into(),
+    ));
+  }
+  Ok(())
+}
+
+/// Gives the addresses and routes of `result` to link `index` of the container, as interface
+/// `interface` of the result. The result is returned in the version of the request, with the DNS
+/// configuration of the network if any.
+pub async fn configure_ips(
+  args: &CniAddContext,
+  container: &Netlink,
+  index: u32,
+  interface: usize,
+  mut result: AddReply,
+) -> CniResult<AddReply> {
+  require_ips(&result)?;
+  result.cni_version = args.config.cni_version.clone();
+  for ips in result.ips.iter_mut() {
+    ips.interface = Some(interface);
+  }
+  container.configure(index, &result, interface).await?;
+  if let Some(dns) = args.config.plugin.dns.clone() {
+    result.dns = dns;
+  }
+  Ok(result)
+}
+
+/// Deletes the interface of the container, and returns the addresses it had.
+pub async fn del_container_link(args: &CniDelContext) -> CniResult<Vec<IpNetwork>> {
+  // Without its namespace, the container's interface is gone, and the other end of a veth pair
+  // with it.
+  let Some(Ok(netns)) = args.netns.as_deref().map(NetNs::open) else {
+    return Ok(Vec::new());
+  };
+  let container = Netlink::open(&netns)?;
+  let Some(link) = container.link(&args.if_name).await? else {
+    return Ok(Vec::new());
+  };
+  let addresses = container.addresses(link.index).await?;
+  container.del_link(link.index).await?;
+  Ok(addresses)
+}
@@ -0,0 +1,247 @@
+/*
+Rules of the `iptables` and `ip6tables` commands, named and commented after the attachment they
+belong to, in the same format as the reference plugins.
+ */
+use std::net::IpAddr;
+use std::process::Command;
+
+use ipnetwork::IpNetwork;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use sha2::Digest;
+use sha2::Sha512;
+
+/// Prefix of the chains created for attachments.
+const CHAIN_PREFIX: &str = "CNI-";
+/// Chain names are limited by iptables.
+const MAX_CHAIN_LENGTH: usize = 28;
+
+/// The chain of the attachment of container `container_id` to network `network`.
+pub fn chain_name(network: &str, container_id: &str) -> String {
+  prefixed_chain_name("", network, container_id)
+}
+
+/// The chain of the attachment, for a plugin with several chains per attachment told apart by
+/// `prefix`, like `DN-`.
+pub fn prefixed_chain_name(prefix: &str, network: &str, container_id: &str) -> String {
+  let digest = Sha512::digest(format!("{}{}", network, container_id).as_bytes());
+  let hex = digest
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  format!(
+    "{}{}{}",
+    CHAIN_PREFIX,
+    prefix,
+    &hex[..MAX_CHAIN_LENGTH - CHAIN_PREFIX.len() - prefix.len()]
+  )
+}
+
+/// The comment of the rules of the attachment.
+pub fn comment(network: &str, container_id: &str) -> String {
+  format!("name: {:?} id: {:?}", network, container_id)
+}
+
+/// The `iptables` command of an IP version.
+#[derive(Clone, Copy, Debug)]
+pub struct IpTables {
+  command: &'static str,
+}
+
+impl IpTables {
+  pub fn for_ip(ip: IpAddr) -> Self {
+    Self {
+      command: if ip.is_ipv4() {
+        "iptables"
+      } else {
+        "ip6tables"
+      },
+    }
+  }
+
+  /// Whether the command can be run.
+  pub fn available(&self) -> bool {
+    Command::new(self.command)
+      .arg("--version")
+      .output()
+      .is_ok_and(|output| output.status.success())
+  }
+
+  pub fn exists(&self, table: &str, chain: &str, rule: &[&str]) -> CniResult<bool> {
+    let status = Command::new(self.command)
+      .args(["--wait", "-t", table, "-C", chain])
+      .args(rule)
+      .output()
+      .map_err(CniErrorCode::IOFailure)?
+      .status;
+    Ok(status.success())
+  }
+
+  /// Appends `rule` to `chain`, unless it is there already.
+  pub fn append_unique(&self, table: &str, chain: &str, rule: &[&str]) -> CniResult<()> {
+    if self.exists(table, chain, rule)? {
+      return Ok(());
+    }
+    self.run(&[&["-t", table, "-A", chain], rule].concat())
+  }
+
+  /// Deletes `rule` from `chain`, if it is there.
+  pub fn delete(&self, table: &str, chain: &str, rule: &[&str]) -> CniResult<()> {
+    if !self.exists(table, chain, rule)? {
+      return Ok(());
+    }
+    self.run(&[&["-t", table, "-D", chain], rule].concat())
+  }
+
+  /// Creates `chain`, unless it exists already.
+  pub fn ensure_chain(&self, table: &str, chain: &str) -> CniResult<()> {
+    let exists = Command::new(self.command)
+      .args(["--wait", "-t", table, "-n", "-L", chain])
+      .output()
+      .map_err(CniErrorCode::IOFailure)?
+      .status
+      .success();
+    if exists {
+      return Ok(());
+    }
+    self.run(&["-t", table, "-N", chain])
+  }
+
+  /// Flushes and deletes `chain`, if it exists.
+  pub fn delete_chain(&self, table: &str, chain: &str) -> CniResult<()> {
+    let flushed = Command::new(self.command)
+      .args(["--wait", "-t", table, "-F", chain])
+      .output()
+      .map_err(CniErrorCode::IOFailure)?
+      .status
+      .success();
+    if !flushed {
+      return Ok(());
+    }
+    self.run(&["-t", table, "-X", chain])
+  }
+
+  /// The rules of `chain` as listed by `-S`, without the chain itself. The rule at index `i` is
+  /// rule number `i + 1`. None if the chain does not exist.
+  pub fn rules(&self, table: &str, chain: &str) -> CniResult<Vec<String>> {
+    let output = Command::new(self.command)
+      .args(["--wait", "-t", table, "-S", chain])
+      .output()
+      .map_err(CniErrorCode::IOFailure)?;
+    if !output.status.success() {
+      return Ok(Vec::new());
+    }
+    Ok(
+      String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.starts_with("-A "))
+        .map(str::to_string)
+        .collect(),
+    )
+  }
+
+  /// Deletes rule number `number` of `chain`, counting from 1.
+  pub fn delete_number(&self, table: &str, chain: &str, number: usize) -> CniResult<()> {
+    self.run(&["-t", table, "-D", chain, &number.to_string()])
+  }
+
+  fn run(&self, args: &[&str]) -> CniResult<()> {
+    let output = Command::new(self.command)
+      .arg("--wait")
+      .args(args)
+      .output()
+      .map_err(CniErrorCode::IOFailure)?;
+    if !output.status.success() {
+      return Err(CniErrorCode::Internal(
+        format!(
+          "{} {}: {}",
+          self.command,
+          args.join(" "),
+          String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into(),
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Masquerades traffic from the address of `network` leaving it, through chain `chain`.
+pub fn setup_ip_masq(network: IpNetwork, chain: &str, comment: &str) -> CniResult<()> {
+  let iptables = IpTables::for_ip(network.ip());
+  let multicast = if network.is_ipv4() {
+    "224.0.0.0/4"
+  } else {
+    "ff00::/8"
+  };
+  let destination = network.to_string();
+  let source = network.ip().to_string();
+
+  iptables.ensure_chain("nat", chain)?;
+  // Traffic within the network is left alone, as is multicast.
+  iptables.append_unique(
+    "nat",
+    chain,
+    &[
+      "-d",
+      &destination,
+      "-j",
+      "ACCEPT",
+      "-m",
+      "comment",
+      "--comment",
+      comment,
+    ],
+  )?;
+  iptables.append_unique(
+    "nat",
+    chain,
+    &[
+      "!",
+      "-d",
+      multicast,
+      "-j",
+      "MASQUERADE",
+      "-m",
+      "comment",
+      "--comment",
+      comment,
+    ],
+  )?;
+  iptables.append_unique(
+    "nat",
+    "POSTROUTING",
+    &[
+      "-s",
+      &source,
+      "-j",
+      chain,
+      "-m",
+      "comment",
+      "--comment",
+      comment,
+    ],
+  )
+}
+
+/// Removes what [`setup_ip_masq`] installed.
+pub fn teardown_ip_masq(network: IpNetwork, chain: &str, comment: &str) -> CniResult<()> {
+  let iptables = IpTables::for_ip(network.ip());
+  let source = network.ip().to_string();
+
+  iptables.delete(
+    "nat",
+    "POSTROUTING",
+    &[
+      "-s",
+      &source,
+      "-j",
+      chain,
+      "-m",
+      "comment",
+      "--comment",
+      comment,
+    ],
+  )?;
+  iptables.delete_chain("nat", chain)
+}
@@ -0,0 +1,10 @@
+//! Helpers shared by the reference plugins.
+
+pub mod args;
+pub mod ips;
+pub mod iptables;
+pub mod master;
+pub mod nftables;
+pub mod stacked;
+pub mod sysctl;
+pub mod validate;
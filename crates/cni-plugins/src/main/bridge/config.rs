@@ -0,0 +1,51 @@
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use serde::Deserialize;
+
+/// The network configuration keys of the bridge plugin.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeConfig {
+  /// Name of the bridge to use or create, `cni0` by default.
+  #[serde(default = "default_bridge")]
+  pub bridge:             String,
+  /// Assigns the gateway address of each IP to the bridge.
+  #[serde(default)]
+  pub is_gateway:         bool,
+  /// Routes the default traffic of the container through the bridge, implies `isGateway`.
+  #[serde(default)]
+  pub is_default_gateway: bool,
+  /// Replaces an address of the bridge in the subnet of a gateway, instead of failing.
+  #[serde(default)]
+  pub force_address:      bool,
+  /// MTU of the bridge and veth pair, defaults to the kernel's.
+  #[serde(default)]
+  pub mtu:                Option<u32>,
+  /// Lets containers reach themselves through services of the host.
+  #[serde(default)]
+  pub hairpin_mode:       bool,
+  /// Puts the bridge in promiscuous mode.
+  #[serde(default)]
+  pub promisc_mode:       bool,
+  /// Tags the traffic of the container with this VLAN, on a VLAN filtering bridge.
+  #[serde(default)]
+  pub vlan:               Option<u16>,
+}
+
+fn default_bridge() -> String {
+  "cni0".to_string()
+}
+
+impl BridgeConfig {
+  pub fn load(config: &NetworkConfig) -> CniResult<Self> {
+    let mut bridge = config.plugin.parse::<Self>()?;
+    if bridge.vlan.is_some_and(|vlan| vlan == 0 || vlan > 4094) {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "invalid VLAN ID, must be between 1 and 4094".into(),
+      ));
+    }
+    bridge.is_gateway |= bridge.is_default_gateway;
+    Ok(bridge)
+  }
+}
@@ -0,0 +1,356 @@
+/*
+[bridge plugin](https://www.cni.dev/plugins/current/main/bridge/)
+
+Attaches containers to a bridge of the host through veth pairs, with the addresses allocated by the
+IPAM plugin.
+ */
+use std::net::IpAddr;
+use std::path::Path;
+
+use cni_plugins::args;
+use cni_plugins::ips;
+use cni_plugins::iptables;
+use cni_plugins::sysctl;
+use cni_plugins::validate;
+use cni_plugins::validate::check_mac;
+use cni_plugins::validate::not_found;
+use cni_plugins::validate::parse_address;
+use ipnetwork::IpNetwork;
+use libcni::api::CniPlugin;
+use libcni::block_on;
+use libcni::link::random_veth_name;
+use libcni::link::InfoKind;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::plugin::ipam;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::Route;
+use libcni::version;
+
+use crate::config::BridgeConfig;
+
+mod config;
+
+/// Index of the container interface in results, after the bridge and the host end of the veth.
+const CONTAINER_INTERFACE: usize = 2;
+
+struct Bridge;
+
+impl CniPlugin for Bridge {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = BridgeConfig::load(&args.config)?;
+    let netns = NetNs::open(&args.netns)?;
+    block_on(setup(&args, &config, &netns))
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    BridgeConfig::load(&args.config)?;
+    if args.config.plugin.ipam.is_some() {
+      ipam::exec_del(&args.config, &args.path)?;
+    }
+    block_on(teardown(&args))
+  }
+
+  /// Checks the links, addresses and routes of `prevResult` against those in place.
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = BridgeConfig::load(&args.config)?;
+    if args.config.plugin.ipam.is_some() {
+      ipam::exec_check(&args)?;
+    }
+    let result = validate::prev_result(&args.config)?;
+    block_on(check_links(&args, &config, result))
+  }
+
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    if args.config.plugin.ipam.is_some() {
+      ipam::exec_gc(&args)?;
+    }
+    Ok(())
+  }
+
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    BridgeConfig::load(&args.config)?;
+    if args.config.plugin.ipam.is_some() {
+      ipam::exec_status(&args)?;
+    }
+    Ok(())
+  }
+}
+
+/// Attaches the container to the bridge, created if needed, and applies the result of the IPAM
+/// plugin if any.
+async fn setup(args: &CniAddContext, config: &BridgeConfig, netns: &NetNs) -> CniResult<AddReply> {
+  let host = Netlink::new()?;
+  let container = Netlink::open(netns)?;
+
+  let bridge = host
+    .ensure_bridge(&config.bridge, config.mtu, config.vlan.is_some())
+    .await?;
+  if config.promisc_mode {
+    host.set_promiscuous(bridge.index, true).await?;
+  }
+  let (host_veth, container_veth) = setup_veth(args, config, &host, &container, &bridge).await?;
+
+  let mut result = ips::delegate_or_up(args, &container, container_veth.index, |result| {
+    configure_ips(
+      args,
+      config,
+      &host,
+      &container,
+      &bridge,
+      &container_veth,
+      result,
+    )
+  })
+  .await?;
+
+  // Addresses may have changed since the links were created.
+  result.interfaces = vec![
+    host.link_by_index(bridge.index).await?.interface(None),
+    host.link_by_index(host_veth.index).await?.interface(None),
+    container
+      .link_by_index(container_veth.index)
+      .await?
+      .interface(Some(Path::new(&args.netns))),
+  ];
+  Ok(result)
+}
+
+/// Deletes the veth pair, along with the masquerading of the addresses of the container.
+async fn teardown(args: &CniDelContext) -> CniResult<()> {
+  let addresses = ips::del_container_link(args).await?;
+  if args.config.plugin.ip_masq {
+    let chain = iptables::chain_name(&args.config.name, &args.container_id);
+    let comment = iptables::comment(&args.config.name, &args.container_id);
+    for address in addresses {
+      iptables::teardown_ip_masq(address, &chain, &comment)?;
+    }
+  }
+  Ok(())
+}
+
+async fn check_links(
+  args: &CniCheckContext,
+  config: &BridgeConfig,
+  result: &AddReply,
+) -> CniResult<()> {
+  let host = Netlink::new()?;
+  let bridge = host
+    .link(&config.bridge)
+    .await?
+    .filter(|link| link.kind == Some(InfoKind::Bridge))
+    .ok_or_else(|| not_found(&format!("bridge {}", config.bridge)))?;
+  if let Some(interface) = result
+    .interfaces
+    .iter()
+    .find(|interface| interface.name == config.bridge && interface.sandbox.is_none())
+  {
+    check_mac(&bridge, interface)?;
+  }
+  for interface in result.interfaces.iter() {
+    if interface.sandbox.is_some() || interface.name == config.bridge {
+      continue;
+    }
+    let veth = host
+      .link(&interface.name)
+      .await?
+      .ok_or_else(|| not_found(&format!("interface {}", interface.name)))?;
+    check_mac(&veth, interface)?;
+    if veth.master != Some(bridge.index) {
+      return Err(CniErrorCode::Internal(
+        format!(
+          "interface {} is not attached to bridge {}",
+          veth.name, bridge.name
+        )
+        .into(),
+      ));
+    }
+  }
+
+  let netns = NetNs::open(&args.netns)?;
+  let container = Netlink::open(&netns)?;
+  validate::check_container_interface(&container, result, &args.if_name, &args.netns).await?;
+  Ok(())
+}
+
+/// Creates the veth pair, with the host end attached to the bridge and up.
+async fn setup_veth(
+  args: &CniAddContext,
+  config: &BridgeConfig,
+  host: &Netlink,
+  container: &Netlink,
+  bridge: &Link,
+) -> CniResult<(Link, Link)> {
+  let host_name = random_veth_name()?;
+  let container_veth = container
+    .add_veth(&args.if_name, &host_name, &NetNs::current()?, config.mtu)
+    .await?;
+  let host_veth = host
+    .link(&host_name)
+    .await?
+    .ok_or_else(|| not_found(&format!("interface {}", host_name)))?;
+
+  host.set_master(host_veth.index, bridge.index).await?;
+  if config.hairpin_mode {
+    host.set_hairpin(host_veth.index, true).await?;
+  }
+  if let Some(vlan) = config.vlan {
+    host.add_bridge_vlan(host_veth.index, vlan).await?;
+  }
+  host.set_up(host_veth.index).await?;
+
+  if let Some(mac) = args::requested_mac(args)? {
+    container.set_down(container_veth.index).await?;
+    container.set_mac(container_veth.index, &mac).await?;
+  }
+  Ok((host_veth, container_veth))
+}
+
+/// Applies the result of the IPAM plugin to the container interface, and makes the bridge the
+/// gateway of its networks if configured.
+async fn configure_ips(
+  args: &CniAddContext,
+  config: &BridgeConfig,
+  host: &Netlink,
+  container: &Netlink,
+  bridge: &Link,
+  container_veth: &Link,
+  mut result: AddReply,
+) -> CniResult<AddReply> {
+  let mut addresses = Vec::with_capacity(result.ips.len());
+  for ips in result.ips.iter_mut() {
+    let address = parse_address(&ips.address)?;
+    if config.is_gateway && ips.gateway.is_none() {
+      ips.gateway = Some(first_address(address));
+    }
+    addresses.push(address);
+  }
+
+  if config.is_default_gateway {
+    add_default_routes(&mut result)?;
+  }
+  let result = ips::configure_ips(
+    args,
+    container,
+    container_veth.index,
+    CONTAINER_INTERFACE,
+    result,
+  )
+  .await?;
+
+  if config.is_gateway {
+    for (ips, address) in result.ips.iter().zip(addresses.iter()) {
+      if let Some(gateway) = ips.gateway {
+        let gateway = IpNetwork::new(gateway, address.prefix())
+          .map_err(|e| CniErrorCode::Internal(e.to_string().into()))?;
+        ensure_address(host, bridge, gateway, config.force_address).await?;
+      }
+    }
+    if addresses.iter().any(IpNetwork::is_ipv4) {
+      sysctl::set("net.ipv4.ip_forward", "1")?;
+    }
+    if addresses.iter().any(IpNetwork::is_ipv6) {
+      sysctl::set("net.ipv6.conf.all.forwarding", "1")?;
+    }
+  }
+
+  if args.config.plugin.ip_masq {
+    let chain = iptables::chain_name(&args.config.name, &args.container_id);
+    let comment = iptables::comment(&args.config.name, &args.container_id);
+    for address in addresses {
+      iptables::setup_ip_masq(address, &chain, &comment)?;
+    }
+  }
+  Ok(result)
+}
+
+/// Routes the default traffic of each IP version through the gateway of its first IP.
+fn add_default_routes(result: &mut AddReply) -> CniResult<()> {
+  let mut routed = Vec::new();
+  for gateway in result.ips.iter().filter_map(|ips| ips.gateway) {
+    if routed.contains(&gateway.is_ipv4()) {
+      continue;
+    }
+    routed.push(gateway.is_ipv4());
+
+    let dst = if gateway.is_ipv4() {
+      IpNetwork::new(IpAddr::from([0u8; 4]), 0)
+    } else {
+      IpNetwork::new(IpAddr::from([0u8; 16]), 0)
+    }
+    .map_err(|e| CniErrorCode::Internal(e.to_string().into()))?;
+    match result.routes.iter().find(|route| route.dst == dst) {
+      Some(Route { gw: Some(gw), .. }) if *gw != gateway => {
+        return Err(CniErrorCode::InvalidNetworkConfig(
+          format!(
+            "isDefaultGateway ineffective because IPAM sets default route via {}",
+            gw
+          )
+          .into(),
+        ));
+      }
+      Some(_) => {}
+      None => result.routes.push(Route {
+        dst,
+        gw: Some(gateway),
+        mtu: None,
+        advmss: None,
+        priority: None,
+      }),
+    }
+  }
+  Ok(())
+}
+
+/// Assigns `address` to the bridge. Other addresses of the bridge in the same subnet, or of IPv4,
+/// are replaced with `force`, and fail otherwise.
+async fn ensure_address(
+  host: &Netlink,
+  bridge: &Link,
+  address: IpNetwork,
+  force: bool,
+) -> CniResult<()> {
+  for existing in host.addresses(bridge.index).await? {
+    if existing == address {
+      return Ok(());
+    }
+    let conflicting = (existing.is_ipv4() && address.is_ipv4())
+      || existing.contains(address.ip())
+      || address.contains(existing.ip());
+    if !conflicting {
+      continue;
+    }
+    if !force {
+      return Err(CniErrorCode::Internal(
+        format!(
+          "{} already has an IP address different from {}",
+          bridge.name, address
+        )
+        .into(),
+      ));
+    }
+    host.del_address(bridge.index, existing).await?;
+  }
+  host.add_address(bridge.index, address).await
+}
+
+/// The address following the network address of `network`, the gateway by convention.
+fn first_address(network: IpNetwork) -> IpAddr {
+  match network.network() {
+    IpAddr::V4(ip) => IpAddr::from((u32::from(ip) + 1).to_be_bytes()),
+    IpAddr::V6(ip) => IpAddr::from((u128::from(ip) + 1).to_be_bytes()),
+  }
+}
+
+fn main() {
+  plugin_main_entrypoint(Bridge, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,54 @@
+/*
+[ipvlan plugin](https://www.cni.dev/plugins/current/main/ipvlan/)
+
+Gives containers an ipvlan interface on top of a link of the host, sharing its MAC address, with
+the addresses allocated by the IPAM plugin, if any.
+ */
+use cni_plugins::stacked;
+use cni_plugins::stacked::StackedConfig;
+use libcni::api::CniPlugin;
+use libcni::link::IpvlanMode;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::version;
+
+/// The network configuration keys of the ipvlan plugin.
+type IpvlanConfig = StackedConfig<IpvlanMode>;
+
+struct Ipvlan;
+
+impl CniPlugin for Ipvlan {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = IpvlanConfig::load(&args.config)?;
+    stacked::add(&args, &config)
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    IpvlanConfig::load(&args.config)?;
+    stacked::del(&args)
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = IpvlanConfig::load(&args.config)?;
+    stacked::check(&args, &config)
+  }
+
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    stacked::gc(&args)
+  }
+
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    IpvlanConfig::load(&args.config)?;
+    stacked::status(&args)
+  }
+}
+
+fn main() {
+  plugin_main_entrypoint(Ipvlan, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,101 @@
+/*
+[loopback plugin](https://www.cni.dev/plugins/current/main/loopback/)
+
+Brings the loopback interface of the container up, and down again on `DEL`.
+ */
+use std::path::Path;
+
+use cni_plugins::validate;
+use libcni::api::CniPlugin;
+use libcni::block_on;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::version;
+
+struct Loopback;
+
+impl CniPlugin for Loopback {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    block_on(add(args))
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    block_on(del(args))
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    block_on(check(args))
+  }
+
+  fn gc(&self, _args: CniGcContext) -> CniResult<()> {
+    Ok(())
+  }
+
+  fn status(&self, _args: CniStatusContext) -> CniResult<()> {
+    Ok(())
+  }
+}
+
+/// Passes `prevResult` through if any, and reports the loopback interface with its addresses
+/// otherwise.
+async fn add(args: CniAddContext) -> CniResult<AddReply> {
+  let netns = NetNs::open(&args.netns)?;
+  let container = Netlink::open(&netns)?;
+  let link = container
+    .link(&args.if_name)
+    .await?
+    .ok_or_else(|| validate::not_found(&format!("interface {}", args.if_name)))?;
+  container.set_up(link.index).await?;
+
+  if let Some(mut result) = args.config.prev_result.clone() {
+    result.cni_version = args.config.cni_version.clone();
+    return Ok(result);
+  }
+  container
+    .describe(
+      args.config.cni_version.clone(),
+      link.index,
+      Some(Path::new(&args.netns)),
+    )
+    .await
+}
+
+async fn del(args: CniDelContext) -> CniResult<()> {
+  // The interface is gone with the namespace.
+  let Some(Ok(netns)) = args.netns.as_deref().map(NetNs::open) else {
+    return Ok(());
+  };
+  let container = Netlink::open(&netns)?;
+  if let Some(link) = container.link(&args.if_name).await? {
+    container.set_down(link.index).await?;
+  }
+  Ok(())
+}
+
+async fn check(args: CniCheckContext) -> CniResult<()> {
+  let netns = NetNs::open(&args.netns)?;
+  let container = Netlink::open(&netns)?;
+  let link = container
+    .link(&args.if_name)
+    .await?
+    .ok_or_else(|| validate::not_found(&format!("interface {}", args.if_name)))?;
+  if !link.up {
+    return Err(CniErrorCode::Internal(
+      format!("loopback interface {} is down", link.name).into(),
+    ));
+  }
+  Ok(())
+}
+
+fn main() {
+  plugin_main_entrypoint(Loopback, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,54 @@
+/*
+[macvlan plugin](https://www.cni.dev/plugins/current/main/macvlan/)
+
+Gives containers a macvlan interface on top of a link of the host, with a MAC address of its own
+and the addresses allocated by the IPAM plugin, if any.
+ */
+use cni_plugins::stacked;
+use cni_plugins::stacked::StackedConfig;
+use libcni::api::CniPlugin;
+use libcni::link::MacvlanMode;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::version;
+
+/// The network configuration keys of the macvlan plugin.
+type MacvlanConfig = StackedConfig<MacvlanMode>;
+
+struct Macvlan;
+
+impl CniPlugin for Macvlan {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = MacvlanConfig::load(&args.config)?;
+    stacked::add(&args, &config)
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    MacvlanConfig::load(&args.config)?;
+    stacked::del(&args)
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = MacvlanConfig::load(&args.config)?;
+    stacked::check(&args, &config)
+  }
+
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    stacked::gc(&args)
+  }
+
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    MacvlanConfig::load(&args.config)?;
+    stacked::status(&args)
+  }
+}
+
+fn main() {
+  plugin_main_entrypoint(Macvlan, version::All, semver::Version::new(1, 1, 0))
+}
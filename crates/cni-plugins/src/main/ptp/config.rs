@@ -0,0 +1,24 @@
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use serde::Deserialize;
+
+/// The network configuration keys of the ptp plugin.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PtpConfig {
+  /// MTU of the veth pair, defaults to the kernel's.
+  #[serde(default)]
+  pub mtu: Option<u32>,
+}
+
+impl PtpConfig {
+  pub fn load(config: &NetworkConfig) -> CniResult<Self> {
+    if config.plugin.ipam.is_none() {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "missing IPAM configuration in network config".into(),
+      ));
+    }
+    config.plugin.parse::<Self>()
+  }
+}
@@ -0,0 +1,241 @@
+/*
+[ptp plugin](https://www.cni.dev/plugins/current/main/ptp/)
+
+Connects containers to the host through veth pairs, routing between them instead of bridging. Both
+ends take addresses from the IPAM plugin: the container its own, and the host end the gateway, as
+a host address. Each side routes to the other through its end of the pair.
+ */
+use std::net::IpAddr;
+use std::path::Path;
+
+use cni_plugins::ips;
+use cni_plugins::iptables;
+use cni_plugins::sysctl;
+use cni_plugins::validate;
+use cni_plugins::validate::check_mac;
+use cni_plugins::validate::not_found;
+use cni_plugins::validate::parse_address;
+use ipnetwork::IpNetwork;
+use libcni::api::CniPlugin;
+use libcni::block_on;
+use libcni::link::random_veth_name;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::plugin::ipam;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::Route;
+use libcni::version;
+
+use crate::config::PtpConfig;
+
+mod config;
+
+/// Index of the container interface in results, after the host end of the veth.
+const CONTAINER_INTERFACE: usize = 1;
+
+struct Ptp;
+
+impl CniPlugin for Ptp {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = PtpConfig::load(&args.config)?;
+    let netns = NetNs::open(&args.netns)?;
+    block_on(ips::delegate(&args, |result| {
+      setup(&args, &config, &netns, result)
+    }))
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    PtpConfig::load(&args.config)?;
+    ipam::exec_del(&args.config, &args.path)?;
+    block_on(teardown(&args))
+  }
+
+  /// Checks the links, addresses and routes of `prevResult` against those in place.
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    PtpConfig::load(&args.config)?;
+    ipam::exec_check(&args)?;
+    let result = validate::prev_result(&args.config)?;
+    block_on(check_links(&args, result))
+  }
+
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    ipam::exec_gc(&args)
+  }
+
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    PtpConfig::load(&args.config)?;
+    ipam::exec_status(&args)
+  }
+}
+
+/// Deletes the veth pair, along with the masquerading of the addresses of the container.
+async fn teardown(args: &CniDelContext) -> CniResult<()> {
+  let addresses = ips::del_container_link(args).await?;
+
+  if args.config.plugin.ip_masq {
+    let chain = iptables::chain_name(&args.config.name, &args.container_id);
+    let comment = iptables::comment(&args.config.name, &args.container_id);
+    for address in addresses {
+      iptables::teardown_ip_masq(address, &chain, &comment)?;
+    }
+  }
+  Ok(())
+}
+
+async fn check_links(args: &CniCheckContext, result: &AddReply) -> CniResult<()> {
+  let host = Netlink::new()?;
+  for interface in result.interfaces.iter() {
+    if interface.sandbox.is_some() {
+      continue;
+    }
+    let veth = host
+      .link(&interface.name)
+      .await?
+      .ok_or_else(|| not_found(&format!("interface {}", interface.name)))?;
+    check_mac(&veth, interface)?;
+  }
+
+  let netns = NetNs::open(&args.netns)?;
+  let container = Netlink::open(&netns)?;
+  validate::check_container_interface(&container, result, &args.if_name, &args.netns).await?;
+  Ok(())
+}
+
+/// Creates the veth pair and applies the result of the IPAM plugin to both of its ends.
+async fn setup(
+  args: &CniAddContext,
+  config: &PtpConfig,
+  netns: &NetNs,
+  result: AddReply,
+) -> CniResult<AddReply> {
+  let host = Netlink::new()?;
+  let container = Netlink::open(netns)?;
+  ips::require_ips(&result)?;
+
+  let mut addresses = Vec::with_capacity(result.ips.len());
+  for ips in result.ips.iter() {
+    let address = parse_address(&ips.address)?;
+    let gateway = ips.gateway.ok_or_else(|| {
+      CniErrorCode::Internal(format!("IPAM plugin returned no gateway for {}", address).into())
+    })?;
+    addresses.push((address, gateway));
+  }
+  if addresses.iter().any(|(address, _)| address.is_ipv4()) {
+    sysctl::set("net.ipv4.ip_forward", "1")?;
+  }
+  if addresses.iter().any(|(address, _)| address.is_ipv6()) {
+    sysctl::set("net.ipv6.conf.all.forwarding", "1")?;
+  }
+
+  let host_name = random_veth_name()?;
+  let container_veth = container
+    .add_veth(&args.if_name, &host_name, &NetNs::current()?, config.mtu)
+    .await?;
+  let host_veth = host
+    .link(&host_name)
+    .await?
+    .ok_or_else(|| not_found(&format!("interface {}", host_name)))?;
+
+  let mut result = ips::configure_ips(
+    args,
+    &container,
+    container_veth.index,
+    CONTAINER_INTERFACE,
+    result,
+  )
+  .await?;
+  for (address, gateway) in addresses.iter() {
+    route_through_gateway(&container, &container_veth, *address, *gateway).await?;
+  }
+
+  host.set_up(host_veth.index).await?;
+  for (address, gateway) in addresses.iter() {
+    host
+      .add_address(host_veth.index, host_network(*gateway)?)
+      .await?;
+    host
+      .add_route(host_veth.index, &link_route(host_network(address.ip())?))
+      .await?;
+  }
+  if addresses.iter().any(|(address, _)| address.is_ipv4()) {
+    sysctl::set(
+      &sysctl::interface("net.ipv4.conf", &host_name, "proxy_arp"),
+      "1",
+    )?;
+  }
+
+  if args.config.plugin.ip_masq {
+    let chain = iptables::chain_name(&args.config.name, &args.container_id);
+    let comment = iptables::comment(&args.config.name, &args.container_id);
+    for (address, _) in addresses.iter() {
+      iptables::setup_ip_masq(*address, &chain, &comment)?;
+    }
+  }
+
+  result.interfaces = vec![
+    host.link_by_index(host_veth.index).await?.interface(None),
+    container
+      .link_by_index(container_veth.index)
+      .await?
+      .interface(Some(Path::new(&args.netns))),
+  ];
+  Ok(result)
+}
+
+/// Replaces the route to the subnet of `address`, which the kernel adds on the link, with a route
+/// to the gateway alone and one to the subnet through it. The subnet is then only reachable
+/// through the host.
+async fn route_through_gateway(
+  container: &Netlink,
+  link: &Link,
+  address: IpNetwork,
+  gateway: IpAddr,
+) -> CniResult<()> {
+  let subnet = IpNetwork::new(address.network(), address.prefix())
+    .map_err(|e| CniErrorCode::Internal(e.to_string().into()))?;
+  container.del_route(link.index, subnet).await?;
+  container
+    .add_route(link.index, &link_route(host_network(gateway)?))
+    .await?;
+  container
+    .add_route(
+      link.index,
+      &Route {
+        dst:      subnet,
+        gw:       Some(gateway),
+        mtu:      None,
+        advmss:   None,
+        priority: None,
+      },
+    )
+    .await
+}
+
+/// `ip` alone, as a `/32` or `/128` network.
+fn host_network(ip: IpAddr) -> CniResult<IpNetwork> {
+  let prefix = if ip.is_ipv4() { 32 } else { 128 };
+  IpNetwork::new(ip, prefix).map_err(|e| CniErrorCode::Internal(e.to_string().into()))
+}
+
+fn link_route(dst: IpNetwork) -> Route {
+  Route {
+    dst,
+    gw: None,
+    mtu: None,
+    advmss: None,
+    priority: None,
+  }
+}
+
+fn main() {
+  plugin_main_entrypoint(Ptp, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,38 @@
+/*
+Master links of the interfaces stacked on a link of the host, like macvlan and ipvlan.
+ */
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+use crate::validate::not_found;
+
+/// Link `name`, or the link of the default route if no name is given.
+pub async fn find(netlink: &Netlink, name: Option<&str>) -> CniResult<Link> {
+  match name {
+    Some(name) => netlink
+      .link(name)
+      .await?
+      .ok_or_else(|| not_found(&format!("master {}", name))),
+    None => netlink.default_route_link().await?.ok_or_else(|| {
+      CniErrorCode::InvalidNetworkConfig(
+        "master not given, and no default route to take it from".into(),
+      )
+    }),
+  }
+}
+
+/// Stacked links cannot exceed the MTU of their master.
+pub fn check_mtu(master: &Link, mtu: Option<u32>) -> CniResult<()> {
+  match (mtu, master.mtu) {
+    (Some(mtu), Some(master_mtu)) if mtu > master_mtu => Err(CniErrorCode::InvalidNetworkConfig(
+      format!(
+        "invalid MTU {}, must be at most the MTU of master {} ({})",
+        mtu, master.name, master_mtu
+      )
+      .into(),
+    )),
+    _ => Ok(()),
+  }
+}
@@ -0,0 +1,35 @@
+use libcni::schema::config::BandwidthEntry;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+/// Bursts are passed to the kernel in bytes, as 32 bits.
+const MAX_BURST: u64 = (u32::MAX as u64) * 8;
+
+/// The limits of the `bandwidth` capability if given, of the network configuration otherwise,
+/// which has the same keys.
+pub fn load(config: &NetworkConfig) -> CniResult<BandwidthEntry> {
+  let bandwidth = match config
+    .plugin
+    .runtime_config
+    .as_ref()
+    .and_then(|config| config.bandwidth.clone())
+  {
+    Some(bandwidth) => bandwidth,
+    None => config.plugin.parse::<BandwidthEntry>()?,
+  };
+  validate(bandwidth.ingress_rate, bandwidth.ingress_burst)?;
+  validate(bandwidth.egress_rate, bandwidth.egress_burst)?;
+  Ok(bandwidth)
+}
+
+fn validate(rate: u64, burst: u64) -> CniResult<()> {
+  let error = match (rate, burst) {
+    (0, 0) => return Ok(()),
+    (0, _) => "if burst is set, rate must also be set",
+    (_, 0) => "if rate is set, burst must also be set",
+    (_, burst) if burst > MAX_BURST => "burst cannot be more than 4GB",
+    _ => return Ok(()),
+  };
+  Err(CniErrorCode::InvalidNetworkConfig(error.into()))
+}
@@ -0,0 +1,228 @@
+/*
+[bandwidth plugin](https://www.cni.dev/plugins/current/meta/bandwidth/)
+
+Limits the bandwidth of the container with token bucket filters on the host end of its veth pair.
+Traffic to the container is shaped as it leaves the host end. Traffic from the container enters
+the host end, so it is redirected to an IFB device and shaped as it leaves that.
+ */
+use async_trait::async_trait;
+use cni_plugins::validate;
+use cni_plugins::validate::not_found;
+use libcni::api::AsyncCniPlugin;
+use libcni::async_plugin_main_entrypoint;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::config::BandwidthEntry;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::version;
+use sha2::Digest;
+use sha2::Sha512;
+
+mod config;
+mod tc;
+
+/// Prefix of the IFB devices of attachments.
+const IFB_PREFIX: &str = "bwp";
+/// Interface names are limited by the kernel.
+const MAX_IFNAME_LENGTH: usize = 15;
+
+struct Bandwidth;
+
+#[async_trait(?Send)]
+impl AsyncCniPlugin for Bandwidth {
+  async fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let bandwidth = config::load(&args.config)?;
+    let mut result = args.config.prev_result.clone().ok_or_else(|| {
+      CniErrorCode::InvalidNetworkConfig("must be called as chained plugin".into())
+    })?;
+    result.cni_version = args.config.cni_version.clone();
+    if !shapes(&bandwidth) {
+      return Ok(result);
+    }
+
+    let netns = NetNs::open(&args.netns)?;
+    let host = Netlink::new()?;
+    let container = Netlink::open(&netns)?;
+    let host_veth = host_interface(&host, &container, &result, &args.if_name).await?;
+
+    if bandwidth.ingress_rate > 0 {
+      tc::add_tbf(
+        &host_veth.name,
+        bandwidth.ingress_rate,
+        bandwidth.ingress_burst,
+      )?;
+    }
+    if bandwidth.egress_rate > 0 {
+      match shape_egress(&host, &args, &bandwidth, &host_veth).await {
+        Ok(ifb) => result.interfaces.push(ifb.interface(None)),
+        Err(e) => {
+          if bandwidth.ingress_rate > 0 {
+            let _ = tc::del_root(&host_veth.name);
+          }
+          return Err(e);
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  /// Deletes the IFB device. The qdiscs of the host end of the veth pair go away with the veth,
+  /// which the main plugin deletes.
+  async fn del(&self, args: CniDelContext) -> CniResult<()> {
+    config::load(&args.config)?;
+    let host = Netlink::new()?;
+    if let Some(ifb) = host
+      .link(&ifb_name(&args.config.name, &args.container_id))
+      .await?
+    {
+      host.del_link(ifb.index).await?;
+    }
+    Ok(())
+  }
+
+  async fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let bandwidth = config::load(&args.config)?;
+    let result = validate::prev_result(&args.config)?;
+    if !shapes(&bandwidth) {
+      return Ok(());
+    }
+
+    let netns = NetNs::open(&args.netns)?;
+    let host = Netlink::new()?;
+    let container = Netlink::open(&netns)?;
+    let host_veth = host_interface(&host, &container, result, &args.if_name).await?;
+
+    if bandwidth.ingress_rate > 0 {
+      check_tbf(
+        &host_veth.name,
+        bandwidth.ingress_rate,
+        bandwidth.ingress_burst,
+      )?;
+    }
+    if bandwidth.egress_rate > 0 {
+      let name = ifb_name(&args.config.name, &args.container_id);
+      host
+        .link(&name)
+        .await?
+        .ok_or_else(|| not_found(&format!("IFB device {}", name)))?;
+      check_tbf(&name, bandwidth.egress_rate, bandwidth.egress_burst)?;
+      if !tc::redirects_ingress(&host_veth.name, &name)? {
+        return Err(CniErrorCode::Internal(
+          format!(
+            "traffic of {} is not redirected to {}",
+            host_veth.name, name
+          )
+          .into(),
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  async fn gc(&self, _args: CniGcContext) -> CniResult<()> {
+    Ok(())
+  }
+
+  async fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    config::load(&args.config).map(|_| ())
+  }
+}
+
+/// Redirects the traffic entering the host end of the veth pair to a new IFB device, shaped as it
+/// leaves that. The device is deleted again if it cannot be set up.
+async fn shape_egress(
+  host: &Netlink,
+  args: &CniAddContext,
+  bandwidth: &BandwidthEntry,
+  host_veth: &Link,
+) -> CniResult<Link> {
+  let ifb = host
+    .add_ifb(
+      &ifb_name(&args.config.name, &args.container_id),
+      host_veth.mtu,
+    )
+    .await?;
+  let shaped = tc::add_tbf(&ifb.name, bandwidth.egress_rate, bandwidth.egress_burst)
+    .and_then(|_| tc::redirect_ingress(&host_veth.name, &ifb.name));
+  if let Err(e) = shaped {
+    let _ = host.del_link(ifb.index).await;
+    return Err(e);
+  }
+  Ok(ifb)
+}
+
+/// The host end of the veth pair of container interface `if_name`, which must be among the
+/// interfaces of `result`.
+async fn host_interface(
+  host: &Netlink,
+  container: &Netlink,
+  result: &AddReply,
+  if_name: &str,
+) -> CniResult<Link> {
+  let peer = container
+    .link(if_name)
+    .await?
+    .and_then(|link| link.parent)
+    .ok_or_else(|| not_found(&format!("veth peer of container interface {}", if_name)))?;
+  let link = host.link_by_index(peer).await?;
+  let known = result
+    .interfaces
+    .iter()
+    .any(|interface| interface.sandbox.is_none() && interface.name == link.name);
+  if !known {
+    return Err(not_found(&format!(
+      "veth peer {} of container interface {} in prevResult",
+      link.name, if_name
+    )));
+  }
+  Ok(link)
+}
+
+fn check_tbf(device: &str, rate: u64, burst: u64) -> CniResult<()> {
+  let tbf = tc::tbf(device)?.ok_or_else(|| not_found(&format!("tbf qdisc on {}", device)))?;
+  if tbf.rate != rate / 8 || tbf.burst != burst / 8 {
+    return Err(CniErrorCode::Internal(
+      format!(
+        "tbf qdisc on {} shapes to {} bit/s with bursts of {} bits, expected {} and {}",
+        device,
+        tbf.rate * 8,
+        tbf.burst * 8,
+        rate,
+        burst
+      )
+      .into(),
+    ));
+  }
+  Ok(())
+}
+
+/// The IFB device of the attachment of container `container_id` to network `network`.
+fn ifb_name(network: &str, container_id: &str) -> String {
+  let digest = Sha512::digest(format!("{}{}", network, container_id).as_bytes());
+  let hex = digest
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  format!(
+    "{}{}",
+    IFB_PREFIX,
+    &hex[..MAX_IFNAME_LENGTH - IFB_PREFIX.len()]
+  )
+}
+
+/// Whether any direction is limited.
+fn shapes(bandwidth: &BandwidthEntry) -> bool {
+  bandwidth.ingress_rate > 0 || bandwidth.egress_rate > 0
+}
+
+fn main() {
+  async_plugin_main_entrypoint(Bandwidth, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,112 @@
+/*
+Queueing disciplines and filters of the `tc` command, in the network namespace of the plugin.
+ */
+use std::process::Command;
+
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Latency of the token bucket filters, from which `tc` derives their queue length.
+const LATENCY: &str = "25ms";
+
+/// A token bucket filter, as listed by `tc -j`, in bytes.
+#[derive(Debug, Deserialize)]
+pub struct Tbf {
+  pub rate:  u64,
+  pub burst: u64,
+}
+
+/// Shapes the traffic leaving `device` to `rate` bits per second, with bursts of `burst` bits.
+pub fn add_tbf(device: &str, rate: u64, burst: u64) -> CniResult<()> {
+  run(&[
+    "qdisc",
+    "add",
+    "dev",
+    device,
+    "root",
+    "tbf",
+    "rate",
+    &format!("{}bit", rate),
+    "burst",
+    &(burst / 8).to_string(),
+    "latency",
+    LATENCY,
+  ])
+}
+
+/// Deletes the root queueing discipline of `device`, back to the default of the kernel.
+pub fn del_root(device: &str) -> CniResult<()> {
+  run(&["qdisc", "del", "dev", device, "root"])
+}
+
+/// The root token bucket filter of `device`, if any.
+pub fn tbf(device: &str) -> CniResult<Option<Tbf>> {
+  let qdiscs = list(&["qdisc", "show", "dev", device])?;
+  Ok(
+    qdiscs
+      .into_iter()
+      .find(|qdisc| qdisc["kind"] == "tbf" && qdisc["root"] == true)
+      .and_then(|qdisc| serde_json::from_value(qdisc["options"].clone()).ok()),
+  )
+}
+
+/// Redirects the traffic entering `device` to the egress of `target`, usually an IFB device.
+pub fn redirect_ingress(device: &str, target: &str) -> CniResult<()> {
+  run(&["qdisc", "add", "dev", device, "handle", "ffff:", "ingress"])?;
+  run(&[
+    "filter", "add", "dev", device, "parent", "ffff:", "protocol", "all", "u32", "match", "u32",
+    "0", "0", "action", "mirred", "egress", "redirect", "dev", target,
+  ])
+}
+
+/// Whether the traffic entering `device` is redirected to `target`.
+pub fn redirects_ingress(device: &str, target: &str) -> CniResult<bool> {
+  let filters = list(&["filter", "show", "dev", device, "parent", "ffff:"])?;
+  Ok(filters.iter().any(|filter| {
+    filter["options"]["actions"]
+      .as_array()
+      .into_iter()
+      .flatten()
+      .any(|action| action["kind"] == "mirred" && action["to_dev"] == target)
+  }))
+}
+
+fn list(args: &[&str]) -> CniResult<Vec<Value>> {
+  let output = Command::new("tc")
+    .arg("-j")
+    .args(args)
+    .output()
+    .map_err(CniErrorCode::IOFailure)?;
+  if !output.status.success() {
+    return Err(error(args, &output.stderr));
+  }
+  // Nothing is listed as nothing at all by some versions.
+  if output.stdout.iter().all(u8::is_ascii_whitespace) {
+    return Ok(Vec::new());
+  }
+  serde_json::from_slice(&output.stdout).map_err(|e| CniErrorCode::Internal(e.to_string().into()))
+}
+
+fn run(args: &[&str]) -> CniResult<()> {
+  let output = Command::new("tc")
+    .args(args)
+    .output()
+    .map_err(CniErrorCode::IOFailure)?;
+  if !output.status.success() {
+    return Err(error(args, &output.stderr));
+  }
+  Ok(())
+}
+
+fn error(args: &[&str], stderr: &[u8]) -> CniErrorCode {
+  CniErrorCode::Internal(
+    format!(
+      "tc {}: {}",
+      args.join(" "),
+      String::from_utf8_lossy(stderr).trim()
+    )
+    .into(),
+  )
+}
@@ -0,0 +1,137 @@
+use std::net::IpAddr;
+
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::config::PortMapping;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use serde::Deserialize;
+
+/// Bit of the packet mark flagging traffic to masquerade, by default.
+const DEFAULT_MARK_MASQ_BIT: u32 = 13;
+
+/// How forwarding rules are installed.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+  Iptables,
+  Nftables,
+}
+
+/// The network configuration keys of the portmap plugin.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortMapConfig {
+  /// Masquerades traffic to mapped ports coming from the container itself or from localhost, so
+  /// that replies go back through the host. True by default.
+  #[serde(default = "default_snat")]
+  pub snat:                    bool,
+  /// Bit of the packet mark flagging traffic to masquerade, 13 by default. iptables only.
+  #[serde(default)]
+  pub mark_masq_bit:           Option<u32>,
+  /// Chain marking traffic to masquerade, maintained by someone else, instead of ours. iptables
+  /// only.
+  #[serde(default)]
+  pub external_set_mark_chain: Option<String>,
+  /// Either `iptables` or `nftables`. By default nftables, unless `nft` is unavailable.
+  #[serde(default)]
+  pub backend:                 Option<Backend>,
+}
+
+fn default_snat() -> bool {
+  true
+}
+
+impl PortMapConfig {
+  pub fn load(config: &NetworkConfig) -> CniResult<Self> {
+    let portmap = config.plugin.parse::<Self>()?;
+    if portmap.mark_masq_bit.is_some_and(|bit| bit > 31) {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "invalid markMasqBit, must be between 0 and 31".into(),
+      ));
+    }
+    if portmap.mark_masq_bit.is_some() && portmap.external_set_mark_chain.is_some() {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        "cannot specify both externalSetMarkChain and markMasqBit".into(),
+      ));
+    }
+    Ok(portmap)
+  }
+
+  /// The packet mark flagging traffic to masquerade.
+  pub fn masq_mark(&self) -> u32 {
+    1 << self.mark_masq_bit.unwrap_or(DEFAULT_MARK_MASQ_BIT)
+  }
+
+  pub fn backend(&self) -> Backend {
+    self.backend.unwrap_or_else(|| {
+      if cni_plugins::nftables::available() {
+        Backend::Nftables
+      } else {
+        Backend::Iptables
+      }
+    })
+  }
+}
+
+/// The mappings of the `portMappings` capability.
+pub fn port_mappings(config: &NetworkConfig) -> CniResult<Vec<PortMapping>> {
+  let mappings = config
+    .plugin
+    .runtime_config
+    .as_ref()
+    .and_then(|config| config.port_mappings.clone())
+    .unwrap_or_default();
+  for mapping in mappings.iter() {
+    if !matches!(mapping.protocol.as_str(), "tcp" | "udp" | "sctp") {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        format!("unsupported protocol {:?}", mapping.protocol).into(),
+      ));
+    }
+    if mapping.host_port == 0 || mapping.container_port == 0 {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        format!(
+          "invalid port mapping {}:{}",
+          mapping.host_port, mapping.container_port
+        )
+        .into(),
+      ));
+    }
+  }
+  Ok(mappings)
+}
+
+/// The first IPv4 and the first IPv6 address of the container in `result`. IPs of interfaces
+/// outside the container are skipped.
+pub fn container_ips(result: &AddReply) -> CniResult<Vec<IpAddr>> {
+  let mut ips: Vec<IpAddr> = Vec::new();
+  for entry in result.ips.iter() {
+    let in_container = entry.interface.map_or(true, |index| {
+      result
+        .interfaces
+        .get(index)
+        .is_some_and(|interface| interface.sandbox.is_some())
+    });
+    if !in_container {
+      continue;
+    }
+    let ip = cni_plugins::validate::parse_address(&entry.address)?.ip();
+    if !ips.iter().any(|known| known.is_ipv4() == ip.is_ipv4()) {
+      ips.push(ip);
+    }
+  }
+  Ok(ips)
+}
+
+/// The mappings which apply to `container_ip`, those bound to a host IP of the other version
+/// excepted.
+pub fn mappings_for(mappings: &[PortMapping], container_ip: IpAddr) -> Vec<&PortMapping> {
+  mappings
+    .iter()
+    .filter(|mapping| {
+      mapping
+        .host_ip
+        .map_or(true, |host_ip| host_ip.is_ipv4() == container_ip.is_ipv4())
+    })
+    .collect()
+}
@@ -0,0 +1,327 @@
+/*
+Port forwarding with iptables, in the chains of the reference plugin:
+
+- `CNI-HOSTPORT-DNAT`, jumped to from `PREROUTING` and `OUTPUT` for local destinations, jumps to
+  the chain of each attachment for its host ports.
+- `CNI-DN-<hash>`, the chain of an attachment, marks traffic to masquerade and DNATs it.
+- `CNI-HOSTPORT-SETMARK` marks traffic, and `CNI-HOSTPORT-MASQ` masquerades what was marked.
+ */
+use std::net::IpAddr;
+
+use cni_plugins::iptables;
+use cni_plugins::iptables::IpTables;
+use libcni::schema::config::PortMapping;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+use crate::config::PortMapConfig;
+
+const DNAT_CHAIN: &str = "CNI-HOSTPORT-DNAT";
+const SETMARK_CHAIN: &str = "CNI-HOSTPORT-SETMARK";
+const MASQ_CHAIN: &str = "CNI-HOSTPORT-MASQ";
+/// Prefix of the chains of attachments.
+const CHAIN_PREFIX: &str = "DN-";
+/// `multiport` matches at most this many ports.
+const MAX_MULTIPORT: usize = 15;
+
+/// Forwards the host ports of `mappings` to `container_ip`, replacing previous rules of the
+/// attachment.
+pub fn forward_ports(
+  config: &PortMapConfig,
+  network: &str,
+  container_id: &str,
+  container_ip: IpAddr,
+  mappings: &[&PortMapping],
+) -> CniResult<()> {
+  let iptables = IpTables::for_ip(container_ip);
+  let local = ["-m", "addrtype", "--dst-type", "LOCAL", "-j", DNAT_CHAIN];
+  iptables.ensure_chain("nat", DNAT_CHAIN)?;
+  iptables.append_unique("nat", "PREROUTING", &local)?;
+  iptables.append_unique("nat", "OUTPUT", &local)?;
+  let setmark_chain = if config.snat {
+    ensure_masquerade(config, &iptables)?
+  } else {
+    ""
+  };
+
+  teardown(&iptables, network, container_id)?;
+  let chain = iptables::prefixed_chain_name(CHAIN_PREFIX, network, container_id);
+  iptables.ensure_chain("nat", &chain)?;
+  for rule in chain_rules(config, setmark_chain, container_ip, mappings) {
+    let rule = rule.iter().map(String::as_str).collect::<Vec<_>>();
+    iptables.append_unique("nat", &chain, &rule)?;
+  }
+  let comment = iptables::comment(network, container_id);
+  for rule in entry_rules(&chain, &comment, mappings) {
+    let rule = rule.iter().map(String::as_str).collect::<Vec<_>>();
+    iptables.append_unique("nat", DNAT_CHAIN, &rule)?;
+  }
+  Ok(())
+}
+
+/// Checks that the rules installed by [`forward_ports`] are in place.
+pub fn check(
+  config: &PortMapConfig,
+  network: &str,
+  container_id: &str,
+  container_ip: IpAddr,
+  mappings: &[&PortMapping],
+) -> CniResult<()> {
+  let iptables = IpTables::for_ip(container_ip);
+  let setmark_chain = match config.external_set_mark_chain.as_deref() {
+    Some(chain) => chain,
+    None => SETMARK_CHAIN,
+  };
+  let chain = iptables::prefixed_chain_name(CHAIN_PREFIX, network, container_id);
+  let comment = iptables::comment(network, container_id);
+  let expected = chain_rules(config, setmark_chain, container_ip, mappings)
+    .into_iter()
+    .map(|rule| (chain.as_str(), rule))
+    .chain(
+      entry_rules(&chain, &comment, mappings)
+        .into_iter()
+        .map(|rule| (DNAT_CHAIN, rule)),
+    );
+  for (chain, rule) in expected {
+    let rule = rule.iter().map(String::as_str).collect::<Vec<_>>();
+    if !iptables.exists("nat", chain, &rule)? {
+      return Err(CniErrorCode::Internal(
+        format!("rule {:?} not found in chain {}", rule.join(" "), chain).into(),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Removes the rules of the attachment, for both IP versions.
+pub fn teardown_all(network: &str, container_id: &str) -> CniResult<()> {
+  for iptables in available() {
+    teardown(&iptables, network, container_id)?;
+  }
+  Ok(())
+}
+
+/// Removes the rules of the attachments of `network` other than those of `valid` containers.
+pub fn gc(network: &str, valid: &[&str]) -> CniResult<()> {
+  for iptables in available() {
+    let stale = iptables
+      .rules("nat", DNAT_CHAIN)?
+      .iter()
+      .filter_map(|rule| owner(rule))
+      .filter(|(owner, container_id)| owner == network && !valid.contains(&container_id.as_str()))
+      .map(|(_, container_id)| container_id)
+      .collect::<Vec<_>>();
+    for container_id in stale {
+      teardown(&iptables, network, &container_id)?;
+    }
+  }
+  Ok(())
+}
+
+/// The commands of both IP versions which can be run.
+fn available() -> impl Iterator<Item = IpTables> {
+  [IpAddr::from([0u8; 4]), IpAddr::from([0u8; 16])]
+    .into_iter()
+    .map(IpTables::for_ip)
+    .filter(IpTables::available)
+}
+
+/// Creates the chains which mark and masquerade traffic, unless an external chain marks it, and
+/// returns the chain marking traffic.
+fn ensure_masquerade<'a>(config: &'a PortMapConfig, iptables: &IpTables) -> CniResult<&'a str> {
+  if let Some(chain) = config.external_set_mark_chain.as_deref() {
+    return Ok(chain);
+  }
+  let mark = format!("{:#x}/{:#x}", config.masq_mark(), config.masq_mark());
+  iptables.ensure_chain("nat", SETMARK_CHAIN)?;
+  iptables.append_unique(
+    "nat",
+    SETMARK_CHAIN,
+    &[
+      "-m",
+      "comment",
+      "--comment",
+      "CNI portfwd masquerade mark",
+      "-j",
+      "MARK",
+      "--set-xmark",
+      &mark,
+    ],
+  )?;
+  iptables.ensure_chain("nat", MASQ_CHAIN)?;
+  iptables.append_unique(
+    "nat",
+    MASQ_CHAIN,
+    &["-m", "mark", "--mark", &mark, "-j", "MASQUERADE"],
+  )?;
+  iptables.append_unique(
+    "nat",
+    "POSTROUTING",
+    &[
+      "-m",
+      "comment",
+      "--comment",
+      "CNI portfwd requiring masquerade",
+      "-j",
+      MASQ_CHAIN,
+    ],
+  )?;
+  Ok(SETMARK_CHAIN)
+}
+
+/// Deletes the rules jumping to the chain of the attachment, then the chain itself.
+fn teardown(iptables: &IpTables, network: &str, container_id: &str) -> CniResult<()> {
+  let chain = iptables::prefixed_chain_name(CHAIN_PREFIX, network, container_id);
+  let jump = format!("-j {}", chain);
+  let numbers = iptables
+    .rules("nat", DNAT_CHAIN)?
+    .iter()
+    .enumerate()
+    .filter(|(_, rule)| rule.ends_with(&jump))
+    .map(|(index, _)| index + 1)
+    .collect::<Vec<_>>();
+  // Later rules are numbered after the earlier ones are gone.
+  for number in numbers.into_iter().rev() {
+    iptables.delete_number("nat", DNAT_CHAIN, number)?;
+  }
+  iptables.delete_chain("nat", &chain)
+}
+
+/// The rules of the chain of the attachment. Traffic from the container itself, or from localhost,
+/// is marked for masquerading with `setmark_chain`, unless empty.
+fn chain_rules(
+  config: &PortMapConfig,
+  setmark_chain: &str,
+  container_ip: IpAddr,
+  mappings: &[&PortMapping],
+) -> Vec<Vec<String>> {
+  let mut rules = Vec::new();
+  for mapping in mappings {
+    let mut matches = vec![
+      "-p".to_string(),
+      mapping.protocol.clone(),
+      "--dport".to_string(),
+      mapping.host_port.to_string(),
+    ];
+    if let Some(host_ip) = mapping.host_ip {
+      matches.extend(["-d".to_string(), host_ip.to_string()]);
+    }
+
+    if config.snat && !setmark_chain.is_empty() {
+      let mut sources = vec![container_ip.to_string()];
+      // IPv6 loopback traffic cannot be routed elsewhere.
+      if container_ip.is_ipv4() {
+        sources.push("127.0.0.1".to_string());
+      }
+      for source in sources {
+        let mut rule = matches.clone();
+        rule.extend([
+          "-s".to_string(),
+          source,
+          "-j".to_string(),
+          setmark_chain.to_string(),
+        ]);
+        rules.push(rule);
+      }
+    }
+
+    let destination = match container_ip {
+      IpAddr::V4(ip) => format!("{}:{}", ip, mapping.container_port),
+      IpAddr::V6(ip) => format!("[{}]:{}", ip, mapping.container_port),
+    };
+    let mut rule = matches;
+    rule.extend([
+      "-j".to_string(),
+      "DNAT".to_string(),
+      "--to-destination".to_string(),
+      destination,
+    ]);
+    rules.push(rule);
+  }
+  rules
+}
+
+/// The rules of `CNI-HOSTPORT-DNAT` jumping to the chain of the attachment, one for every protocol
+/// and at most [`MAX_MULTIPORT`] host ports.
+fn entry_rules(chain: &str, comment: &str, mappings: &[&PortMapping]) -> Vec<Vec<String>> {
+  let mut protocols = mappings
+    .iter()
+    .map(|mapping| mapping.protocol.as_str())
+    .collect::<Vec<_>>();
+  protocols.sort_unstable();
+  protocols.dedup();
+
+  let mut rules = Vec::new();
+  for protocol in protocols {
+    let mut ports = mappings
+      .iter()
+      .filter(|mapping| mapping.protocol == protocol)
+      .map(|mapping| mapping.host_port.to_string())
+      .collect::<Vec<_>>();
+    ports.sort_unstable();
+    ports.dedup();
+    for ports in ports.chunks(MAX_MULTIPORT) {
+      rules.push(
+        [
+          "-p",
+          protocol,
+          "-m",
+          "comment",
+          "--comment",
+          comment,
+          "-m",
+          "multiport",
+          "--destination-ports",
+          &ports.join(","),
+          "-j",
+          chain,
+        ]
+        .map(str::to_string)
+        .to_vec(),
+      );
+    }
+  }
+  rules
+}
+
+/// The network and container of a rule jumping to the chain of an attachment, from its comment as
+/// listed by `-S`, like `--comment "name: \"net\" id: \"id\""`.
+fn owner(rule: &str) -> Option<(String, String)> {
+  let field = |name: &str| {
+    let (_, value) = rule.split_once(&format!("{}: \\\"", name))?;
+    let (value, _) = value.split_once("\\\"")?;
+    Some(value.to_string())
+  };
+  Some((field("name")?, field("id")?))
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn entry_rules_dedup() {
+    let mappings = serde_json::from_value::<Vec<PortMapping>>(json!([
+      { "hostPort": 8080, "containerPort": 80 },
+      { "hostPort": 53, "containerPort": 53, "protocol": "udp" },
+      { "hostPort": 8443, "containerPort": 443 },
+      { "hostPort": 8080, "containerPort": 8080 }
+    ]))
+    .unwrap();
+    let mappings = mappings.iter().collect::<Vec<_>>();
+
+    let ports = entry_rules("CNI-DN-test", "comment", &mappings)
+      .into_iter()
+      .map(|rule| (rule[1].clone(), rule[9].clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      ports,
+      [
+        ("tcp".to_string(), "8080,8443".to_string()),
+        ("udp".to_string(), "53".to_string())
+      ]
+    );
+  }
+}
@@ -0,0 +1,147 @@
+/*
+[portmap plugin](https://www.cni.dev/plugins/current/meta/portmap/)
+
+Forwards ports of the host to the container, as requested through the `portMappings` capability.
+Chained after a plugin giving the container its addresses, whose result is passed through.
+ */
+use cni_plugins::sysctl;
+use cni_plugins::validate;
+use libcni::api::CniPlugin;
+use libcni::plugin_main_entrypoint;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::version;
+
+use crate::config::container_ips;
+use crate::config::mappings_for;
+use crate::config::port_mappings;
+use crate::config::Backend;
+use crate::config::PortMapConfig;
+
+mod config;
+mod iptables;
+mod nftables;
+
+struct PortMap;
+
+impl CniPlugin for PortMap {
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = PortMapConfig::load(&args.config)?;
+    let mut result = args.config.prev_result.clone().ok_or_else(|| {
+      CniErrorCode::InvalidNetworkConfig("must be called as chained plugin".into())
+    })?;
+    result.cni_version = args.config.cni_version.clone();
+
+    let mappings = port_mappings(&args.config)?;
+    if mappings.is_empty() {
+      return Ok(result);
+    }
+    let backend = config.backend();
+    for container_ip in container_ips(&result)? {
+      let mappings = mappings_for(&mappings, container_ip);
+      if mappings.is_empty() {
+        continue;
+      }
+      match backend {
+        Backend::Iptables => iptables::forward_ports(
+          &config,
+          &args.config.name,
+          &args.container_id,
+          container_ip,
+          &mappings,
+        )?,
+        Backend::Nftables => nftables::forward_ports(
+          &config,
+          &args.config.name,
+          &args.container_id,
+          container_ip,
+          &mappings,
+        )?,
+      }
+      if config.snat && container_ip.is_ipv4() {
+        enable_localnet_routing(&result)?;
+      }
+    }
+    Ok(result)
+  }
+
+  fn del(&self, args: CniDelContext) -> CniResult<()> {
+    let config = PortMapConfig::load(&args.config)?;
+    match config.backend() {
+      Backend::Iptables => iptables::teardown_all(&args.config.name, &args.container_id),
+      Backend::Nftables => nftables::teardown_all(&args.config.name, &args.container_id),
+    }
+  }
+
+  fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = PortMapConfig::load(&args.config)?;
+    let result = validate::prev_result(&args.config)?;
+    let mappings = port_mappings(&args.config)?;
+    let backend = config.backend();
+    for container_ip in container_ips(result)? {
+      let mappings = mappings_for(&mappings, container_ip);
+      if mappings.is_empty() {
+        continue;
+      }
+      match backend {
+        Backend::Iptables => iptables::check(
+          &config,
+          &args.config.name,
+          &args.container_id,
+          container_ip,
+          &mappings,
+        )?,
+        Backend::Nftables => nftables::check(
+          &args.config.name,
+          &args.container_id,
+          container_ip,
+          &mappings,
+        )?,
+      }
+    }
+    Ok(())
+  }
+
+  fn gc(&self, args: CniGcContext) -> CniResult<()> {
+    let config = PortMapConfig::load(&args.config)?;
+    let valid = args
+      .valid_attachments
+      .iter()
+      .map(|attachment| attachment.container_id.as_str())
+      .collect::<Vec<_>>();
+    match config.backend() {
+      Backend::Iptables => iptables::gc(&args.config.name, &valid),
+      Backend::Nftables => nftables::gc(&args.config.name, &valid),
+    }
+  }
+
+  fn status(&self, args: CniStatusContext) -> CniResult<()> {
+    PortMapConfig::load(&args.config).map(|_| ())
+  }
+}
+
+/// Lets traffic to `127.0.0.0/8` be DNATed to the container, through the host interfaces of
+/// `result`.
+fn enable_localnet_routing(result: &AddReply) -> CniResult<()> {
+  for interface in result.interfaces.iter() {
+    if interface.sandbox.is_some() {
+      continue;
+    }
+    let name = sysctl::interface("net.ipv4.conf", &interface.name, "route_localnet");
+    // Interfaces of the result may be gone since, or have been renamed.
+    if sysctl::exists(&name) {
+      sysctl::set(&name, "1")?;
+    }
+  }
+  Ok(())
+}
+
+fn main() {
+  plugin_main_entrypoint(PortMap, version::All, semver::Version::new(1, 1, 0))
+}
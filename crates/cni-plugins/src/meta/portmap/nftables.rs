@@ -0,0 +1,161 @@
+/*
+Port forwarding with nftables, in a `cni_hostport` table of each IP version:
+
+- `hostports` DNATs traffic to the host ports of each attachment. The `prerouting` and `output`
+  base chains jump to it for local destinations.
+- `masquerading`, a postrouting base chain, masquerades traffic coming from the container itself or
+  from localhost.
+
+Rules are commented with the network and container they belong to.
+ */
+use std::net::IpAddr;
+
+use cni_plugins::nftables;
+use libcni::schema::config::PortMapping;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+use crate::config::PortMapConfig;
+
+const TABLE: &str = "cni_hostport";
+const HOSTPORTS_CHAIN: &str = "hostports";
+const MASQUERADING_CHAIN: &str = "masquerading";
+const FAMILIES: [&str; 2] = ["ip", "ip6"];
+
+/// Forwards the host ports of `mappings` to `container_ip`, replacing previous rules of the
+/// attachment.
+pub fn forward_ports(
+  config: &PortMapConfig,
+  network: &str,
+  container_id: &str,
+  container_ip: IpAddr,
+  mappings: &[&PortMapping],
+) -> CniResult<()> {
+  let family = family(container_ip);
+  teardown(family, network, container_id)?;
+
+  let comment = comment(network, container_id);
+  let mut script = format!(
+    "add table {family} {TABLE}\n\
+     add chain {family} {TABLE} {HOSTPORTS_CHAIN}\n\
+     add chain {family} {TABLE} prerouting {{ type nat hook prerouting priority dstnat ; }}\n\
+     add chain {family} {TABLE} output {{ type nat hook output priority dstnat ; }}\n\
+     add chain {family} {TABLE} {MASQUERADING_CHAIN} {{ type nat hook postrouting priority srcnat ; }}\n\
+     flush chain {family} {TABLE} prerouting\n\
+     flush chain {family} {TABLE} output\n\
+     add rule {family} {TABLE} prerouting fib daddr type local jump {HOSTPORTS_CHAIN}\n\
+     add rule {family} {TABLE} output fib daddr type local jump {HOSTPORTS_CHAIN}\n"
+  );
+  for mapping in mappings {
+    let protocol = &mapping.protocol;
+    let host_ip = mapping
+      .host_ip
+      .map(|host_ip| format!("{} daddr {} ", family, host_ip))
+      .unwrap_or_default();
+    let destination = match container_ip {
+      IpAddr::V4(ip) => format!("{}:{}", ip, mapping.container_port),
+      IpAddr::V6(ip) => format!("[{}]:{}", ip, mapping.container_port),
+    };
+    script.push_str(&format!(
+      "add rule {family} {TABLE} {HOSTPORTS_CHAIN} {host_ip}{protocol} dport {} dnat to {destination} comment \"{comment}\"\n",
+      mapping.host_port,
+    ));
+
+    if !config.snat {
+      continue;
+    }
+    let mut sources = vec![container_ip.to_string()];
+    // IPv6 loopback traffic cannot be routed elsewhere.
+    if container_ip.is_ipv4() {
+      sources.push("127.0.0.1".to_string());
+    }
+    for source in sources {
+      script.push_str(&format!(
+        "add rule {family} {TABLE} {MASQUERADING_CHAIN} {family} saddr {source} {family} daddr {container_ip} {protocol} dport {} masquerade comment \"{comment}\"\n",
+        mapping.container_port,
+      ));
+    }
+  }
+  nftables::apply(&script)
+}
+
+/// Checks that the DNAT rules installed by [`forward_ports`] are in place.
+pub fn check(
+  network: &str,
+  container_id: &str,
+  container_ip: IpAddr,
+  mappings: &[&PortMapping],
+) -> CniResult<()> {
+  let comment = comment(network, container_id);
+  let found = nftables::rules(family(container_ip), TABLE, HOSTPORTS_CHAIN)?
+    .into_iter()
+    .filter(|rule| rule.comment.as_deref() == Some(comment.as_str()))
+    .count();
+  if found != mappings.len() {
+    return Err(CniErrorCode::Internal(
+      format!(
+        "found {} of the {} port mappings of {}",
+        found,
+        mappings.len(),
+        container_ip
+      )
+      .into(),
+    ));
+  }
+  Ok(())
+}
+
+/// Removes the rules of the attachment, for both IP versions.
+pub fn teardown_all(network: &str, container_id: &str) -> CniResult<()> {
+  for family in FAMILIES {
+    teardown(family, network, container_id)?;
+  }
+  Ok(())
+}
+
+/// Removes the rules of the attachments of `network` other than those of `valid` containers.
+pub fn gc(network: &str, valid: &[&str]) -> CniResult<()> {
+  for family in FAMILIES {
+    for chain in [HOSTPORTS_CHAIN, MASQUERADING_CHAIN] {
+      let stale = nftables::rules(family, TABLE, chain)?
+        .into_iter()
+        .filter(|rule| {
+          rule
+            .comment
+            .as_deref()
+            .and_then(|comment| comment.split_once(' '))
+            .is_some_and(|(owner, container_id)| owner == network && !valid.contains(&container_id))
+        })
+        .map(|rule| rule.handle)
+        .collect::<Vec<_>>();
+      nftables::delete_rules(family, TABLE, chain, &stale)?;
+    }
+  }
+  Ok(())
+}
+
+fn teardown(family: &str, network: &str, container_id: &str) -> CniResult<()> {
+  let comment = comment(network, container_id);
+  for chain in [HOSTPORTS_CHAIN, MASQUERADING_CHAIN] {
+    let handles = nftables::rules(family, TABLE, chain)?
+      .into_iter()
+      .filter(|rule| rule.comment.as_deref() == Some(comment.as_str()))
+      .map(|rule| rule.handle)
+      .collect::<Vec<_>>();
+    nftables::delete_rules(family, TABLE, chain, &handles)?;
+  }
+  Ok(())
+}
+
+/// Network names and container IDs have no spaces, nor quotes.
+fn comment(network: &str, container_id: &str) -> String {
+  format!("{} {}", network, container_id)
+}
+
+fn family(ip: IpAddr) -> &'static str {
+  if ip.is_ipv4() {
+    "ip"
+  } else {
+    "ip6"
+  }
+}
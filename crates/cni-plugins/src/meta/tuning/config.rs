@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use libcni::schema::args::CniArgs;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::MacAddress;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Host file listing the sysctls containers may set, one regular expression per line. All `net.`
+/// sysctls are allowed without it.
+const ALLOWLIST: &str = "/etc/cni/tuning/allowlist.conf";
+/// Placeholder for the container interface in the names of sysctls.
+const IFNAME: &str = "IFNAME";
+
+/// The network configuration keys of the tuning plugin. `MAC`, `MTU`, `PROMISC` and `ALLMULTI` of
+/// `CNI_ARGS` take precedence over their keys, and the `mac` capability over both.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TuningConfig {
+  /// Sysctls of the network namespace of the container. `IFNAME` in names stands for the
+  /// container interface.
+  #[serde(default)]
+  pub sysctl:   BTreeMap<String, String>,
+  #[serde(default)]
+  pub mac:      Option<MacAddress>,
+  #[serde(default)]
+  pub mtu:      Option<u32>,
+  /// Puts the container interface in promiscuous mode, if set.
+  #[serde(default)]
+  pub promisc:  bool,
+  #[serde(default)]
+  pub allmulti: Option<bool>,
+  /// Where the original values of the interface are kept until `DEL`.
+  #[serde(default = "default_data_dir")]
+  pub data_dir: PathBuf,
+}
+
+/// The `CNI_ARGS` of the tuning plugin.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct TuningArgs {
+  #[serde(default)]
+  mac:      Option<MacAddress>,
+  #[serde(default)]
+  mtu:      Option<u32>,
+  #[serde(default)]
+  promisc:  Option<bool>,
+  #[serde(default)]
+  allmulti: Option<bool>,
+}
+
+fn default_data_dir() -> PathBuf {
+  PathBuf::from("/run/cni/tuning")
+}
+
+impl TuningConfig {
+  pub fn load(config: &NetworkConfig, args: &CniArgs) -> CniResult<Self> {
+    let mut tuning = config.plugin.parse::<Self>()?;
+    let args = args.parse::<TuningArgs>()?;
+    if let Some(mac) = args.mac {
+      tuning.mac = Some(mac);
+    }
+    if let Some(mtu) = args.mtu {
+      tuning.mtu = Some(mtu);
+    }
+    if let Some(promisc) = args.promisc {
+      tuning.promisc = promisc;
+    }
+    if let Some(allmulti) = args.allmulti {
+      tuning.allmulti = Some(allmulti);
+    }
+    if let Some(mac) = config
+      .plugin
+      .runtime_config
+      .as_ref()
+      .and_then(|config| config.mac.clone())
+    {
+      tuning.mac = Some(mac);
+    }
+
+    let allowlist = allowlist(Path::new(ALLOWLIST))?;
+    check_sysctls(&tuning.sysctl, allowlist.as_deref())?;
+    Ok(tuning)
+  }
+
+  /// The sysctls of the container, with the names of `if_name`. Names are given with slashes, so
+  /// that the dots of `if_name` are kept.
+  pub fn sysctls<'a>(&'a self, if_name: &'a str) -> impl Iterator<Item = (String, &'a str)> {
+    self.sysctl.iter().map(move |(name, value)| {
+      (
+        name.replace('.', "/").replace(IFNAME, if_name),
+        value.as_str(),
+      )
+    })
+  }
+}
+
+/// Fails on sysctls outside of `net.`, or missing from `allowlist` if any.
+fn check_sysctls(sysctl: &BTreeMap<String, String>, allowlist: Option<&[Regex]>) -> CniResult<()> {
+  for name in sysctl.keys() {
+    if !name.starts_with("net.") || name.split('.').any(str::is_empty) {
+      return Err(CniErrorCode::InvalidNetworkConfig(
+        format!("invalid net sysctl key {:?}", name).into(),
+      ));
+    }
+    if let Some(allowlist) = allowlist {
+      if !allowlist.iter().any(|allowed| allowed.is_match(name)) {
+        return Err(CniErrorCode::InvalidNetworkConfig(
+          format!("sysctl {:?} is not allowed", name).into(),
+        ));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// The expressions of the allowlist at `path`, none if there is no allowlist.
+fn allowlist(path: &Path) -> CniResult<Option<Vec<Regex>>> {
+  let allowlist = match std::fs::read_to_string(path) {
+    Ok(allowlist) => allowlist,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(CniErrorCode::IOFailure(e)),
+  };
+  allowlist
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .map(|line| {
+      Regex::new(line).map_err(|e| {
+        CniErrorCode::Internal(format!("invalid expression in {}: {}", path.display(), e).into())
+      })
+    })
+    .collect::<CniResult<_>>()
+    .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  fn sysctl(names: &[&str]) -> BTreeMap<String, String> {
+    names
+      .iter()
+      .map(|name| (name.to_string(), "1".to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn sysctls() {
+    let tuning = serde_json::from_value::<TuningConfig>(json!({
+      "sysctl": {
+        "net.ipv4.conf.IFNAME.arp_filter": "1",
+        "net.ipv4.ip_forward": "0"
+      }
+    }))
+    .unwrap();
+    assert_eq!(
+      tuning.sysctls("eth0.100").collect::<Vec<_>>(),
+      [
+        ("net/ipv4/conf/eth0.100/arp_filter".to_string(), "1"),
+        ("net/ipv4/ip_forward".to_string(), "0"),
+      ]
+    );
+  }
+
+  #[test]
+  fn net_sysctls_only() {
+    check_sysctls(&sysctl(&["net.ipv4.ip_forward"]), None).unwrap();
+    for name in ["kernel.panic", "net..ip_forward", "net.ipv4."] {
+      let error = check_sysctls(&sysctl(&[name]), None).unwrap_err();
+      assert!(matches!(error, CniErrorCode::InvalidNetworkConfig(_)));
+    }
+  }
+
+  #[test]
+  fn allowlisted_sysctls() {
+    let dir = std::env::temp_dir().join(format!("tuning-allowlist-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("allowlist.conf");
+    assert!(allowlist(&path).unwrap().is_none());
+
+    std::fs::write(
+      &path,
+      "^net\\.ipv4\\.conf\\.IFNAME\\.[a-z_]*$\n\n  ^net\\.ipv4\\.ip_forward$\n",
+    )
+    .unwrap();
+    let allowed = allowlist(&path).unwrap().unwrap();
+    check_sysctls(
+      &sysctl(&["net.ipv4.conf.IFNAME.arp_filter", "net.ipv4.ip_forward"]),
+      Some(&allowed),
+    )
+    .unwrap();
+    let error = check_sysctls(&sysctl(&["net.core.somaxconn"]), Some(&allowed)).unwrap_err();
+    assert!(matches!(error, CniErrorCode::InvalidNetworkConfig(_)));
+
+    std::fs::write(&path, "(unclosed\n").unwrap();
+    assert!(allowlist(&path).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
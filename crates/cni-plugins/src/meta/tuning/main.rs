@@ -0,0 +1,228 @@
+/*
+[tuning plugin](https://www.cni.dev/plugins/current/meta/tuning/)
+
+Sets sysctls of the network namespace of the container, and the MAC address, MTU and flags of its
+interface. The original values of the interface are kept in a file of the data directory, and
+restored on `DEL`. Sysctls go away with the namespace.
+ */
+use std::path::Path;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use cni_plugins::sysctl;
+use cni_plugins::validate;
+use cni_plugins::validate::not_found;
+use libcni::api::AsyncCniPlugin;
+use libcni::async_plugin_main_entrypoint;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::MacAddress;
+use libcni::version;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::TuningConfig;
+
+mod config;
+
+/// The original values of what the plugin changes on the container interface.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Backup {
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  mac:      Option<MacAddress>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  mtu:      Option<u32>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  promisc:  Option<bool>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  allmulti: Option<bool>,
+}
+
+struct Tuning;
+
+#[async_trait(?Send)]
+impl AsyncCniPlugin for Tuning {
+  async fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+    let config = TuningConfig::load(&args.config, &args.args)?;
+    let mut result = args.config.prev_result.clone().ok_or_else(|| {
+      CniErrorCode::InvalidNetworkConfig("must be called as chained plugin".into())
+    })?;
+    result.cni_version = args.config.cni_version.clone();
+
+    let netns = NetNs::open(&args.netns)?;
+    for (name, value) in config.sysctls(&args.if_name) {
+      sysctl::set_in(&netns, &name, value)?;
+    }
+
+    let container = Netlink::open(&netns)?;
+    let link = container
+      .link(&args.if_name)
+      .await?
+      .ok_or_else(|| not_found(&format!("interface {}", args.if_name)))?;
+    // A second ADD must not take the values of the first for the original ones.
+    let backup = backup_path(&config.data_dir, &args.container_id, &args.if_name);
+    if !backup.exists() {
+      save_backup(&backup, &config, &link)?;
+    }
+
+    if let Some(mac) = config.mac.as_ref() {
+      container.set_mac(link.index, mac).await?;
+    }
+    if let Some(mtu) = config.mtu {
+      container.set_mtu(link.index, mtu).await?;
+    }
+    if config.promisc {
+      container.set_promiscuous(link.index, true).await?;
+    }
+    if let Some(allmulti) = config.allmulti {
+      container.set_allmulticast(link.index, allmulti).await?;
+    }
+
+    let link = container.link_by_index(link.index).await?;
+    for interface in result.interfaces.iter_mut() {
+      if interface.name == args.if_name
+        && interface.sandbox.as_deref() == Some(Path::new(&args.netns))
+      {
+        interface.mac = link.mac.clone();
+      }
+    }
+    Ok(result)
+  }
+
+  /// Restores the original values of the interface, if it is still there.
+  async fn del(&self, args: CniDelContext) -> CniResult<()> {
+    let config = TuningConfig::load(&args.config, &args.args)?;
+    let path = backup_path(&config.data_dir, &args.container_id, &args.if_name);
+    let backup = match std::fs::read(&path) {
+      Ok(backup) => serde_json::from_slice::<Backup>(&backup)
+        .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(CniErrorCode::IOFailure(e)),
+    };
+
+    if let Some(Ok(netns)) = args.netns.as_deref().map(NetNs::open) {
+      let container = Netlink::open(&netns)?;
+      if let Some(link) = container.link(&args.if_name).await? {
+        restore(&container, &link, &backup).await?;
+      }
+    }
+    std::fs::remove_file(&path).map_err(CniErrorCode::IOFailure)
+  }
+
+  async fn check(&self, args: CniCheckContext) -> CniResult<()> {
+    let config = TuningConfig::load(&args.config, &args.args)?;
+    validate::prev_result(&args.config)?;
+
+    let netns = NetNs::open(&args.netns)?;
+    for (name, value) in config.sysctls(&args.if_name) {
+      let actual = sysctl::get_in(&netns, &name)?;
+      if actual != value {
+        return Err(mismatch(&format!("sysctl {}", name), &actual, value));
+      }
+    }
+
+    let container = Netlink::open(&netns)?;
+    let link = container
+      .link(&args.if_name)
+      .await?
+      .ok_or_else(|| not_found(&format!("interface {}", args.if_name)))?;
+    if let Some(mac) = config.mac.as_ref() {
+      if link.mac.as_ref().map(|actual| actual.0) != Some(mac.0) {
+        let actual = link.mac.as_ref().map(|mac| mac.0.to_string());
+        return Err(mismatch(
+          "MAC",
+          actual.as_deref().unwrap_or("none"),
+          &mac.0.to_string(),
+        ));
+      }
+    }
+    if let Some(mtu) = config.mtu {
+      if link.mtu != Some(mtu) {
+        return Err(mismatch(
+          "MTU",
+          &format!("{:?}", link.mtu),
+          &mtu.to_string(),
+        ));
+      }
+    }
+    if config.promisc && !link.promisc {
+      return Err(mismatch("promiscuous mode", "off", "on"));
+    }
+    if let Some(allmulti) = config.allmulti {
+      if link.allmulti != allmulti {
+        return Err(mismatch(
+          "all-multicast mode",
+          &link.allmulti.to_string(),
+          &allmulti.to_string(),
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  async fn gc(&self, _args: CniGcContext) -> CniResult<()> {
+    Ok(())
+  }
+
+  async fn status(&self, _args: CniStatusContext) -> CniResult<()> {
+    Ok(())
+  }
+}
+
+fn backup_path(data_dir: &Path, container_id: &str, if_name: &str) -> PathBuf {
+  data_dir.join(format!("{}-{}.json", container_id, if_name))
+}
+
+/// Keeps the values of `link` which `config` changes.
+fn save_backup(path: &Path, config: &TuningConfig, link: &Link) -> CniResult<()> {
+  let backup = Backup {
+    mac:      config.mac.as_ref().and(link.mac.clone()),
+    mtu:      config.mtu.and(link.mtu),
+    promisc:  config.promisc.then_some(link.promisc),
+    allmulti: config.allmulti.map(|_| link.allmulti),
+  };
+  let bytes =
+    serde_json::to_vec(&backup).map_err(|e| CniErrorCode::Internal(e.to_string().into()))?;
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent).map_err(CniErrorCode::IOFailure)?;
+  }
+  std::fs::write(path, bytes).map_err(CniErrorCode::IOFailure)
+}
+
+async fn restore(container: &Netlink, link: &Link, backup: &Backup) -> CniResult<()> {
+  if let Some(mac) = backup.mac.as_ref() {
+    container.set_mac(link.index, mac).await?;
+  }
+  if let Some(mtu) = backup.mtu {
+    container.set_mtu(link.index, mtu).await?;
+  }
+  if let Some(promisc) = backup.promisc {
+    container.set_promiscuous(link.index, promisc).await?;
+  }
+  if let Some(allmulti) = backup.allmulti {
+    container.set_allmulticast(link.index, allmulti).await?;
+  }
+  Ok(())
+}
+
+fn mismatch(what: &str, actual: &str, expected: &str) -> CniErrorCode {
+  CniErrorCode::Internal(format!("{} is {}, expected {}", what, actual, expected).into())
+}
+
+fn main() {
+  async_plugin_main_entrypoint(Tuning, version::All, semver::Version::new(1, 1, 0))
+}
@@ -0,0 +1,96 @@
+/*
+Rules of the `nft` command. Rules are owned through their comment, and deleted by handle.
+ */
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+/// A rule as listed by `nft -a`, with its comment and handle.
+#[derive(Clone, Debug)]
+pub struct Rule {
+  pub comment: Option<String>,
+  pub handle:  u64,
+}
+
+/// Whether the `nft` command can be run.
+pub fn available() -> bool {
+  Command::new("nft")
+    .arg("--version")
+    .output()
+    .is_ok_and(|output| output.status.success())
+}
+
+/// Applies the commands of `script` atomically, as `nft -f`.
+pub fn apply(script: &str) -> CniResult<()> {
+  let mut child = Command::new("nft")
+    .args(["-f", "-"])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(CniErrorCode::IOFailure)?;
+  child
+    .stdin
+    .take()
+    .expect("stdin is piped")
+    .write_all(script.as_bytes())
+    .map_err(CniErrorCode::IOFailure)?;
+
+  let output = child.wait_with_output().map_err(CniErrorCode::IOFailure)?;
+  if !output.status.success() {
+    return Err(CniErrorCode::Internal(
+      format!("nft: {}", String::from_utf8_lossy(&output.stderr).trim()).into(),
+    ));
+  }
+  Ok(())
+}
+
+/// The rules of `chain`, none if the table or the chain does not exist.
+pub fn rules(family: &str, table: &str, chain: &str) -> CniResult<Vec<Rule>> {
+  let output = Command::new("nft")
+    .args(["-a", "list", "chain", family, table, chain])
+    .output()
+    .map_err(CniErrorCode::IOFailure)?;
+  if !output.status.success() {
+    return Ok(Vec::new());
+  }
+
+  Ok(
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| {
+        let (rule, handle) = line.rsplit_once("# handle ")?;
+        let handle = handle.trim().parse().ok()?;
+        // Chains list their own handle too.
+        if rule.trim_start().starts_with("chain ") {
+          return None;
+        }
+        let comment = rule
+          .split_once("comment \"")
+          .and_then(|(_, comment)| comment.split_once('"'))
+          .map(|(comment, _)| comment.to_string());
+        Some(Rule { comment, handle })
+      })
+      .collect(),
+  )
+}
+
+/// Deletes the rules of `chain` with `handles`.
+pub fn delete_rules(family: &str, table: &str, chain: &str, handles: &[u64]) -> CniResult<()> {
+  if handles.is_empty() {
+    return Ok(());
+  }
+  let script = handles
+    .iter()
+    .map(|handle| {
+      format!(
+        "delete rule {} {} {} handle {}\n",
+        family, table, chain, handle
+      )
+    })
+    .collect::<String>();
+  apply(&script)
+}
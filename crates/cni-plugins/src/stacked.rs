@@ -0,0 +1,222 @@
+/*
+Interfaces stacked on a master link of the host, like macvlan and ipvlan.
+
+The link is created on the master and moved to the container at once, then given the addresses of
+the IPAM plugin, if any. Either is undone if the other fails.
+ */
+use std::path::Path;
+
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::IpvlanMode;
+use libcni::link::Link;
+use libcni::link::MacvlanMode;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::plugin::ipam;
+use libcni::schema::args::CniAddContext;
+use libcni::schema::args::CniCheckContext;
+use libcni::schema::args::CniDelContext;
+use libcni::schema::args::CniGcContext;
+use libcni::schema::args::CniStatusContext;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::args;
+use crate::ips;
+use crate::master;
+use crate::validate;
+use crate::validate::not_found;
+
+/// Index of the container interface in results, the only one.
+const CONTAINER_INTERFACE: usize = 0;
+
+/// The kind of a stacked link, with its mode.
+#[derive(Clone, Copy, Debug)]
+pub enum StackedKind {
+  Macvlan(MacvlanMode),
+  Ipvlan(IpvlanMode),
+}
+
+impl From<MacvlanMode> for StackedKind {
+  fn from(mode: MacvlanMode) -> Self {
+    StackedKind::Macvlan(mode)
+  }
+}
+
+impl From<IpvlanMode> for StackedKind {
+  fn from(mode: IpvlanMode) -> Self {
+    StackedKind::Ipvlan(mode)
+  }
+}
+
+impl StackedKind {
+  fn info_kind(self) -> InfoKind {
+    match self {
+      StackedKind::Macvlan(_) => InfoKind::MacVlan,
+      StackedKind::Ipvlan(_) => InfoKind::IpVlan,
+    }
+  }
+
+  fn description(self) -> &'static str {
+    match self {
+      StackedKind::Macvlan(_) => "a macvlan link",
+      StackedKind::Ipvlan(_) => "an ipvlan link",
+    }
+  }
+
+  async fn add(
+    self,
+    netlink: &Netlink,
+    name: &str,
+    master: u32,
+    mtu: Option<u32>,
+    netns: &NetNs,
+  ) -> CniResult<()> {
+    match self {
+      StackedKind::Macvlan(mode) => netlink.add_macvlan(name, master, mode, mtu, netns).await,
+      StackedKind::Ipvlan(mode) => netlink.add_ipvlan(name, master, mode, mtu, netns).await,
+    }
+  }
+}
+
+/// The network configuration keys of the plugins of stacked links, with the modes `M` of their
+/// kind.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackedConfig<M> {
+  /// Link to stack the interface on, that of the default route by default.
+  #[serde(default)]
+  pub master:            Option<String>,
+  #[serde(default)]
+  pub mode:              M,
+  /// MTU of the interface, at most that of the master. Defaults to the kernel's.
+  #[serde(default)]
+  pub mtu:               Option<u32>,
+  /// Looks the master up in the network namespace of the container, instead of the host's.
+  #[serde(default)]
+  pub link_in_container: bool,
+}
+
+impl<M> StackedConfig<M>
+where
+  M: DeserializeOwned + Default,
+{
+  pub fn load(config: &NetworkConfig) -> CniResult<Self> {
+    config.plugin.parse::<Self>()
+  }
+}
+
+/// Creates the interface of the container on its master, with the addresses of the IPAM plugin if
+/// any.
+pub fn add<M>(args: &CniAddContext, config: &StackedConfig<M>) -> CniResult<AddReply>
+where
+  M: Copy + Into<StackedKind>,
+{
+  let kind = config.mode.into();
+  let netns = NetNs::open(&args.netns)?;
+  block_on(async {
+    let container = Netlink::open(&netns)?;
+    let host = if config.link_in_container {
+      Netlink::open(&netns)?
+    } else {
+      Netlink::new()?
+    };
+
+    let master = master::find(&host, config.master.as_deref()).await?;
+    master::check_mtu(&master, config.mtu)?;
+    kind
+      .add(&host, &args.if_name, master.index, config.mtu, &netns)
+      .await?;
+    let link = container
+      .link(&args.if_name)
+      .await?
+      .ok_or_else(|| not_found(&format!("interface {}", args.if_name)))?;
+
+    match setup(args, kind, &container, &link).await {
+      Ok(result) => Ok(result),
+      Err(e) => {
+        let _ = container.del_link(link.index).await;
+        Err(e)
+      }
+    }
+  })
+}
+
+/// Releases the addresses of the interface, and deletes it unless its namespace is gone already.
+pub fn del(args: &CniDelContext) -> CniResult<()> {
+  if args.config.plugin.ipam.is_some() {
+    ipam::exec_del(&args.config, &args.path)?;
+  }
+
+  block_on(ips::del_container_link(args))?;
+  Ok(())
+}
+
+/// Checks the link, addresses and routes of `prevResult` against those in place.
+pub fn check<M>(args: &CniCheckContext, config: &StackedConfig<M>) -> CniResult<()>
+where
+  M: Copy + Into<StackedKind>,
+{
+  let kind = config.mode.into();
+  if args.config.plugin.ipam.is_some() {
+    ipam::exec_check(args)?;
+  }
+  let result = validate::prev_result(&args.config)?;
+
+  let netns = NetNs::open(&args.netns)?;
+  let link = block_on(async {
+    let container = Netlink::open(&netns)?;
+    validate::check_container_interface(&container, result, &args.if_name, &args.netns).await
+  })?;
+  if link.kind != Some(kind.info_kind()) {
+    return Err(CniErrorCode::Internal(
+      format!("interface {} is not {}", args.if_name, kind.description()).into(),
+    ));
+  }
+  Ok(())
+}
+
+pub fn gc(args: &CniGcContext) -> CniResult<()> {
+  if args.config.plugin.ipam.is_some() {
+    ipam::exec_gc(args)?;
+  }
+  Ok(())
+}
+
+pub fn status(args: &CniStatusContext) -> CniResult<()> {
+  if args.config.plugin.ipam.is_some() {
+    ipam::exec_status(args)?;
+  }
+  Ok(())
+}
+
+/// Sets the requested MAC address of a new macvlan link, ipvlan ones sharing that of their
+/// master, and applies the result of the IPAM plugin to it.
+async fn setup(
+  args: &CniAddContext,
+  kind: StackedKind,
+  container: &Netlink,
+  link: &Link,
+) -> CniResult<AddReply> {
+  if let StackedKind::Macvlan(_) = kind {
+    if let Some(mac) = args::requested_mac(args)? {
+      container.set_mac(link.index, &mac).await?;
+    }
+  }
+
+  let mut result = ips::delegate_or_up(args, container, link.index, |result| {
+    ips::configure_ips(args, container, link.index, CONTAINER_INTERFACE, result)
+  })
+  .await?;
+
+  result.interfaces = vec![container
+    .link_by_index(link.index)
+    .await?
+    .interface(Some(Path::new(&args.netns)))];
+  Ok(result)
+}
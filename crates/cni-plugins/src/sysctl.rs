@@ -0,0 +1,77 @@
+/*
+Kernel parameters under `/proc/sys`, named with dots like `net.ipv4.ip_forward`, or with slashes
+like `net/ipv4/conf/eth0.100/forwarding` when a component has dots of its own, as interface names
+may. Network parameters are those of the network namespace of the calling thread, or of the one
+given to the `_in` variants.
+ */
+use std::path::PathBuf;
+
+use libcni::netns::NetNs;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+
+pub fn get(name: &str) -> CniResult<String> {
+  std::fs::read_to_string(path(name))
+    .map(|value| value.trim().to_string())
+    .map_err(CniErrorCode::IOFailure)
+}
+
+pub fn set(name: &str, value: &str) -> CniResult<()> {
+  std::fs::write(path(name), value).map_err(CniErrorCode::IOFailure)
+}
+
+/// Reads the parameter in network namespace `netns`.
+pub fn get_in(netns: &NetNs, name: &str) -> CniResult<String> {
+  let path = path(name);
+  netns
+    .run(|| std::fs::read_to_string(&path))?
+    .map(|value| value.trim().to_string())
+    .map_err(CniErrorCode::IOFailure)
+}
+
+/// Writes the parameter in network namespace `netns`.
+pub fn set_in(netns: &NetNs, name: &str, value: &str) -> CniResult<()> {
+  let path = path(name);
+  netns
+    .run(|| std::fs::write(&path, value))?
+    .map_err(CniErrorCode::IOFailure)
+}
+
+/// Whether the parameter exists, like those of an interface which is gone.
+pub fn exists(name: &str) -> bool {
+  path(name).exists()
+}
+
+/// The parameter of interface `if_name` in `prefix`, like `net.ipv4.conf`, named with slashes.
+pub fn interface(prefix: &str, if_name: &str, parameter: &str) -> String {
+  format!("{}/{}/{}", prefix.replace('.', "/"), if_name, parameter)
+}
+
+fn path(name: &str) -> PathBuf {
+  let name = match name.contains('/') {
+    true => name.to_string(),
+    false => name.replace('.', "/"),
+  };
+  PathBuf::from("/proc/sys").join(name)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+
+  #[test]
+  fn paths() {
+    assert_eq!(
+      path("net.ipv4.ip_forward"),
+      Path::new("/proc/sys/net/ipv4/ip_forward")
+    );
+    let name = interface("net.ipv4.conf", "eth0.100", "proxy_arp");
+    assert_eq!(name, "net/ipv4/conf/eth0.100/proxy_arp");
+    assert_eq!(
+      path(&name),
+      Path::new("/proc/sys/net/ipv4/conf/eth0.100/proxy_arp")
+    );
+  }
+}
@@ -0,0 +1,93 @@
+/*
+Checks of the state of links against the result of a previous `ADD`, for `CHECK`.
+ */
+use std::path::Path;
+
+use ipnetwork::IpNetwork;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use libcni::schema::config::NetworkConfig;
+use libcni::schema::error::CniErrorCode;
+use libcni::schema::error::CniResult;
+use libcni::schema::reply::AddReply;
+use libcni::schema::reply::Interface;
+
+/// The `prevResult` of the network configuration, which `CHECK` requires.
+pub fn prev_result(config: &NetworkConfig) -> CniResult<&AddReply> {
+  config
+    .prev_result
+    .as_ref()
+    .ok_or_else(|| CniErrorCode::InvalidNetworkConfig("required prevResult missing".into()))
+}
+
+/// Checks that interface `if_name` of the container in `netns` has the MAC, addresses and routes of
+/// `result`, and returns its link.
+pub async fn check_container_interface(
+  container: &Netlink,
+  result: &AddReply,
+  if_name: &str,
+  netns: &str,
+) -> CniResult<Link> {
+  let (index, interface) = result
+    .interfaces
+    .iter()
+    .enumerate()
+    .find(|(_, interface)| {
+      interface.name == if_name && interface.sandbox.as_deref() == Some(Path::new(netns))
+    })
+    .ok_or_else(|| not_found(&format!("interface {} in prevResult", if_name)))?;
+  let link = container
+    .link(if_name)
+    .await?
+    .ok_or_else(|| not_found(&format!("interface {}", if_name)))?;
+  check_mac(&link, interface)?;
+
+  let addresses = container.addresses(link.index).await?;
+  for ips in result.ips.iter() {
+    if ips.interface != Some(index) {
+      continue;
+    }
+    let address = parse_address(&ips.address)?;
+    if !addresses.contains(&address) {
+      return Err(not_found(&format!("address {} on {}", address, if_name)));
+    }
+  }
+  let routes = container.routes(link.index).await?;
+  for expected in result.routes.iter() {
+    let found = routes.iter().any(|route| {
+      route.dst == expected.dst && expected.gw.map_or(true, |gw| route.gw == Some(gw))
+    });
+    if !found {
+      return Err(not_found(&format!(
+        "route to {} on {}",
+        expected.dst, if_name
+      )));
+    }
+  }
+  Ok(link)
+}
+
+/// Checks that `link` has the MAC address of `interface`, if the result has one.
+pub fn check_mac(link: &Link, interface: &Interface) -> CniResult<()> {
+  match (&interface.mac, &link.mac) {
+    (Some(expected), Some(actual)) if expected.0 != actual.0 => Err(CniErrorCode::Internal(
+      format!(
+        "interface {} has MAC {}, expected {}",
+        link.name, actual.0, expected.0
+      )
+      .into(),
+    )),
+    _ => Ok(()),
+  }
+}
+
+/// Parses an address of a result, in CIDR notation.
+pub fn parse_address(address: &str) -> CniResult<IpNetwork> {
+  address
+    .parse()
+    .map_err(|e| CniErrorCode::Internal(format!("invalid address {}: {}", address, e).into()))
+}
+
+pub fn not_found(what: &str) -> CniErrorCode {
+  CniErrorCode::Internal(format!("{} not found", what).into())
+}
@@ -0,0 +1,150 @@
+use std::process::Command;
+
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::Netlink;
+use serde_json::json;
+use serde_json::Value;
+use sha2::Digest;
+use sha2::Sha512;
+
+use crate::common::exec;
+use crate::common::TestNs;
+
+mod common;
+
+const BANDWIDTH: &str = env!("CARGO_BIN_EXE_bandwidth");
+
+/// The output of `tc` for `args`, in `netns`.
+fn tc(netns: &TestNs, args: &[&str]) -> Vec<Value> {
+  let output = netns
+    .netns
+    .run(|| Command::new("tc").arg("-j").args(args).output())
+    .unwrap()
+    .unwrap();
+  assert!(output.status.success(), "tc {}", args.join(" "));
+  if output.stdout.iter().all(u8::is_ascii_whitespace) {
+    return Vec::new();
+  }
+  serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// The root queueing discipline of `device`.
+fn root_qdisc(netns: &TestNs, device: &str) -> Value {
+  tc(netns, &["qdisc", "show", "dev", device])
+    .into_iter()
+    .find(|qdisc| qdisc["root"] == true)
+    .unwrap()
+}
+
+/// Connects the container to the host with a veth pair, as the main plugin would, and returns the
+/// network configuration chaining bandwidth after it.
+fn setup(host: &TestNs, container: &TestNs) -> Value {
+  block_on(async {
+    Netlink::open(&container.netns)?
+      .add_veth("eth0", "veth-bw", &host.netns, None)
+      .await?;
+    Ok(())
+  })
+  .unwrap();
+  json!({
+    "cniVersion": "1.1.0",
+    "name": "bwnet",
+    "type": "bandwidth",
+    "ingressRate": 8000000,
+    "ingressBurst": 80000,
+    "egressRate": 16000000,
+    "egressBurst": 160000,
+    "prevResult": {
+      "cniVersion": "1.1.0",
+      "interfaces": [
+        { "name": "veth-bw" },
+        { "name": "eth0", "sandbox": container.netns.path() }
+      ],
+      "ips": []
+    }
+  })
+}
+
+fn ifb_name() -> String {
+  let digest = Sha512::digest(b"bwnettest");
+  let hex = digest
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  format!("bwp{}", &hex[..12])
+}
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("bandwidth-host");
+  let container = TestNs::create("bandwidth-container");
+  let mut config = setup(&host, &container);
+  let ifb = ifb_name();
+
+  let result = exec(BANDWIDTH, "ADD", &host, &container, "eth0", &config).unwrap();
+  let interfaces = result["interfaces"].as_array().unwrap();
+  assert_eq!(interfaces.len(), 3);
+  assert_eq!(interfaces[2]["name"], json!(ifb));
+  assert_eq!(interfaces[2]["sandbox"], Value::Null);
+
+  let tbf = root_qdisc(&host, "veth-bw");
+  assert_eq!(tbf["kind"], "tbf");
+  assert_eq!(tbf["options"]["rate"], 1000000);
+  assert_eq!(tbf["options"]["burst"], 10000);
+  let tbf = root_qdisc(&host, &ifb);
+  assert_eq!(tbf["kind"], "tbf");
+  assert_eq!(tbf["options"]["rate"], 2000000);
+  assert_eq!(tbf["options"]["burst"], 20000);
+  let filters = tc(
+    &host,
+    &["filter", "show", "dev", "veth-bw", "parent", "ffff:"],
+  );
+  assert!(filters.iter().any(|filter| {
+    filter["options"]["actions"][0]["kind"] == "mirred"
+      && filter["options"]["actions"][0]["to_dev"] == json!(ifb)
+  }));
+  block_on(async {
+    let link = Netlink::open(&host.netns)?.link(&ifb).await?.unwrap();
+    assert_eq!(link.kind, Some(InfoKind::Ifb));
+    assert!(link.up);
+    Ok(())
+  })
+  .unwrap();
+
+  config["prevResult"] = result;
+  exec(BANDWIDTH, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec(BANDWIDTH, "DEL", &host, &container, "eth0", &config).unwrap();
+  block_on(async {
+    assert!(Netlink::open(&host.netns)?.link(&ifb).await?.is_none());
+    Ok(())
+  })
+  .unwrap();
+  let error = exec(BANDWIDTH, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+
+  // Deleting again finds nothing left to delete.
+  exec(BANDWIDTH, "DEL", &host, &container, "eth0", &config).unwrap();
+}
+
+/// The ingress limit is lifted again when egress cannot be limited.
+#[test]
+fn egress_failure() {
+  let host = TestNs::create("bandwidth-failure-host");
+  let container = TestNs::create("bandwidth-failure-container");
+  let config = setup(&host, &container);
+  // The name of the IFB device is taken.
+  block_on(async {
+    Netlink::open(&host.netns)?
+      .add_veth(&ifb_name(), "bwp-peer", &host.netns, None)
+      .await?;
+    Ok(())
+  })
+  .unwrap();
+
+  exec(BANDWIDTH, "ADD", &host, &container, "eth0", &config).unwrap_err();
+  assert!(!tc(&host, &["qdisc", "show", "dev", "veth-bw"])
+    .iter()
+    .any(|qdisc| qdisc["kind"] == "tbf"));
+}
@@ -0,0 +1,201 @@
+use std::process::Command;
+
+use cni_plugins::iptables;
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::Netlink;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::common::exec;
+use crate::common::exec_with_env;
+use crate::common::FakeIpTables;
+use crate::common::TestDir;
+use crate::common::TestNs;
+
+mod common;
+
+const BRIDGE: &str = env!("CARGO_BIN_EXE_bridge");
+
+/// The output of the `bridge` command for `args`, in `netns`.
+fn bridge(netns: &TestNs, args: &[&str]) -> Value {
+  let output = netns
+    .netns
+    .run(|| Command::new("bridge").arg("-j").args(args).output())
+    .unwrap()
+    .unwrap();
+  assert!(output.status.success(), "bridge {}", args.join(" "));
+  serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("bridge-host");
+  let container = TestNs::create("bridge-container");
+  let data_dir = TestDir::create("bridge");
+  let iptables = FakeIpTables::install(&data_dir);
+  let path = iptables.path();
+  let env = [("PATH", path.as_str())];
+  let mut config = json!({
+    "cniVersion": "1.1.0",
+    "name": "bridgenet",
+    "type": "bridge",
+    "bridge": "cni-test0",
+    "isGateway": true,
+    "hairpinMode": true,
+    "ipMasq": true,
+    "ipam": {
+      "type": "host-local",
+      "subnet": "10.1.2.0/24",
+      "dataDir": data_dir.0
+    }
+  });
+
+  let result = exec_with_env(BRIDGE, "ADD", &host, &container, "eth0", &config, &env).unwrap();
+  assert_eq!(
+    result["ips"],
+    json!([{ "address": "10.1.2.2/24", "gateway": "10.1.2.1", "interface": 2 }])
+  );
+  let interfaces = result["interfaces"].as_array().unwrap();
+  assert_eq!(interfaces.len(), 3);
+  assert_eq!(interfaces[0]["name"], "cni-test0");
+  assert_eq!(interfaces[0]["sandbox"], Value::Null);
+  assert_eq!(interfaces[1]["sandbox"], Value::Null);
+  assert_eq!(interfaces[2]["name"], "eth0");
+  assert_eq!(interfaces[2]["sandbox"], json!(container.netns.path()));
+  let host_name = interfaces[1]["name"].as_str().unwrap().to_string();
+
+  block_on(async {
+    let netlink = Netlink::open(&host.netns)?;
+    let bridge = netlink.link("cni-test0").await?.unwrap();
+    assert_eq!(bridge.kind, Some(InfoKind::Bridge));
+    assert!(bridge.up);
+    assert_eq!(
+      netlink.addresses(bridge.index).await?,
+      vec!["10.1.2.1/24".parse().unwrap()]
+    );
+    let veth = netlink.link(&host_name).await?.unwrap();
+    assert_eq!(veth.kind, Some(InfoKind::Veth));
+    assert_eq!(veth.master, Some(bridge.index));
+    assert!(veth.up);
+
+    let netlink = Netlink::open(&container.netns)?;
+    let eth0 = netlink.link("eth0").await?.unwrap();
+    assert_eq!(eth0.parent, Some(veth.index));
+    assert!(eth0.up);
+    assert_eq!(
+      netlink.addresses(eth0.index).await?,
+      vec!["10.1.2.2/24".parse().unwrap()]
+    );
+    Ok(())
+  })
+  .unwrap();
+
+  let port = bridge(&host, &["-d", "link", "show", "dev", &host_name]);
+  assert_eq!(port[0]["master"], "cni-test0");
+  assert_eq!(port[0]["hairpin"], true);
+  let ip_forward = host
+    .netns
+    .run(|| std::fs::read_to_string("/proc/sys/net/ipv4/ip_forward"))
+    .unwrap()
+    .unwrap();
+  assert_eq!(ip_forward.trim(), "1");
+
+  let chain = iptables::chain_name("bridgenet", "test");
+  let comment = iptables::comment("bridgenet", "test");
+  assert_eq!(
+    iptables.rules(),
+    [
+      format!(":{}", chain),
+      format!(
+        "{} -d 10.1.2.2/24 -j ACCEPT -m comment --comment {}",
+        chain, comment
+      ),
+      format!(
+        "{} ! -d 224.0.0.0/4 -j MASQUERADE -m comment --comment {}",
+        chain, comment
+      ),
+      format!(
+        "POSTROUTING -s 10.1.2.2 -j {} -m comment --comment {}",
+        chain, comment
+      ),
+    ]
+  );
+
+  config["prevResult"] = result;
+  exec(BRIDGE, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec_with_env(BRIDGE, "DEL", &host, &container, "eth0", &config, &env).unwrap();
+  block_on(async {
+    let netlink = Netlink::open(&host.netns)?;
+    assert!(netlink.link(&host_name).await?.is_none());
+    // The bridge is shared with other containers.
+    assert!(netlink.link("cni-test0").await?.is_some());
+    assert!(Netlink::open(&container.netns)?
+      .link("eth0")
+      .await?
+      .is_none());
+    Ok(())
+  })
+  .unwrap();
+  assert!(iptables.rules().is_empty());
+  assert!(!data_dir.0.join("bridgenet").join("10.1.2.2").exists());
+  let error = exec(BRIDGE, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+
+  // Deleting again finds nothing left to delete.
+  exec_with_env(BRIDGE, "DEL", &host, &container, "eth0", &config, &env).unwrap();
+}
+
+#[test]
+fn vlan() {
+  let host = TestNs::create("bridge-vlan-host");
+  let container = TestNs::create("bridge-vlan-container");
+  let filtering = block_on(async {
+    Netlink::open(&host.netns)?
+      .ensure_bridge("cni-test0", None, true)
+      .await
+  });
+  if filtering.is_err() {
+    eprintln!("skipped: the kernel does not support VLAN filtering bridges");
+    return;
+  }
+  let config = json!({
+    "cniVersion": "1.1.0",
+    "name": "bridgenet",
+    "type": "bridge",
+    "bridge": "cni-test0",
+    "vlan": 100
+  });
+
+  let result = exec(BRIDGE, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(result["ips"], json!([]));
+  let host_name = result["interfaces"][1]["name"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let vlans = bridge(&host, &["vlan", "show", "dev", &host_name]);
+  assert_eq!(
+    vlans[0]["vlans"],
+    json!([{ "vlan": 100, "flags": ["PVID", "Egress Untagged"] }])
+  );
+  block_on(async {
+    let eth0 = Netlink::open(&container.netns)?
+      .link("eth0")
+      .await?
+      .unwrap();
+    assert!(eth0.up);
+    Ok(())
+  })
+  .unwrap();
+
+  exec(BRIDGE, "DEL", &host, &container, "eth0", &config).unwrap();
+  block_on(async {
+    assert!(Netlink::open(&host.netns)?
+      .link(&host_name)
+      .await?
+      .is_none());
+    Ok(())
+  })
+  .unwrap();
+}
@@ -0,0 +1,202 @@
+/*
+Helpers for running the plugin binaries against network namespaces created for each test.
+
+Plugins are run from a namespace of their own standing for the host, so that neither the links nor
+the sysctls they change on the host side leak out of the test.
+ */
+// Each test crate uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+use libcni::block_on;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use libcni::schema::reply::Route;
+use serde_json::Value;
+
+/// A namespace created for a single test, deleted once it is done.
+pub struct TestNs {
+  name:      String,
+  pub netns: NetNs,
+}
+
+impl TestNs {
+  pub fn create(test: &str) -> Self {
+    let name = format!("cni-plugins-{}-{}", test, std::process::id());
+    let netns = NetNs::create(&name).unwrap();
+    Self { name, netns }
+  }
+}
+
+impl Drop for TestNs {
+  fn drop(&mut self) {
+    let _ = NetNs::delete(&self.name);
+  }
+}
+
+/// Gives `host` a master link `veth0`, with the default route, whose peer is in `peer`.
+pub fn add_master(host: &TestNs, peer: &TestNs) {
+  block_on(async {
+    let netlink = Netlink::open(&host.netns)?;
+    let master = netlink
+      .add_veth("veth0", "eth0", &peer.netns, Some(1500))
+      .await?;
+    netlink
+      .add_address(master.index, "10.0.0.1/24".parse().unwrap())
+      .await?;
+    netlink
+      .add_route(
+        master.index,
+        &Route {
+          dst:      "0.0.0.0/0".parse().unwrap(),
+          gw:       Some("10.0.0.254".parse().unwrap()),
+          mtu:      None,
+          advmss:   None,
+          priority: None,
+        },
+      )
+      .await
+  })
+  .unwrap();
+}
+
+/// A directory for the state of a single test, removed once it is done.
+pub struct TestDir(pub PathBuf);
+
+impl TestDir {
+  pub fn create(test: &str) -> Self {
+    let dir = std::env::temp_dir().join(format!("cni-plugins-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Self(dir)
+  }
+}
+
+impl Drop for TestDir {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_dir_all(&self.0);
+  }
+}
+
+/// Runs `plugin` from `host` for the container in `container`, and returns its result, or its
+/// error object if it failed. Commands without a result return `null`.
+pub fn exec(
+  plugin: &str,
+  command: &str,
+  host: &TestNs,
+  container: &TestNs,
+  if_name: &str,
+  config: &Value,
+) -> Result<Value, Value> {
+  exec_with_env(plugin, command, host, container, if_name, config, &[])
+}
+
+/// As [`exec`], with the additional environment variables `env`.
+pub fn exec_with_env(
+  plugin: &str,
+  command: &str,
+  host: &TestNs,
+  container: &TestNs,
+  if_name: &str,
+  config: &Value,
+  env: &[(&str, &str)],
+) -> Result<Value, Value> {
+  let output = host
+    .netns
+    .run(|| {
+      let mut child = Command::new(plugin)
+        .envs(env.iter().copied())
+        .env("CNI_COMMAND", command)
+        .env("CNI_CONTAINERID", "test")
+        .env("CNI_NETNS", container.netns.path())
+        .env("CNI_IFNAME", if_name)
+        .env("CNI_PATH", Path::new(plugin).parent().unwrap())
+        .env_remove("CNI_ARGS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+      child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(config.to_string().as_bytes())
+        .unwrap();
+      child.wait_with_output().unwrap()
+    })
+    .unwrap();
+
+  let value = match output.stdout.is_empty() {
+    true => Value::Null,
+    false => serde_json::from_slice(&output.stdout).unwrap(),
+  };
+  match output.status.success() {
+    true => Ok(value),
+    false => Err(value),
+  }
+}
+
+/// A stand-in for `iptables`, which keeps the rules of its single table in a file rather than in
+/// the kernel: one line per rule, after the name of its chain, and `:<chain>` for chains.
+pub struct FakeIpTables {
+  dir: PathBuf,
+}
+
+impl FakeIpTables {
+  pub fn install(dir: &TestDir) -> Self {
+    let dir = dir.0.join("bin");
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = format!(
+      r#"#!/bin/sh
+rules="{}/rules"
+touch "$rules"
+[ "$1" = --wait ] && shift
+[ "$1" = -t ] && shift 2
+[ "$1" = -n ] && shift
+op=$1
+chain=$2
+shift 2
+rule="$chain $*"
+case "$op" in
+  -N) echo ":$chain" >> "$rules" ;;
+  -L) grep -qxF ":$chain" "$rules" ;;
+  -A) echo "$rule" >> "$rules" ;;
+  -C) grep -qxF "$rule" "$rules" ;;
+  -D) grep -qxF "$rule" "$rules" || exit 1
+      grep -vxF "$rule" "$rules" > "$rules.new"; mv "$rules.new" "$rules" ;;
+  -F) grep -qxF ":$chain" "$rules" || exit 1
+      grep -v "^$chain " "$rules" > "$rules.new"; mv "$rules.new" "$rules" ;;
+  -X) grep -vxF ":$chain" "$rules" > "$rules.new"; mv "$rules.new" "$rules" ;;
+  *) exit 2 ;;
+esac
+"#,
+      dir.display()
+    );
+    let path = dir.join("iptables");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    Self { dir }
+  }
+
+  /// `PATH`, with this `iptables` first.
+  pub fn path(&self) -> String {
+    format!(
+      "{}:{}",
+      self.dir.display(),
+      std::env::var("PATH").unwrap_or_default()
+    )
+  }
+
+  pub fn rules(&self) -> Vec<String> {
+    std::fs::read_to_string(self.dir.join("rules"))
+      .unwrap_or_default()
+      .lines()
+      .map(str::to_string)
+      .collect()
+  }
+}
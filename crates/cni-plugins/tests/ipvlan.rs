@@ -0,0 +1,121 @@
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::IpvlanMode;
+use libcni::link::Netlink;
+use serde_json::json;
+
+use crate::common::add_master;
+use crate::common::exec;
+use crate::common::TestDir;
+use crate::common::TestNs;
+
+mod common;
+
+const IPVLAN: &str = env!("CARGO_BIN_EXE_ipvlan");
+
+/// Whether the kernel of `host` supports ipvlan links, on its master `veth0`.
+fn supported(host: &TestNs) -> bool {
+  let supported = block_on(async {
+    let netlink = Netlink::open(&host.netns)?;
+    let master = netlink.link("veth0").await?.unwrap();
+    if netlink
+      .add_ipvlan(
+        "ipvlan-probe",
+        master.index,
+        IpvlanMode::L2,
+        None,
+        &host.netns,
+      )
+      .await
+      .is_err()
+    {
+      return Ok(false);
+    }
+    let probe = netlink.link("ipvlan-probe").await?.unwrap();
+    netlink.del_link(probe.index).await?;
+    Ok(true)
+  })
+  .unwrap();
+  if !supported {
+    eprintln!("skipped: the kernel does not support ipvlan links");
+  }
+  supported
+}
+
+fn add_check_del(test: &str, mode: &str) {
+  let host = TestNs::create(&format!("{}-host", test));
+  let peer = TestNs::create(&format!("{}-peer", test));
+  let container = TestNs::create(&format!("{}-container", test));
+  let data_dir = TestDir::create(test);
+  add_master(&host, &peer);
+  if !supported(&host) {
+    return;
+  }
+  let mut config = json!({
+    "cniVersion": "1.1.0",
+    "name": "ipvnet",
+    "type": "ipvlan",
+    "master": "veth0",
+    "mode": mode,
+    "mtu": 1400,
+    "ipam": {
+      "type": "host-local",
+      "subnet": "10.0.0.0/24",
+      "rangeStart": "10.0.0.10",
+      "dataDir": data_dir.0
+    }
+  });
+
+  let result = exec(IPVLAN, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(
+    result["ips"],
+    json!([{ "address": "10.0.0.10/24", "gateway": "10.0.0.1", "interface": 0 }])
+  );
+  let interfaces = result["interfaces"].as_array().unwrap();
+  assert_eq!(interfaces.len(), 1);
+  assert_eq!(interfaces[0]["name"], "eth0");
+  assert_eq!(interfaces[0]["sandbox"], json!(container.netns.path()));
+  block_on(async {
+    let master = Netlink::open(&host.netns)?.link("veth0").await?.unwrap();
+    let netlink = Netlink::open(&container.netns)?;
+    let eth0 = netlink.link("eth0").await?.unwrap();
+    assert_eq!(eth0.kind, Some(InfoKind::IpVlan));
+    assert_eq!(eth0.mtu, Some(1400));
+    assert_eq!(eth0.parent, Some(master.index));
+    // ipvlan links share the MAC address of their master.
+    assert_eq!(eth0.mac.map(|mac| mac.0), master.mac.map(|mac| mac.0));
+    assert!(eth0.up);
+    assert_eq!(
+      netlink.addresses(eth0.index).await?,
+      vec!["10.0.0.10/24".parse().unwrap()]
+    );
+    Ok(())
+  })
+  .unwrap();
+
+  config["prevResult"] = result;
+  exec(IPVLAN, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec(IPVLAN, "DEL", &host, &container, "eth0", &config).unwrap();
+  block_on(async {
+    assert!(Netlink::open(&container.netns)?
+      .link("eth0")
+      .await?
+      .is_none());
+    Ok(())
+  })
+  .unwrap();
+  assert!(!data_dir.0.join("ipvnet").join("10.0.0.10").exists());
+  let error = exec(IPVLAN, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+}
+
+#[test]
+fn l2() {
+  add_check_del("ipvlan-l2", "l2");
+}
+
+#[test]
+fn l3() {
+  add_check_del("ipvlan-l3", "l3");
+}
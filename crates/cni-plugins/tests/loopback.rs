@@ -0,0 +1,89 @@
+use libcni::block_on;
+use libcni::link::Netlink;
+use libcni::netns::NetNs;
+use serde_json::json;
+
+use crate::common::exec;
+use crate::common::TestNs;
+
+mod common;
+
+const LOOPBACK: &str = env!("CARGO_BIN_EXE_loopback");
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("loopback-host");
+  let container = TestNs::create("loopback-container");
+  let config = json!({ "cniVersion": "1.1.0", "name": "lo", "type": "loopback" });
+  let is_up = || {
+    block_on(async {
+      let link = Netlink::open(&container.netns)?.link("lo").await?.unwrap();
+      Ok(link.up)
+    })
+    .unwrap()
+  };
+  assert!(!is_up());
+
+  let result = exec(LOOPBACK, "ADD", &host, &container, "lo", &config).unwrap();
+  assert_eq!(result["cniVersion"], "1.1.0");
+  assert_eq!(
+    result["interfaces"],
+    json!([{ "name": "lo", "mac": "00:00:00:00:00:00", "sandbox": container.netns.path() }])
+  );
+  assert!(result["ips"]
+    .as_array()
+    .unwrap()
+    .contains(&json!({ "address": "127.0.0.1/8", "interface": 0 })));
+  assert!(is_up());
+
+  exec(LOOPBACK, "CHECK", &host, &container, "lo", &config).unwrap();
+
+  exec(LOOPBACK, "DEL", &host, &container, "lo", &config).unwrap();
+  assert!(!is_up());
+  let error = exec(LOOPBACK, "CHECK", &host, &container, "lo", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+  assert_eq!(error["msg"], "loopback interface lo is down");
+}
+
+#[test]
+fn prev_result() {
+  let host = TestNs::create("loopback-prev-host");
+  let container = TestNs::create("loopback-prev-container");
+  let prev_result = json!({
+    "cniVersion": "1.1.0",
+    "interfaces": [{ "name": "eth0" }],
+    "ips": [{ "address": "10.1.0.5/16", "interface": 0 }],
+    "routes": []
+  });
+  let config = json!({
+    "cniVersion": "1.1.0",
+    "name": "lo",
+    "type": "loopback",
+    "prevResult": prev_result
+  });
+
+  let result = exec(LOOPBACK, "ADD", &host, &container, "lo", &config).unwrap();
+  assert_eq!(result, prev_result);
+}
+
+/// Only `DEL` tolerates the container's namespace being gone.
+#[test]
+fn missing_netns() {
+  let host = TestNs::create("loopback-missing-host");
+  let container = TestNs::create("loopback-missing-container");
+  let name = container
+    .netns
+    .path()
+    .file_name()
+    .unwrap()
+    .to_str()
+    .unwrap();
+  NetNs::delete(name).unwrap();
+  let config = json!({ "cniVersion": "1.1.0", "name": "lo", "type": "loopback" });
+
+  let error = exec(LOOPBACK, "ADD", &host, &container, "lo", &config).unwrap_err();
+  assert_eq!(error["code"], 4);
+  let error = exec(LOOPBACK, "CHECK", &host, &container, "lo", &config).unwrap_err();
+  assert_eq!(error["code"], 4);
+  exec(LOOPBACK, "DEL", &host, &container, "lo", &config).unwrap();
+}
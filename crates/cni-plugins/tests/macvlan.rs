@@ -0,0 +1,104 @@
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::Netlink;
+use serde_json::json;
+
+use crate::common::add_master;
+use crate::common::exec;
+use crate::common::TestDir;
+use crate::common::TestNs;
+
+mod common;
+
+const MACVLAN: &str = env!("CARGO_BIN_EXE_macvlan");
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("macvlan-host");
+  let peer = TestNs::create("macvlan-peer");
+  let container = TestNs::create("macvlan-container");
+  let data_dir = TestDir::create("macvlan");
+  add_master(&host, &peer);
+  let mut config = json!({
+    "cniVersion": "1.1.0",
+    "name": "macnet",
+    "type": "macvlan",
+    "mode": "bridge",
+    "mtu": 1400,
+    "capabilities": { "mac": true },
+    "runtimeConfig": { "mac": "02:00:00:00:00:01" },
+    "ipam": {
+      "type": "host-local",
+      "subnet": "10.0.0.0/24",
+      "rangeStart": "10.0.0.10",
+      "dataDir": data_dir.0
+    }
+  });
+
+  let result = exec(MACVLAN, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(
+    result["interfaces"],
+    json!([{
+      "name": "eth0",
+      "mac": "02:00:00:00:00:01",
+      "sandbox": container.netns.path()
+    }])
+  );
+  assert_eq!(
+    result["ips"],
+    json!([{ "address": "10.0.0.10/24", "gateway": "10.0.0.1", "interface": 0 }])
+  );
+  block_on(async {
+    let master = Netlink::open(&host.netns)?.link("veth0").await?.unwrap();
+    let netlink = Netlink::open(&container.netns)?;
+    let eth0 = netlink.link("eth0").await?.unwrap();
+    assert_eq!(eth0.kind, Some(InfoKind::MacVlan));
+    assert_eq!(eth0.mtu, Some(1400));
+    assert_eq!(eth0.parent, Some(master.index));
+    assert!(eth0.up);
+    assert_eq!(
+      netlink.addresses(eth0.index).await?,
+      vec!["10.0.0.10/24".parse().unwrap()]
+    );
+    Ok(())
+  })
+  .unwrap();
+
+  config["prevResult"] = result;
+  exec(MACVLAN, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec(MACVLAN, "DEL", &host, &container, "eth0", &config).unwrap();
+  block_on(async {
+    assert!(Netlink::open(&container.netns)?
+      .link("eth0")
+      .await?
+      .is_none());
+    Ok(())
+  })
+  .unwrap();
+  assert!(!data_dir.0.join("macnet").join("10.0.0.10").exists());
+  let error = exec(MACVLAN, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+}
+
+#[test]
+fn mtu_above_master() {
+  let host = TestNs::create("macvlan-mtu-host");
+  let peer = TestNs::create("macvlan-mtu-peer");
+  let container = TestNs::create("macvlan-mtu-container");
+  add_master(&host, &peer);
+  let config = json!({
+    "cniVersion": "1.1.0",
+    "name": "macnet",
+    "type": "macvlan",
+    "master": "veth0",
+    "mtu": 9000
+  });
+
+  let error = exec(MACVLAN, "ADD", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 7);
+  assert_eq!(
+    error["details"],
+    "invalid MTU 9000, must be at most the MTU of master veth0 (1500)"
+  );
+}
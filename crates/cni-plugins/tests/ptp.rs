@@ -0,0 +1,116 @@
+use libcni::block_on;
+use libcni::link::InfoKind;
+use libcni::link::Netlink;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::common::exec;
+use crate::common::TestDir;
+use crate::common::TestNs;
+
+mod common;
+
+const PTP: &str = env!("CARGO_BIN_EXE_ptp");
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("ptp-host");
+  let container = TestNs::create("ptp-container");
+  let data_dir = TestDir::create("ptp");
+  let mut config = json!({
+    "cniVersion": "1.1.0",
+    "name": "ptpnet",
+    "type": "ptp",
+    "mtu": 1400,
+    "ipam": {
+      "type": "host-local",
+      "subnet": "10.1.1.0/24",
+      "routes": [{ "dst": "0.0.0.0/0" }],
+      "dataDir": data_dir.0
+    }
+  });
+
+  let result = exec(PTP, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(
+    result["ips"],
+    json!([{ "address": "10.1.1.2/24", "gateway": "10.1.1.1", "interface": 1 }])
+  );
+  let interfaces = result["interfaces"].as_array().unwrap();
+  assert_eq!(interfaces.len(), 2);
+  assert_eq!(interfaces[0]["sandbox"], Value::Null);
+  assert_eq!(interfaces[1]["name"], "eth0");
+  assert_eq!(interfaces[1]["sandbox"], json!(container.netns.path()));
+  let host_name = interfaces[0]["name"].as_str().unwrap().to_string();
+
+  block_on(async {
+    let netlink = Netlink::open(&host.netns)?;
+    let veth = netlink.link(&host_name).await?.unwrap();
+    assert_eq!(veth.kind, Some(InfoKind::Veth));
+    assert_eq!(veth.mtu, Some(1400));
+    assert!(veth.up);
+    assert_eq!(
+      netlink.addresses(veth.index).await?,
+      vec!["10.1.1.1/32".parse().unwrap()]
+    );
+    assert_eq!(
+      serde_json::to_value(netlink.routes(veth.index).await?).unwrap(),
+      json!([{ "dst": "10.1.1.2/32" }])
+    );
+
+    let netlink = Netlink::open(&container.netns)?;
+    let eth0 = netlink.link("eth0").await?.unwrap();
+    assert_eq!(eth0.kind, Some(InfoKind::Veth));
+    assert_eq!(eth0.mtu, Some(1400));
+    assert_eq!(eth0.parent, Some(veth.index));
+    assert!(eth0.up);
+    assert_eq!(
+      netlink.addresses(eth0.index).await?,
+      vec!["10.1.1.2/24".parse().unwrap()]
+    );
+    let mut routes = serde_json::to_value(netlink.routes(eth0.index).await?).unwrap();
+    routes
+      .as_array_mut()
+      .unwrap()
+      .sort_by_key(|route| route["dst"].to_string());
+    assert_eq!(
+      routes,
+      json!([
+        { "dst": "0.0.0.0/0", "gw": "10.1.1.1" },
+        { "dst": "10.1.1.0/24", "gw": "10.1.1.1" },
+        { "dst": "10.1.1.1/32" }
+      ])
+    );
+    Ok(())
+  })
+  .unwrap();
+
+  let proxy_arp = host
+    .netns
+    .run(|| std::fs::read_to_string(format!("/proc/sys/net/ipv4/conf/{}/proxy_arp", host_name)))
+    .unwrap()
+    .unwrap();
+  assert_eq!(proxy_arp.trim(), "1");
+
+  config["prevResult"] = result;
+  exec(PTP, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec(PTP, "DEL", &host, &container, "eth0", &config).unwrap();
+  block_on(async {
+    assert!(Netlink::open(&host.netns)?
+      .link(&host_name)
+      .await?
+      .is_none());
+    assert!(Netlink::open(&container.netns)?
+      .link("eth0")
+      .await?
+      .is_none());
+    Ok(())
+  })
+  .unwrap();
+  assert!(!data_dir.0.join("ptpnet").join("10.1.1.2").exists());
+  let error = exec(PTP, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+
+  // Deleting again finds nothing left to delete.
+  exec(PTP, "DEL", &host, &container, "eth0", &config).unwrap();
+}
@@ -0,0 +1,174 @@
+use libcni::block_on;
+use libcni::link::Link;
+use libcni::link::Netlink;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::common::exec;
+use crate::common::TestDir;
+use crate::common::TestNs;
+
+mod common;
+
+const TUNING: &str = env!("CARGO_BIN_EXE_tuning");
+
+/// Gives the container interface `if_name`, the end of a veth pair with the host, and returns the
+/// network configuration chaining tuning after it.
+fn setup(host: &TestNs, container: &TestNs, if_name: &str, data_dir: &TestDir) -> Value {
+  block_on(async {
+    Netlink::open(&container.netns)?
+      .add_veth(if_name, "veth-tuning", &host.netns, None)
+      .await?;
+    Ok(())
+  })
+  .unwrap();
+  json!({
+    "cniVersion": "1.1.0",
+    "name": "tuningnet",
+    "type": "tuning",
+    "dataDir": data_dir.0,
+    "prevResult": {
+      "cniVersion": "1.1.0",
+      "interfaces": [
+        { "name": "veth-tuning" },
+        { "name": if_name, "sandbox": container.netns.path() }
+      ],
+      "ips": []
+    }
+  })
+}
+
+fn link(container: &TestNs, if_name: &str) -> Link {
+  block_on(async {
+    Ok(
+      Netlink::open(&container.netns)?
+        .link(if_name)
+        .await?
+        .unwrap(),
+    )
+  })
+  .unwrap()
+}
+
+fn mac(link: &Link) -> String {
+  link.mac.as_ref().unwrap().0.to_string().to_lowercase()
+}
+
+/// Reads sysctl `name`, with slashes, in `netns`.
+fn sysctl(netns: &TestNs, name: &str) -> String {
+  netns
+    .netns
+    .run(|| std::fs::read_to_string(format!("/proc/sys/{}", name)))
+    .unwrap()
+    .unwrap()
+    .trim()
+    .to_string()
+}
+
+#[test]
+fn add_check_del() {
+  let host = TestNs::create("tuning-host");
+  let container = TestNs::create("tuning-container");
+  let data_dir = TestDir::create("tuning");
+  let mut config = setup(&host, &container, "eth0", &data_dir);
+  config["sysctl"] = json!({
+    "net.ipv4.conf.IFNAME.arp_filter": "1",
+    "net.ipv4.ip_forward": "1"
+  });
+  config["mac"] = json!("02:11:22:33:44:55");
+  config["mtu"] = json!(1400);
+  config["promisc"] = json!(true);
+  config["allmulti"] = json!(true);
+  let original = link(&container, "eth0");
+  assert!(!original.promisc);
+
+  let result = exec(TUNING, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(
+    result["interfaces"][1]["mac"]
+      .as_str()
+      .unwrap()
+      .to_lowercase(),
+    "02:11:22:33:44:55"
+  );
+  assert_eq!(sysctl(&container, "net/ipv4/conf/eth0/arp_filter"), "1");
+  assert_eq!(sysctl(&container, "net/ipv4/ip_forward"), "1");
+  let eth0 = link(&container, "eth0");
+  assert_eq!(mac(&eth0), "02:11:22:33:44:55");
+  assert_eq!(eth0.mtu, Some(1400));
+  assert!(eth0.promisc);
+  assert!(eth0.allmulti);
+
+  let backup = data_dir.0.join("test-eth0.json");
+  let saved = serde_json::from_slice::<Value>(&std::fs::read(&backup).unwrap()).unwrap();
+  assert_eq!(
+    saved["mac"].as_str().unwrap().to_lowercase(),
+    mac(&original)
+  );
+  assert_eq!(saved["mtu"], json!(original.mtu));
+  assert_eq!(saved["promisc"], false);
+  assert_eq!(saved["allmulti"], json!(original.allmulti));
+
+  config["prevResult"] = result;
+  exec(TUNING, "CHECK", &host, &container, "eth0", &config).unwrap();
+
+  exec(TUNING, "DEL", &host, &container, "eth0", &config).unwrap();
+  let eth0 = link(&container, "eth0");
+  assert_eq!(mac(&eth0), mac(&original));
+  assert_eq!(eth0.mtu, original.mtu);
+  assert!(!eth0.promisc);
+  assert_eq!(eth0.allmulti, original.allmulti);
+  assert!(!backup.exists());
+  let error = exec(TUNING, "CHECK", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 999);
+
+  // Deleting again finds nothing left to restore.
+  exec(TUNING, "DEL", &host, &container, "eth0", &config).unwrap();
+}
+
+/// The backup of the first `ADD` is kept by later ones.
+#[test]
+fn repeated_add() {
+  let host = TestNs::create("tuning-repeated-host");
+  let container = TestNs::create("tuning-repeated-container");
+  let data_dir = TestDir::create("tuning-repeated");
+  let mut config = setup(&host, &container, "eth0", &data_dir);
+  let original = link(&container, "eth0");
+
+  config["mtu"] = json!(1400);
+  exec(TUNING, "ADD", &host, &container, "eth0", &config).unwrap();
+  config["mtu"] = json!(1300);
+  exec(TUNING, "ADD", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(link(&container, "eth0").mtu, Some(1300));
+
+  exec(TUNING, "DEL", &host, &container, "eth0", &config).unwrap();
+  assert_eq!(link(&container, "eth0").mtu, original.mtu);
+}
+
+/// Sysctls of interfaces with dots in their names, like those of VLANs.
+#[test]
+fn dotted_interface() {
+  let host = TestNs::create("tuning-dotted-host");
+  let container = TestNs::create("tuning-dotted-container");
+  let data_dir = TestDir::create("tuning-dotted");
+  let mut config = setup(&host, &container, "eth0.100", &data_dir);
+  config["sysctl"] = json!({ "net.ipv4.conf.IFNAME.arp_filter": "1" });
+
+  let result = exec(TUNING, "ADD", &host, &container, "eth0.100", &config).unwrap();
+  assert_eq!(sysctl(&container, "net/ipv4/conf/eth0.100/arp_filter"), "1");
+  config["prevResult"] = result;
+  exec(TUNING, "CHECK", &host, &container, "eth0.100", &config).unwrap();
+  exec(TUNING, "DEL", &host, &container, "eth0.100", &config).unwrap();
+}
+
+#[test]
+fn invalid_sysctl() {
+  let host = TestNs::create("tuning-invalid-host");
+  let container = TestNs::create("tuning-invalid-container");
+  let data_dir = TestDir::create("tuning-invalid");
+  let mut config = setup(&host, &container, "eth0", &data_dir);
+  config["sysctl"] = json!({ "kernel.panic": "1" });
+
+  let error = exec(TUNING, "ADD", &host, &container, "eth0", &config).unwrap_err();
+  assert_eq!(error["code"], 7);
+  assert!(!data_dir.0.join("test-eth0.json").exists());
+}
@@ -0,0 +1,384 @@
+/*
+Cache of `ADD` results, which runtimes pass as `prevResult` to `CHECK` and `DEL`. Entries are kept
+in the layout of the reference implementation, so that runtimes built on either can share them.
+
+[Caching](https://github.com/containernetworking/cni/blob/main/SPEC.md#section-3-execution-of-network-configurations)
+ */
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::Value;
+
+use crate::schema::args::CniArgs;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::legacy::VersionedAddReply;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::ReplyPayload;
+use crate::schema::runtime::RuntimeConfig;
+use crate::schema::ContainerID;
+use crate::schema::IfName;
+use crate::schema::NetNS;
+
+/// Format of the cache entries.
+const CACHE_KIND: &str = "cniCacheV1";
+
+/// An attachment recorded in the result cache, with what is needed to remove it again.
+#[derive(Clone, Debug)]
+pub struct NetworkAttachment {
+  pub container_id:    ContainerID,
+  pub network:         String,
+  pub if_name:         IfName,
+  pub netns:           NetNS,
+  pub args:            CniArgs,
+  pub capability_args: HashMap<String, Value>,
+  /// The network configuration the attachment was added with.
+  pub config:          Vec<u8>,
+}
+
+impl NetworkAttachment {
+  /// The runtime configuration the attachment was added with.
+  pub fn runtime_config(&self) -> RuntimeConfig {
+    RuntimeConfig {
+      container_id:    self.container_id.clone(),
+      netns:           self.netns.clone(),
+      if_name:         self.if_name.clone(),
+      args:            self.args.clone(),
+      capability_args: self.capability_args.clone(),
+    }
+  }
+}
+
+/// Results cached in `<cache dir>/results/<network>-<container ID>-<interface>`.
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+  dir: PathBuf,
+}
+
+impl ResultCache {
+  pub fn new<P>(cache_dir: P) -> Self
+  where
+    P: Into<PathBuf>,
+  {
+    Self {
+      dir: cache_dir.into().join("results"),
+    }
+  }
+
+  /// Records `result` of adding the attachment described by `rt` to network `network`.
+  pub fn save(
+    &self,
+    network: &str,
+    config: &[u8],
+    rt: &RuntimeConfig,
+    result: &AddReply,
+  ) -> CniResult<()> {
+    let entry = CachedInfo {
+      kind:            CACHE_KIND.to_string(),
+      container_id:    rt.container_id.clone(),
+      config:          config.to_vec(),
+      if_name:         rt.if_name.clone(),
+      network_name:    network.to_string(),
+      netns:           rt.netns.clone(),
+      cni_args:        rt
+        .args
+        .iter()
+        .map(|(key, value)| [key.to_string(), value.to_string()])
+        .collect(),
+      capability_args: rt.capability_args.clone(),
+      result:          Some(result.to_value()?),
+    };
+    let bytes = serde_json::to_vec(&entry)
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?;
+
+    std::fs::create_dir_all(&self.dir).map_err(CniErrorCode::IOFailure)?;
+    std::fs::write(self.file(network, rt), bytes).map_err(CniErrorCode::IOFailure)
+  }
+
+  /// The cached result of the attachment, if any.
+  pub fn result(&self, network: &str, rt: &RuntimeConfig) -> CniResult<Option<AddReply>> {
+    let bytes = match std::fs::read(self.file(network, rt)) {
+      Ok(bytes) => bytes,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(CniErrorCode::IOFailure(e)),
+    };
+
+    decode(&bytes)?
+      .result
+      .map(VersionedAddReply::parse)
+      .transpose()
+      .map(|result| result.map(AddReply::from))
+  }
+
+  /// Forgets the attachment, which is not an error if it was never cached.
+  pub fn delete(&self, network: &str, rt: &RuntimeConfig) -> CniResult<()> {
+    match std::fs::remove_file(self.file(network, rt)) {
+      Err(e) if e.kind() != ErrorKind::NotFound => Err(CniErrorCode::IOFailure(e)),
+      _ => Ok(()),
+    }
+  }
+
+  /// Every cached attachment, or only those of container `container_id`.
+  pub fn attachments(&self, container_id: Option<&str>) -> CniResult<Vec<NetworkAttachment>> {
+    let entries = match std::fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(CniErrorCode::IOFailure(e)),
+    };
+
+    let mut attachments = Vec::new();
+    for entry in entries {
+      let path = entry.map_err(CniErrorCode::IOFailure)?.path();
+      // Entries of other formats, or written concurrently, are skipped.
+      let Some(entry) = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| decode(&bytes).ok())
+      else {
+        continue;
+      };
+      if entry.kind != CACHE_KIND
+        || container_id.is_some_and(|container_id| container_id != entry.container_id)
+      {
+        continue;
+      }
+
+      attachments.push(NetworkAttachment {
+        container_id:    entry.container_id,
+        network:         entry.network_name,
+        if_name:         entry.if_name,
+        netns:           entry.netns,
+        args:            entry
+          .cni_args
+          .into_iter()
+          .map(|[key, value]| (key, value))
+          .collect(),
+        capability_args: entry.capability_args,
+        config:          entry.config,
+      });
+    }
+    Ok(attachments)
+  }
+
+  fn file(&self, network: &str, rt: &RuntimeConfig) -> PathBuf {
+    self
+      .dir
+      .join(format!("{}-{}-{}", network, rt.container_id, rt.if_name))
+  }
+}
+
+/// A cache entry as written by the reference implementation.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedInfo {
+  kind:            String,
+  container_id:    ContainerID,
+  /// The raw network configuration, encoded as base64.
+  #[serde(
+    deserialize_with = "deserialize_base64",
+    serialize_with = "serialize_base64"
+  )]
+  config:          Vec<u8>,
+  if_name:         IfName,
+  network_name:    String,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "String::is_empty")]
+  netns:           NetNS,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  cni_args:        Vec<[String; 2]>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "HashMap::is_empty")]
+  capability_args: HashMap<String, Value>,
+  /// The result, in the format of its `cniVersion`.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  result:          Option<Value>,
+}
+
+fn decode(bytes: &[u8]) -> CniResult<CachedInfo> {
+  serde_json::from_slice(bytes)
+    .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+}
+
+fn serialize_base64<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+  S: Serializer,
+{
+  serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+fn deserialize_base64<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  use serde::de::Error;
+  STANDARD
+    .decode(String::deserialize(deserializer)?)
+    .map_err(Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  const CONFIG: &str = r#"{"cniVersion":"1.0.0","name":"dbnet","plugins":[{"type":"bridge"}]}"#;
+
+  /// An entry as written by the reference implementation for `CONFIG`, the runtime configuration of
+  /// [`runtime_config`], and a `1.0.0` result.
+  const REFERENCE_ENTRY: &str = r#"{"kind":"cniCacheV1","containerId":"ctr1","config":"eyJjbmlWZXJzaW9uIjoiMS4wLjAiLCJuYW1lIjoiZGJuZXQiLCJwbHVnaW5zIjpbeyJ0eXBlIjoiYnJpZGdlIn1dfQ==","ifName":"eth0","networkName":"dbnet","netns":"/var/run/netns/blue","cniArgs":[["K8S_POD_NAME","web"]],"capabilityArgs":{"portMappings":[{"containerPort":80,"hostPort":8080,"protocol":"tcp"}]},"result":{"cniVersion":"1.0.0","dns":{"nameservers":["10.1.0.1"]},"interfaces":[{"mac":"00:11:22:33:44:55","name":"eth0","sandbox":"/var/run/netns/blue"}],"ips":[{"address":"10.1.0.5/16","gateway":"10.1.0.1","interface":0}],"routes":[{"dst":"0.0.0.0/0"}]}}"#;
+
+  /// A cache in a directory of its own, removed once the test is done.
+  struct TestCache {
+    dir:   PathBuf,
+    cache: ResultCache,
+  }
+
+  impl TestCache {
+    fn new(test: &str) -> Self {
+      let dir = std::env::temp_dir().join(format!("libcni-cache-{}-{}", test, std::process::id()));
+      let cache = ResultCache::new(&dir);
+      Self { dir, cache }
+    }
+
+    fn write(&self, name: &str, contents: &str) {
+      std::fs::create_dir_all(self.dir.join("results")).unwrap();
+      std::fs::write(self.dir.join("results").join(name), contents).unwrap();
+    }
+
+    fn read(&self, name: &str) -> String {
+      std::fs::read_to_string(self.dir.join("results").join(name)).unwrap()
+    }
+  }
+
+  impl Drop for TestCache {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.dir);
+    }
+  }
+
+  fn runtime_config(container_id: &str) -> RuntimeConfig {
+    RuntimeConfig {
+      container_id:    container_id.to_string(),
+      netns:           "/var/run/netns/blue".to_string(),
+      if_name:         "eth0".to_string(),
+      args:            "K8S_POD_NAME=web".parse().unwrap(),
+      capability_args: serde_json::from_value(json!({
+        "portMappings": [{ "hostPort": 8080, "containerPort": 80, "protocol": "tcp" }]
+      }))
+      .unwrap(),
+    }
+  }
+
+  fn reference_result() -> AddReply {
+    let entry = serde_json::from_str::<Value>(REFERENCE_ENTRY).unwrap();
+    serde_json::from_value(entry["result"].clone()).unwrap()
+  }
+
+  #[test]
+  fn read_reference_entry() {
+    let cache = TestCache::new("read");
+    cache.write("dbnet-ctr1-eth0", REFERENCE_ENTRY);
+
+    let result = cache
+      .cache
+      .result("dbnet", &runtime_config("ctr1"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(result.cni_version, semver::Version::new(1, 0, 0));
+    assert_eq!(result.ips[0].address, "10.1.0.5/16");
+    assert_eq!(result.interfaces[0].name, "eth0");
+    assert_eq!(result.dns.nameservers, ["10.1.0.1"]);
+
+    let attachments = cache.cache.attachments(None).unwrap();
+    assert_eq!(attachments.len(), 1);
+    let attachment = &attachments[0];
+    assert_eq!(attachment.container_id, "ctr1");
+    assert_eq!(attachment.network, "dbnet");
+    assert_eq!(attachment.if_name, "eth0");
+    assert_eq!(attachment.netns, "/var/run/netns/blue");
+    assert_eq!(attachment.args.get("K8S_POD_NAME"), Some("web"));
+    assert_eq!(
+      attachment.capability_args["portMappings"][0]["hostPort"],
+      8080
+    );
+    assert_eq!(attachment.config, CONFIG.as_bytes());
+  }
+
+  /// Entries are written byte for byte as the reference implementation does.
+  #[test]
+  fn save_as_reference() {
+    let cache = TestCache::new("save");
+    cache
+      .cache
+      .save(
+        "dbnet",
+        CONFIG.as_bytes(),
+        &runtime_config("ctr1"),
+        &reference_result(),
+      )
+      .unwrap();
+    assert_eq!(cache.read("dbnet-ctr1-eth0"), REFERENCE_ENTRY);
+  }
+
+  #[test]
+  fn delete() {
+    let cache = TestCache::new("delete");
+    let rt = runtime_config("ctr1");
+    cache.write("dbnet-ctr1-eth0", REFERENCE_ENTRY);
+
+    cache.cache.delete("dbnet", &rt).unwrap();
+    assert!(cache.cache.result("dbnet", &rt).unwrap().is_none());
+    assert!(cache.cache.attachments(None).unwrap().is_empty());
+    // Forgetting an attachment twice is fine.
+    cache.cache.delete("dbnet", &rt).unwrap();
+  }
+
+  #[test]
+  fn attachments() {
+    let cache = TestCache::new("attachments");
+    assert!(cache.cache.attachments(None).unwrap().is_empty());
+
+    cache.write("dbnet-ctr1-eth0", REFERENCE_ENTRY);
+    cache
+      .cache
+      .save(
+        "dbnet",
+        CONFIG.as_bytes(),
+        &runtime_config("ctr2"),
+        &reference_result(),
+      )
+      .unwrap();
+    cache.write("garbage", "not json");
+    cache.write(
+      "other-kind",
+      &REFERENCE_ENTRY.replace("cniCacheV1", "cniCacheV2"),
+    );
+
+    let mut containers = cache
+      .cache
+      .attachments(None)
+      .unwrap()
+      .into_iter()
+      .map(|attachment| attachment.container_id)
+      .collect::<Vec<_>>();
+    containers.sort();
+    assert_eq!(containers, ["ctr1", "ctr2"]);
+
+    let attachments = cache.cache.attachments(Some("ctr2")).unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].container_id, "ctr2");
+    assert_eq!(
+      attachments[0].runtime_config().args.get("K8S_POD_NAME"),
+      Some("web")
+    );
+  }
+}
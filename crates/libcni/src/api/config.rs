@@ -1,10 +1,88 @@
 use std::path::Path;
+use std::path::PathBuf;
 
+use crate::schema::config::NetworkConfig;
+use crate::schema::config::NetworkConfigList;
+use crate::schema::error::CniErrorCode;
 use crate::schema::error::CniResult;
 
-pub fn load_config<P>(path: P, executable: &str) -> CniResult<()>
+/// Extensions of single plugin configuration files.
+pub const CONFIG_EXTENSIONS: [&str; 2] = ["conf", "json"];
+/// Extensions of configuration list files.
+pub const CONFIG_LIST_EXTENSIONS: [&str; 1] = ["conflist"];
+
+/// Loads the network configuration list named `name` from the directory `path`.
+///
+/// Configuration lists take precedence over single plugin configurations, which are wrapped into
+/// a list of their own. Files are searched in lexical order, the first match wins.
+pub fn load_config<P>(path: P, name: &str) -> CniResult<NetworkConfigList>
+where
+  P: AsRef<Path>,
+{
+  let path = path.as_ref();
+
+  for file in config_files(path, &CONFIG_LIST_EXTENSIONS)? {
+    let list = config_list_from_file(&file)?;
+    if list.name == name {
+      return Ok(list);
+    }
+  }
+
+  for file in config_files(path, &CONFIG_EXTENSIONS)? {
+    let config = config_from_file(&file)?;
+    if config.name == name {
+      return Ok(NetworkConfigList::from(config));
+    }
+  }
+
+  Err(CniErrorCode::IOFailure(std::io::Error::new(
+    std::io::ErrorKind::NotFound,
+    format!(
+      "no net configuration with name \"{}\" in {}",
+      name,
+      path.display()
+    ),
+  )))
+}
+
+/// Lists the files in directory `path` with any of `extensions`, in lexical order.
+pub fn config_files<P>(path: P, extensions: &[&str]) -> CniResult<Vec<PathBuf>>
+where
+  P: AsRef<Path>,
+{
+  let mut files = std::fs::read_dir(path)
+    .map_err(CniErrorCode::IOFailure)?
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|file| file.is_file())
+    .filter(|file| {
+      file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension))
+    })
+    .collect::<Vec<_>>();
+  files.sort();
+  Ok(files)
+}
+
+/// Reads a single plugin configuration from a `.conf` or `.json` file.
+pub fn config_from_file<P>(path: P) -> CniResult<NetworkConfig>
+where
+  P: AsRef<Path>,
+{
+  let path = path.as_ref();
+  let bytes = std::fs::read(path).map_err(CniErrorCode::IOFailure)?;
+  serde_json::from_slice::<NetworkConfig>(&bytes)
+    .map_err(|e| CniErrorCode::DecodeContentFailure(format!("{}: {}", path.display(), e).into()))
+}
+
+/// Reads a network configuration list from a `.conflist` file.
+pub fn config_list_from_file<P>(path: P) -> CniResult<NetworkConfigList>
 where
   P: AsRef<Path>,
 {
-  Ok(())
+  let path = path.as_ref();
+  let bytes = std::fs::read(path).map_err(CniErrorCode::IOFailure)?;
+  serde_json::from_slice::<NetworkConfigList>(&bytes)
+    .map_err(|e| CniErrorCode::DecodeContentFailure(format!("{}: {}", path.display(), e).into()))
 }
@@ -0,0 +1,105 @@
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+use crate::schema::error::CniError;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+
+/// Executes plugin binaries on behalf of the runtime.
+pub trait Invoker {
+  /// Runs the plugin at `plugin` with `environ` added to the environment and `stdin` written to
+  /// its standard input, returning its standard output on success.
+  fn exec_plugin(
+    &self,
+    plugin: &Path,
+    stdin: &[u8],
+    environ: &[(&'static str, String)],
+  ) -> CniResult<Vec<u8>>;
+}
+
+/// Invoker spawning the plugin as a child process, which inherits the environment of the
+/// runtime.
+#[derive(Clone, Debug, Default)]
+pub struct RawExec;
+
+impl Invoker for RawExec {
+  fn exec_plugin(
+    &self,
+    plugin: &Path,
+    stdin: &[u8],
+    environ: &[(&'static str, String)],
+  ) -> CniResult<Vec<u8>> {
+    let mut child = Command::new(plugin)
+      .envs(environ.iter().map(|(key, value)| (key, value)))
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(CniErrorCode::IOFailure)?;
+
+    // Plugins may exit without reading their input, like on an invalid environment, and still
+    // answer with an error object. Writing concurrently keeps a full stdout from blocking either.
+    let input = child.stdin.take();
+    let output = std::thread::scope(|scope| {
+      let writer = scope.spawn(|| match input {
+        Some(mut input) => match input.write_all(stdin) {
+          Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+          result => result,
+        },
+        None => Ok(()),
+      });
+      let output = child.wait_with_output();
+      writer
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+      output
+    })
+    .map_err(CniErrorCode::IOFailure)?;
+    if output.status.success() {
+      return Ok(output.stdout);
+    }
+
+    // Plugins report failures as an error object on stdout, falling back to the exit code.
+    match serde_json::from_slice::<CniError>(&output.stdout) {
+      Ok(error) => Err(CniErrorCode::Plugin(error)),
+      Err(_) => Err(CniErrorCode::Other {
+        code:    output.status.code().unwrap_or_default() as usize,
+        message: format!("plugin {} failed", plugin.display()).into(),
+        details: String::from_utf8_lossy(&output.stderr).into_owned().into(),
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::PermissionsExt;
+
+  use super::*;
+
+  /// A plugin failing with an error object before reading any of its input.
+  #[test]
+  fn plugin_ignoring_stdin() {
+    let plugin = std::env::temp_dir().join(format!("libcni-invoke-{}", std::process::id()));
+    std::fs::write(
+      &plugin,
+      "#!/bin/sh\necho '{\"cniVersion\": \"1.1.0\", \"code\": 4, \"msg\": \"bad env\"}'\nexit 1\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Larger than a pipe buffer, so that writing it cannot complete.
+    let stdin = vec![b' '; 1 << 20];
+    let error = RawExec.exec_plugin(&plugin, &stdin, &[]).unwrap_err();
+    std::fs::remove_file(&plugin).unwrap();
+
+    let CniErrorCode::Plugin(error) = error else {
+      panic!("unexpected error {:?}", error);
+    };
+    assert_eq!(error.code, 4);
+    assert_eq!(error.message, "bad env");
+  }
+}
@@ -1,87 +1,149 @@
+pub mod cache;
 pub mod config;
+pub mod invoke;
+pub mod runtime;
 
 use std::env::VarError;
+use std::future::Future;
 use std::io::Read;
 use std::str::FromStr;
 
+use async_trait::async_trait;
+
 use crate::schema::args::CniAddContext;
 use crate::schema::args::CniArgs;
 use crate::schema::args::CniCheckContext;
 use crate::schema::args::CniDelContext;
 use crate::schema::args::CniGcContext;
+use crate::schema::args::CniStatusContext;
+use crate::schema::args::GcAttachment;
 use crate::schema::args::CNI_ARGS;
 use crate::schema::args::CNI_COMMAND;
 use crate::schema::args::CNI_CONTAINERID;
 use crate::schema::args::CNI_IFNAME;
-use crate::schema::args::CNI_NSNAME;
+use crate::schema::args::CNI_NETNS;
 use crate::schema::args::CNI_PATH;
-use crate::schema::config::CniNetworkConfig;
+use crate::schema::config::NetworkConfig;
+use crate::schema::config::VALID_ATTACHMENTS;
 use crate::schema::error::CniErrorCode;
 use crate::schema::error::CniResult;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::VersionReply;
 use crate::schema::CniCommand;
 use crate::schema::CniPath;
 use crate::schema::ContainerID;
+use crate::version::check_version;
+use crate::version::decode_config_version;
+use crate::version::PluginInfo;
 
+/// A plugin answering the operations of the protocol. `VERSION` is answered by the entrypoint
+/// from the [`PluginInfo`] of the plugin.
 pub trait CniPlugin {
-  fn add(&self, args: CniAddContext) -> CniResult<()>;
+  fn add(&self, args: CniAddContext) -> CniResult<AddReply>;
   fn del(&self, args: CniDelContext) -> CniResult<()>;
   fn check(&self, args: CniCheckContext) -> CniResult<()>;
   fn gc(&self, args: CniGcContext) -> CniResult<()>;
-  fn status(&self) -> CniResult<()>;
-  fn version(&self) -> CniResult<()>;
+  /// Whether the plugin is ready to service `ADD` requests, failing with
+  /// [`CniErrorCode::PluginNotAvailable`] otherwise.
+  fn status(&self, args: CniStatusContext) -> CniResult<()>;
+}
+
+/// A plugin whose operations are asynchronous, driven by a current-thread tokio runtime.
+#[async_trait(?Send)]
+pub trait AsyncCniPlugin {
+  async fn add(&self, args: CniAddContext) -> CniResult<AddReply>;
+  async fn del(&self, args: CniDelContext) -> CniResult<()>;
+  async fn check(&self, args: CniCheckContext) -> CniResult<()>;
+  async fn gc(&self, args: CniGcContext) -> CniResult<()>;
+  async fn status(&self, args: CniStatusContext) -> CniResult<()>;
+}
+
+/// Runs `future` to completion on a current-thread tokio runtime, as for an [`AsyncCniPlugin`], so
+/// that a synchronous [`CniPlugin`] can use a [`Netlink`](crate::link::Netlink) connection.
+pub fn block_on<F, T>(future: F) -> CniResult<T>
+where
+  F: Future<Output = CniResult<T>>,
+{
+  tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .map_err(CniErrorCode::IOFailure)?
+    .block_on(future)
 }
 
 pub(crate) enum CniExecution {
   Add(CniAddContext),
   Del(CniDelContext),
   Check(CniCheckContext),
-  Status,
+  Status(CniStatusContext),
   GC(CniGcContext),
-  Version,
+  Version(VersionReply),
 }
 
 impl CniExecution {
-  pub fn load_args_from_env() -> CniResult<CniExecution> {
+  /// Loads the execution of the environment, with the request read from stdin as `stdin`.
+  pub fn load_args_from_env(
+    plugin_info: &impl PluginInfo,
+    stdin: &[u8],
+  ) -> CniResult<CniExecution> {
     let path = load_env::<CniPath>(CNI_PATH)?.unwrap_or_default();
 
-    let mut buffer = Vec::with_capacity(1 << 10);
-    std::io::stdin()
-      .read_to_end(&mut buffer)
-      .map_err(|e| CniErrorCode::IOFailure(e))?;
+    let command = require_env::<CniCommand>(CNI_COMMAND)?;
+    if !matches!(command, CniCommand::Version) {
+      let version = decode_config_version(stdin)?;
+      check_version(&command, &version, plugin_info.supported_versions())?;
+    }
 
-    let config = read_config_from_stdio()?;
-    let args = obtain_args();
+    let args = obtain_args()?;
 
-    match require_env::<CniCommand>(CNI_COMMAND)? {
+    match command {
       CniCommand::Add => Ok(Self::Add(CniAddContext {
         container_id: obtain_container_id()?,
-        netns: require_env(CNI_NSNAME)?,
+        netns: require_env(CNI_NETNS)?,
         if_name: require_env(CNI_IFNAME)?,
         args,
-        config,
+        config: read_config(stdin)?,
+        path: path.clone(),
       })),
       CniCommand::Del => Ok(Self::Del(CniDelContext {
         container_id: obtain_container_id()?,
         if_name: require_env(CNI_IFNAME)?,
-        netns: load_env(CNI_NSNAME)?,
+        netns: load_env(CNI_NETNS)?,
         args,
-        config,
+        config: read_config(stdin)?,
+        path: path.clone(),
       })),
       CniCommand::Check => Ok(Self::Check(CniCheckContext {
         container_id: obtain_container_id()?,
-        netns: require_env(CNI_NSNAME)?,
+        netns: require_env(CNI_NETNS)?,
         if_name: require_env(CNI_IFNAME)?,
         args,
-        config,
+        config: read_config(stdin)?,
+        path: path.clone(),
+      })),
+      CniCommand::GC => {
+        let config = read_config(stdin)?;
+        Ok(Self::GC(CniGcContext {
+          valid_attachments: valid_attachments(&config)?,
+          config,
+          path,
+        }))
+      }
+      CniCommand::Status => Ok(Self::Status(CniStatusContext {
+        config: read_config(stdin)?,
+        path,
+      })),
+      // The input of `VERSION` only carries the version the runtime speaks, if anything.
+      CniCommand::Version => Ok(Self::Version(VersionReply {
+        cni_version:        if stdin.iter().all(u8::is_ascii_whitespace) {
+          crate::version::current()
+        } else {
+          decode_config_version(stdin)?
+        },
+        supported_versions: plugin_info.supported_versions(),
       })),
-      CniCommand::GC => Ok(Self::GC(CniGcContext {})),
-      CniCommand::Version => Ok(Self::Version),
     }
   }
-
-  pub fn exec(&self) -> CniResult<()> {
-    Ok(())
-  }
 }
 
 pub fn obtain_container_id() -> CniResult<ContainerID> {
@@ -90,13 +152,8 @@ pub fn obtain_container_id() -> CniResult<ContainerID> {
   Ok(container_id)
 }
 
-pub fn obtain_args() -> CniArgs {
-  let str = require_env::<String>(CNI_ARGS);
-  if let Ok(s) = str {
-    CniArgs::from_str(s.as_str()).unwrap()
-  } else {
-    CniArgs::default()
-  }
+pub fn obtain_args() -> CniResult<CniArgs> {
+  load_env::<CniArgs>(CNI_ARGS).map(Option::unwrap_or_default)
 }
 
 pub(crate) fn check_container_id(container_id: &ContainerID) -> CniResult<()> {
@@ -117,14 +174,33 @@ pub(crate) fn check_container_id(container_id: &ContainerID) -> CniResult<()> {
   Ok(())
 }
 
-pub fn read_config_from_stdio() -> CniResult<CniNetworkConfig> {
+pub fn read_config_from_stdio() -> CniResult<NetworkConfig> {
+  read_config(&read_stdin()?)
+}
+
+pub(crate) fn read_stdin() -> CniResult<Vec<u8>> {
   let mut buffer = Vec::with_capacity(1 << 10);
   std::io::stdin()
     .read_to_end(&mut buffer)
-    .map_err(|e| CniErrorCode::IOFailure(e))?;
+    .map_err(CniErrorCode::IOFailure)?;
+  Ok(buffer)
+}
+
+fn valid_attachments(config: &NetworkConfig) -> CniResult<Vec<GcAttachment>> {
+  config
+    .plugin
+    .attributes
+    .get(VALID_ATTACHMENTS)
+    .cloned()
+    .map(serde_json::from_value)
+    .transpose()
+    .map(Option::unwrap_or_default)
+    .map_err(|e| CniErrorCode::InvalidNetworkConfig(format!("{}: {}", VALID_ATTACHMENTS, e).into()))
+}
 
-  serde_json::from_slice::<CniNetworkConfig>(&buffer)
-    .map_err(|_| CniErrorCode::InvalidNetworkConfig)
+fn read_config(bytes: &[u8]) -> CniResult<NetworkConfig> {
+  serde_json::from_slice::<NetworkConfig>(bytes)
+    .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))
 }
 
 pub fn load_env<T>(var: &'static str) -> CniResult<Option<T>, CniErrorCode>
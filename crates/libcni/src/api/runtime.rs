@@ -0,0 +1,326 @@
+use std::path::PathBuf;
+
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::api::cache::NetworkAttachment;
+use crate::api::cache::ResultCache;
+use crate::api::invoke::Invoker;
+use crate::api::invoke::RawExec;
+use crate::schema::args::GcAttachment;
+use crate::schema::args::CNI_ARGS;
+use crate::schema::args::CNI_COMMAND;
+use crate::schema::args::CNI_CONTAINERID;
+use crate::schema::args::CNI_IFNAME;
+use crate::schema::args::CNI_NETNS;
+use crate::schema::args::CNI_PATH;
+use crate::schema::config;
+use crate::schema::config::NetworkConfig;
+use crate::schema::config::NetworkConfigList;
+use crate::schema::config::VALID_ATTACHMENTS;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::VersionReply;
+use crate::schema::runtime::RuntimeConfig;
+use crate::schema::CniCommand;
+use crate::schema::CniPath;
+use crate::schema::DEFAULT_NET_CACHE_DIR;
+
+/// Runtime side of the protocol, which locates plugins on `CNI_PATH` and executes them.
+///
+/// [Protocol](https://github.com/containernetworking/cni/blob/main/SPEC.md#section-2-execution-protocol)
+pub struct CniConfig<I = RawExec> {
+  path:    CniPath,
+  invoker: I,
+  cache:   ResultCache,
+}
+
+impl CniConfig {
+  pub fn new(path: CniPath) -> Self {
+    Self::with_invoker(path, RawExec)
+  }
+}
+
+impl<I> CniConfig<I>
+where
+  I: Invoker,
+{
+  pub fn with_invoker(path: CniPath, invoker: I) -> Self {
+    Self {
+      path,
+      invoker,
+      cache: ResultCache::new(DEFAULT_NET_CACHE_DIR),
+    }
+  }
+
+  /// Caches results under `cache_dir` instead of [`DEFAULT_NET_CACHE_DIR`].
+  pub fn with_cache_dir<P>(mut self, cache_dir: P) -> Self
+  where
+    P: Into<PathBuf>,
+  {
+    self.cache = ResultCache::new(cache_dir);
+    self
+  }
+
+  pub fn path(&self) -> &CniPath {
+    &self.path
+  }
+
+  /// Adds the container to every network of the list, in order. Each plugin receives the result
+  /// of its predecessor as `prevResult`, and the result of the last plugin is cached and returned.
+  pub fn add_network_list(
+    &self,
+    list: &NetworkConfigList,
+    rt: &RuntimeConfig,
+  ) -> CniResult<AddReply> {
+    let mut prev_result = None;
+    for plugin in list.plugins.iter() {
+      let net = list.network_config(plugin, prev_result.take());
+      prev_result = Some(self.exec_add(&net, rt)?);
+    }
+
+    let result = prev_result.ok_or_else(|| {
+      CniErrorCode::InvalidNetworkConfig(format!("network {} has no plugins", list.name).into())
+    })?;
+    self.cache.save(&list.name, &to_vec(list)?, rt, &result)?;
+    Ok(result)
+  }
+
+  /// Checks the container's networking against every network of the list, in order, passing the
+  /// cached result as `prevResult`, which is required. Lists with `disableCheck` set are skipped.
+  pub fn check_network_list(&self, list: &NetworkConfigList, rt: &RuntimeConfig) -> CniResult<()> {
+    let version = list.version();
+    if version < semver::Version::new(0, 4, 0) {
+      return Err(CniErrorCode::IncompatibleVersion {
+        version,
+        supported: Vec::new(),
+      });
+    }
+    if list.disable_check {
+      return Ok(());
+    }
+
+    let cached_result = self.cache.result(&list.name, rt)?.ok_or_else(|| {
+      CniErrorCode::Internal(format!("no cached result for network {}", list.name).into())
+    })?;
+    for plugin in list.plugins.iter() {
+      let net = list.network_config(plugin, Some(cached_result.clone()));
+      self.exec_plugin(CniCommand::Check, &net, rt)?;
+    }
+    Ok(())
+  }
+
+  /// Removes the container from every network of the list, in reverse order, passing the cached
+  /// result as `prevResult` from `0.4.0` on. The cached result is dropped afterwards.
+  pub fn del_network_list(&self, list: &NetworkConfigList, rt: &RuntimeConfig) -> CniResult<()> {
+    let cached_result = if list.version() >= semver::Version::new(0, 4, 0) {
+      self.cache.result(&list.name, rt)?
+    } else {
+      None
+    };
+
+    for plugin in list.plugins.iter().rev() {
+      let net = list.network_config(plugin, cached_result.clone());
+      self.exec_plugin(CniCommand::Del, &net, rt)?;
+    }
+    self.cache.delete(&list.name, rt)
+  }
+
+  /// Removes the cached attachments of the list other than `valid_attachments`, then asks every
+  /// plugin of the list to clean up whatever else those left behind. Lists with `disableGC` set,
+  /// or conforming to a version without `GC`, are not asked.
+  pub fn gc_network_list(
+    &self,
+    list: &NetworkConfigList,
+    valid_attachments: &[GcAttachment],
+  ) -> CniResult<()> {
+    // Every stale attachment is attempted, the first failure is reported.
+    let mut result = Ok(());
+    for attachment in self.cache.attachments(None)? {
+      let valid = valid_attachments.iter().any(|valid| {
+        valid.container_id == attachment.container_id && valid.if_name == attachment.if_name
+      });
+      if attachment.network == list.name && !valid {
+        let deleted = self.del_network_list(list, &attachment.runtime_config());
+        result = result.and(deleted);
+      }
+    }
+
+    if list.disable_gc || list.version() < semver::Version::new(1, 1, 0) {
+      return result;
+    }
+
+    let valid_attachments = serde_json::to_value(valid_attachments)
+      .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))?;
+    for plugin in list.plugins.iter() {
+      let mut net = list.network_config(plugin, None);
+      net
+        .plugin
+        .attributes
+        .insert(VALID_ATTACHMENTS.to_string(), valid_attachments.clone());
+      self.exec_plugin_without_runtime(CniCommand::GC, &net)?;
+    }
+    result
+  }
+
+  /// Checks whether every plugin of the list is ready to service `ADD` requests. Lists conforming
+  /// to a version without `STATUS` are considered ready.
+  pub fn get_status_network_list(&self, list: &NetworkConfigList) -> CniResult<()> {
+    if list.version() < semver::Version::new(1, 1, 0) {
+      return Ok(());
+    }
+
+    for plugin in list.plugins.iter() {
+      let net = list.network_config(plugin, None);
+      self.exec_plugin_without_runtime(CniCommand::Status, &net)?;
+    }
+    Ok(())
+  }
+
+  /// The cached result of adding the container to the list.
+  pub fn get_network_list_cached_result(
+    &self,
+    list: &NetworkConfigList,
+    rt: &RuntimeConfig,
+  ) -> CniResult<Option<AddReply>> {
+    self.cache.result(&list.name, rt)
+  }
+
+  /// The cached attachments, or only those of container `container_id`.
+  pub fn get_cached_attachments(
+    &self,
+    container_id: Option<&str>,
+  ) -> CniResult<Vec<NetworkAttachment>> {
+    self.cache.attachments(container_id)
+  }
+
+  /// Adds the container to the network, caching the result.
+  pub fn add_network(&self, net: &NetworkConfig, rt: &RuntimeConfig) -> CniResult<AddReply> {
+    let result = self.exec_add(net, rt)?;
+    self.cache.save(&net.name, &to_vec(net)?, rt, &result)?;
+    Ok(result)
+  }
+
+  /// Checks that the container's networking is as expected, passing the cached result as
+  /// `prevResult`.
+  pub fn check_network(&self, net: &NetworkConfig, rt: &RuntimeConfig) -> CniResult<()> {
+    let net = self.with_cached_result(net, rt)?;
+    self.exec_plugin(CniCommand::Check, &net, rt).map(|_| ())
+  }
+
+  /// Removes the container from the network, passing the cached result as `prevResult`.
+  pub fn del_network(&self, net: &NetworkConfig, rt: &RuntimeConfig) -> CniResult<()> {
+    let net = self.with_cached_result(net, rt)?;
+    self.exec_plugin(CniCommand::Del, &net, rt)?;
+    self.cache.delete(&net.name, rt)
+  }
+
+  /// Queries the specification versions supported by plugin `plugin`.
+  pub fn get_version_info(&self, plugin: &str) -> CniResult<VersionReply> {
+    let stdin = serde_json::json!({ "cniVersion": crate::version::current().to_string() });
+    let stdin = serde_json::to_vec(&stdin)
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?;
+    let environ = [
+      (CNI_COMMAND, CniCommand::Version.to_string()),
+      (CNI_PATH, self.path.to_string()),
+    ];
+
+    let stdout = self
+      .invoker
+      .exec_plugin(&self.path.find(plugin)?, &stdin, &environ)?;
+    serde_json::from_slice::<VersionReply>(&stdout)
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+  }
+
+  fn exec_add(&self, net: &NetworkConfig, rt: &RuntimeConfig) -> CniResult<AddReply> {
+    let stdout = self.exec_plugin(CniCommand::Add, net, rt)?;
+    AddReply::from_slice(&stdout)
+  }
+
+  fn with_cached_result(
+    &self,
+    net: &NetworkConfig,
+    rt: &RuntimeConfig,
+  ) -> CniResult<NetworkConfig> {
+    let mut net = net.clone();
+    if net.cni_version >= semver::Version::new(0, 4, 0) {
+      net.prev_result = self.cache.result(&net.name, rt)?.map(|mut prev_result| {
+        prev_result.cni_version = net.cni_version.clone();
+        prev_result
+      });
+    }
+    Ok(net)
+  }
+
+  fn exec_plugin(
+    &self,
+    command: CniCommand,
+    net: &NetworkConfig,
+    rt: &RuntimeConfig,
+  ) -> CniResult<Vec<u8>> {
+    let environ = [
+      (CNI_COMMAND, command.to_string()),
+      (CNI_CONTAINERID, rt.container_id.clone()),
+      (CNI_NETNS, rt.netns.clone()),
+      (CNI_IFNAME, rt.if_name.clone()),
+      (CNI_ARGS, rt.args.to_string()),
+      (CNI_PATH, self.path.to_string()),
+    ];
+
+    self.invoke(&inject_runtime_config(net, rt)?, &environ)
+  }
+
+  fn exec_plugin_without_runtime(
+    &self,
+    command: CniCommand,
+    net: &NetworkConfig,
+  ) -> CniResult<Vec<u8>> {
+    let environ = [
+      (CNI_COMMAND, command.to_string()),
+      (CNI_PATH, self.path.to_string()),
+    ];
+
+    self.invoke(net, &environ)
+  }
+
+  fn invoke(&self, net: &NetworkConfig, environ: &[(&'static str, String)]) -> CniResult<Vec<u8>> {
+    let plugin = self.path.find(&net.plugin.type_)?;
+    let stdin = serde_json::to_vec(net)
+      .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))?;
+    self.invoker.exec_plugin(&plugin, &stdin, environ)
+  }
+}
+
+fn to_vec<T>(config: &T) -> CniResult<Vec<u8>>
+where
+  T: serde::Serialize,
+{
+  serde_json::to_vec(config).map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))
+}
+
+/// Passes the capability arguments of the runtime, which the plugin declares in its
+/// `capabilities`, as `runtimeConfig`.
+fn inject_runtime_config(net: &NetworkConfig, rt: &RuntimeConfig) -> CniResult<NetworkConfig> {
+  let mut net = net.clone();
+  let capability_args = net
+    .plugin
+    .capabilities
+    .iter()
+    .flatten()
+    .filter(|(_, enabled)| **enabled)
+    .filter_map(|(capability, _)| {
+      rt.capability_args
+        .get(capability)
+        .map(|value| (capability.clone(), value.clone()))
+    })
+    .collect::<Map<_, _>>();
+
+  if !capability_args.is_empty() {
+    let runtime_config =
+      serde_json::from_value::<config::RuntimeConfig>(Value::Object(capability_args))
+        .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))?;
+    net.plugin.runtime_config = Some(runtime_config);
+  }
+  Ok(net)
+}
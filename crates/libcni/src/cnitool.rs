@@ -1,28 +1,33 @@
-#![feature(io_error_more)]
-
-pub mod api;
-pub mod schema;
-
-use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::Index;
 use std::path::PathBuf;
-use std::str::FromStr;
 
 use libcni::api::config::load_config;
+use libcni::api::load_env;
+use libcni::api::runtime::CniConfig;
 use libcni::schema::args::CniArgs;
 use libcni::schema::args::CNI_ARGS;
 use libcni::schema::args::CNI_IFNAME;
+use libcni::schema::args::CNI_PATH;
 use libcni::schema::error::CniError;
 use libcni::schema::error::CniErrorCode;
 use libcni::schema::error::CniResult;
+use libcni::schema::reply::ReplyPayload;
 use libcni::schema::runtime::RuntimeConfig;
+use libcni::schema::CniPath;
 use libcni::schema::ContainerID;
 use libcni::schema::DEFAULT_NET_CONF_DIR;
+use serde_json::Value;
 use sha2::Digest;
 use sha2::Sha512;
 
+/// Directory containing the network configurations.
+const NETCONFPATH: &str = "NETCONFPATH";
+/// Capability arguments as a JSON object, passed as `runtimeConfig` to capable plugins.
+const CAP_ARGS: &str = "CAP_ARGS";
+
 fn main() {
-  run().unwrap_or_else(|e| eprintln!("{:?}", e));
+  exit(run().err())
 }
 
 fn run() -> CniResult<()> {
@@ -32,14 +37,22 @@ fn run() -> CniResult<()> {
 
   let args = std::env::args().collect::<Vec<String>>();
 
-  let net_dir = std::env::var("NETCONFPATH")
+  let net_dir = std::env::var(NETCONFPATH)
     .ok()
     .or_else(|| Some(DEFAULT_NET_CONF_DIR.to_string()))
     .expect("CNI network configuration directory is set");
 
-  let _ = load_config(PathBuf::from(net_dir), args.index(2))
-    .map_err(|_| exit(Some(CniErrorCode::DecodeContentFailure)))
-    .expect("Failed to load config file");
+  let list = load_config(PathBuf::from(net_dir), args.index(2))?;
+
+  let capability_args = std::env::var(CAP_ARGS)
+    .ok()
+    .filter(|s| !s.is_empty())
+    .map(|s| serde_json::from_str::<HashMap<String, Value>>(&s))
+    .transpose()
+    .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?
+    .unwrap_or_default();
+
+  let cni_args = load_env::<CniArgs>(CNI_ARGS)?.unwrap_or_default();
 
   let if_name = std::env::var(CNI_IFNAME)
     .ok()
@@ -48,31 +61,37 @@ fn run() -> CniResult<()> {
 
   let netns = args.index(3);
   if !PathBuf::from(netns).is_absolute() {
-    exit(Some(CniErrorCode::IOFailure(std::io::Error::new(
-      std::io::ErrorKind::InvalidFilename,
-      "No network configuration path must be absolute",
-    ))))
+    Err(CniErrorCode::IOFailure(std::io::Error::new(
+      std::io::ErrorKind::InvalidInput,
+      "Network namespace path must be absolute",
+    )))?;
   }
 
-  let cni_args = std::env::var(CNI_ARGS)
-    .ok()
-    .map(|s| CniArgs::from_str(&s).expect("Failed to parse arguments from CNI env"))
-    .expect("CNI arguments is empty");
-
   let rt = RuntimeConfig {
-    container_id: obtain_hashed_container_id(netns.clone()),
+    container_id: obtain_hashed_container_id(netns),
     netns: netns.clone(),
     if_name,
     args: cni_args,
+    capability_args,
   };
 
+  let path = load_env::<CniPath>(CNI_PATH)?.unwrap_or_default();
+  let cni = CniConfig::new(path);
+
   match args.index(1).as_str() {
-    "add" => exit(None),
-    "del" => exit(None),
-    "check" => exit(None),
-    "gc" => exit(None),
-    "status" => exit(None),
-    _ => exit(Some(CniErrorCode::UnknownCommand)),
+    "add" => {
+      let result = cni.add_network_list(&list, &rt)?;
+      let result = serde_json::to_string_pretty(&result.to_value()?)
+        .map_err(|e| CniErrorCode::IOFailure(e.into()))?;
+      println!("{}", result);
+      Ok(())
+    }
+    "del" => cni.del_network_list(&list, &rt),
+    "check" => cni.check_network_list(&list, &rt),
+    // Without valid attachments, every attachment of the network is garbage collected.
+    "gc" => cni.gc_network_list(&list, &[]),
+    "status" => cni.get_status_network_list(&list),
+    command => Err(CniErrorCode::UnknownCommand(command.to_string())),
   }
 }
 
@@ -80,25 +99,36 @@ fn print_usage() {
   eprintln!(
     "\
 cnitool: Add, check, or remove network interfaces from a network namespace
-  cnitool add   <net> <netns>
-  cnitool check <net> <netns>
-  cnitool del   <net> <netns>"
+  cnitool add    <net> <netns>
+  cnitool check  <net> <netns>
+  cnitool del    <net> <netns>
+  cnitool gc     <net> <netns>
+  cnitool status <net> <netns>"
   );
   std::process::exit(1);
 }
 
 fn exit(error: Option<CniErrorCode>) -> ! {
   if let Some(e) = error {
-    eprintln!("{:?}", e);
+    let error = CniError::from(e);
+    eprintln!(
+      "{}",
+      serde_json::to_string(&error).unwrap_or_else(|_| error.to_string())
+    );
     std::process::exit(1);
   } else {
     std::process::exit(0);
   }
 }
 
-fn obtain_hashed_container_id(content: String) -> ContainerID {
+/// Generates the container ID by hashing the network namespace path.
+fn obtain_hashed_container_id(netns: &str) -> ContainerID {
   let mut hasher = Sha512::new();
-  hasher.update(format!("cnitool-{:10}", content));
-  let bytes = &hasher.finalize()[..];
-  String::from_utf8_lossy(bytes).to_string()
+  hasher.update(netns);
+  let digest = hasher.finalize();
+  let hex = digest[..10]
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  format!("cnitool-{}", hex)
 }
@@ -3,61 +3,248 @@
 //! References:
 //! - [CNI Spec](https://github.com/containernetworking/cni/blob/main/SPEC.md)
 
+use crate::api::read_stdin;
+use crate::api::AsyncCniPlugin;
 use crate::api::CniExecution;
 use crate::api::CniPlugin;
+use crate::netns::NetNs;
+use crate::schema::args::CNI_NETNS;
+use crate::schema::error::CniErrorCode;
 use crate::schema::error::CniResult;
-use crate::schema::NetNS;
-use crate::version::check_version;
+use crate::schema::reply::reply;
+use crate::schema::reply::reply_error;
+use crate::version::decode_config_version;
 use crate::version::PluginInfo;
 
 pub mod api;
+pub mod link;
+pub mod netns;
 pub mod plugin;
 pub mod schema;
 pub mod version;
 
-/// ```rust
+pub use crate::api::block_on;
+
+/// ```rust,no_run
+/// use libcni::api::CniPlugin;
 /// use libcni::plugin_main_entrypoint;
+/// use libcni::schema::args::CniAddContext;
+/// use libcni::schema::args::CniCheckContext;
+/// use libcni::schema::args::CniDelContext;
+/// use libcni::schema::args::CniGcContext;
+/// use libcni::schema::args::CniStatusContext;
 /// use libcni::schema::error::CniResult;
+/// use libcni::schema::reply::AddReply;
 /// use libcni::version;
 ///
+/// struct Plugin;
+///
+/// impl CniPlugin for Plugin {
+///   fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+///     Ok(AddReply {
+///       cni_version: args.config.cni_version,
+///       interfaces:  Vec::new(),
+///       ips:         Vec::new(),
+///       routes:      Vec::new(),
+///       dns:         Default::default(),
+///     })
+///   }
+///
+///   fn del(&self, args: CniDelContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   fn check(&self, args: CniCheckContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   fn gc(&self, args: CniGcContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   fn status(&self, args: CniStatusContext) -> CniResult<()> {
+///     Ok(())
+///   }
+/// }
+///
 /// fn main() {
-///   plugin_main_entrypoint(plugin, version::All, semver::Version::new(1, 0, 0))
+///   plugin_main_entrypoint(Plugin, version::All, semver::Version::new(1, 0, 0))
 /// }
 /// ```
 pub fn plugin_main_entrypoint(
   plugin: impl CniPlugin,
   plugin_info: impl PluginInfo,
-  build_version: semver::Version,
+  _build_version: semver::Version,
 ) {
-  plugin_main_entrypoint_with_error(plugin, plugin_info, build_version).map_err(|e| {});
+  let stdin = read_stdin();
+  let cni_version = request_version(&stdin);
+  let result = stdin.and_then(|stdin| {
+    plugin_main_entrypoint_with_error(plugin, plugin_info, &stdin, cni_version.as_ref())
+  });
+  if let Err(error) = result {
+    reply_error(error, cni_version.as_ref());
+  }
 }
 
 fn plugin_main_entrypoint_with_error(
   plugin: impl CniPlugin,
   plugin_info: impl PluginInfo,
-  build_version: semver::Version,
+  stdin: &[u8],
+  cni_version: Option<&semver::Version>,
 ) -> CniResult<()> {
-  let execution = CniExecution::load_args_from_env()?;
+  let execution = CniExecution::load_args_from_env(&plugin_info, stdin)?;
 
   match execution {
     CniExecution::Add(args) => {
-      check_version(&build_version, plugin_info.supported_versions())?;
-      plugin.add(args)
-      // check_netns(netns)
+      check_netns(&args.netns)?;
+      reply(plugin.add(args), cni_version)
     }
     CniExecution::Del(args) => {
-      check_version(&build_version, plugin_info.supported_versions())?;
+      if let Some(netns) = &args.netns {
+        check_del_netns(netns)?;
+      }
       plugin.del(args)
-      // check_netns(netns)?;
     }
-    CniExecution::Check(args) => plugin.check(args),
-    CniExecution::Status => plugin.status(),
+    CniExecution::Check(args) => {
+      check_netns(&args.netns)?;
+      plugin.check(args)
+    }
+    CniExecution::Status(args) => plugin.status(args),
     CniExecution::GC(args) => plugin.gc(args),
-    CniExecution::Version => plugin.version(),
+    CniExecution::Version(version) => reply(Ok(version), cni_version),
   }
 }
 
-// Plugin's netns and CNI netns should not be the same.
-fn check_netns(netns: NetNS) -> CniResult<()> {
-  todo!()
+/// Runs an [`AsyncCniPlugin`] to completion on a current-thread tokio runtime.
+///
+/// ```rust,no_run
+/// use async_trait::async_trait;
+/// use libcni::api::AsyncCniPlugin;
+/// use libcni::async_plugin_main_entrypoint;
+/// use libcni::schema::args::CniAddContext;
+/// use libcni::schema::args::CniCheckContext;
+/// use libcni::schema::args::CniDelContext;
+/// use libcni::schema::args::CniGcContext;
+/// use libcni::schema::args::CniStatusContext;
+/// use libcni::schema::error::CniResult;
+/// use libcni::schema::reply::AddReply;
+/// use libcni::version;
+///
+/// struct Plugin;
+///
+/// #[async_trait(?Send)]
+/// impl AsyncCniPlugin for Plugin {
+///   async fn add(&self, args: CniAddContext) -> CniResult<AddReply> {
+///     Ok(AddReply {
+///       cni_version: args.config.cni_version,
+///       interfaces:  Vec::new(),
+///       ips:         Vec::new(),
+///       routes:      Vec::new(),
+///       dns:         Default::default(),
+///     })
+///   }
+///
+///   async fn del(&self, args: CniDelContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   async fn check(&self, args: CniCheckContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   async fn gc(&self, args: CniGcContext) -> CniResult<()> {
+///     Ok(())
+///   }
+///
+///   async fn status(&self, args: CniStatusContext) -> CniResult<()> {
+///     Ok(())
+///   }
+/// }
+///
+/// fn main() {
+///   async_plugin_main_entrypoint(Plugin, version::All, semver::Version::new(1, 0, 0))
+/// }
+/// ```
+pub fn async_plugin_main_entrypoint(
+  plugin: impl AsyncCniPlugin,
+  plugin_info: impl PluginInfo,
+  _build_version: semver::Version,
+) {
+  let stdin = read_stdin();
+  let cni_version = request_version(&stdin);
+  let result = stdin.and_then(|stdin| {
+    block_on(async_plugin_main_entrypoint_with_error(
+      plugin,
+      plugin_info,
+      &stdin,
+      cni_version.as_ref(),
+    ))
+  });
+  if let Err(error) = result {
+    reply_error(error, cni_version.as_ref());
+  }
+}
+
+async fn async_plugin_main_entrypoint_with_error(
+  plugin: impl AsyncCniPlugin,
+  plugin_info: impl PluginInfo,
+  stdin: &[u8],
+  cni_version: Option<&semver::Version>,
+) -> CniResult<()> {
+  let execution = CniExecution::load_args_from_env(&plugin_info, stdin)?;
+
+  match execution {
+    CniExecution::Add(args) => {
+      check_netns(&args.netns)?;
+      reply(plugin.add(args).await, cni_version)
+    }
+    CniExecution::Del(args) => {
+      if let Some(netns) = &args.netns {
+        check_del_netns(netns)?;
+      }
+      plugin.del(args).await
+    }
+    CniExecution::Check(args) => {
+      check_netns(&args.netns)?;
+      plugin.check(args).await
+    }
+    CniExecution::Status(args) => plugin.status(args).await,
+    CniExecution::GC(args) => plugin.gc(args).await,
+    CniExecution::Version(version) => reply(Ok(version), cni_version),
+  }
+}
+
+/// The `cniVersion` of the request, unknown if it cannot be read.
+fn request_version(stdin: &CniResult<Vec<u8>>) -> Option<semver::Version> {
+  let stdin = stdin.as_ref().ok()?;
+  decode_config_version(stdin).ok()
+}
+
+/// The container's network namespace must be opened, and is never the plugin's own.
+fn check_netns(netns: &str) -> CniResult<()> {
+  let container = NetNs::open(netns).map_err(|error| match error {
+    CniErrorCode::IOFailure(error) => CniErrorCode::InvalidEnvironmentVariable {
+      var:   CNI_NETNS,
+      error: Box::new(error),
+    },
+    error => error,
+  })?;
+  if container.is_same(&NetNs::current()?)? {
+    return Err(CniErrorCode::InvalidEnvironmentVariable {
+      var:   CNI_NETNS,
+      error: Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{} is the network namespace of the plugin", netns),
+      )),
+    });
+  }
+  Ok(())
+}
+
+/// As [`check_netns`], but the namespace may be gone already on `DEL`, which the plugin tolerates.
+fn check_del_netns(netns: &str) -> CniResult<()> {
+  if NetNs::open(netns).is_err() {
+    return Ok(());
+  }
+  check_netns(netns)
 }
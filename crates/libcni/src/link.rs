@@ -0,0 +1,1064 @@
+/*
+Interfaces, addresses and routes of a network namespace, managed over rtnetlink.
+
+A [`Netlink`] connection is bound to the namespace it was opened in, and keeps operating on it
+from any thread. Its requests are driven by the tokio runtime it was opened from, as in an
+[`AsyncCniPlugin`](crate::api::AsyncCniPlugin).
+ */
+use std::io::Read;
+use std::net::IpAddr;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use futures::TryStreamExt;
+use ipnetwork::IpNetwork;
+use netlink_packet_route::link::nlas::AfSpecBridge;
+use netlink_packet_route::link::nlas::BridgeVlanInfo;
+use netlink_packet_route::link::nlas::Info;
+use netlink_packet_route::link::nlas::InfoBridge;
+use netlink_packet_route::link::nlas::InfoData;
+use netlink_packet_route::link::nlas::InfoIpVlan;
+pub use netlink_packet_route::link::nlas::InfoKind;
+use netlink_packet_route::link::nlas::Nla as LinkNla;
+use netlink_packet_route::link::nlas::VethInfo;
+use netlink_packet_route::nlas::address::Nla as AddressNla;
+use netlink_packet_route::nlas::route::Nla as RouteNla;
+use netlink_packet_route::LinkMessage;
+use netlink_packet_route::RouteMessage;
+use netlink_packet_route::AF_BRIDGE;
+use netlink_packet_route::AF_INET;
+use netlink_packet_route::AF_INET6;
+use netlink_packet_route::BRIDGE_VLAN_INFO_PVID;
+use netlink_packet_route::BRIDGE_VLAN_INFO_UNTAGGED;
+use netlink_packet_route::IFF_ALLMULTI;
+use netlink_packet_route::IFF_PROMISC;
+use netlink_packet_route::IFF_UP;
+use netlink_packet_route::IFLA_PROTINFO;
+use netlink_packet_route::MACVLAN_MODE_BRIDGE;
+use netlink_packet_route::MACVLAN_MODE_PASSTHRU;
+use netlink_packet_route::MACVLAN_MODE_PRIVATE;
+use netlink_packet_route::MACVLAN_MODE_VEPA;
+use netlink_packet_route::RTAX_ADVMSS;
+use netlink_packet_route::RTAX_MTU;
+use netlink_packet_route::RTN_UNICAST;
+use netlink_packet_route::RTPROT_KERNEL;
+use netlink_packet_route::RT_SCOPE_LINK;
+use netlink_packet_route::RT_TABLE_MAIN;
+use netlink_packet_utils::nla::DefaultNla;
+use netlink_packet_utils::nla::NLA_F_NESTED;
+use rtnetlink::Handle;
+use rtnetlink::IpVersion;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::netns::NetNs;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::Interface;
+use crate::schema::reply::Ips;
+use crate::schema::reply::MacAddress;
+use crate::schema::reply::Route;
+
+/// `IFLA_BRPORT_MODE`, the hairpin mode of a bridge port.
+const IFLA_BRPORT_MODE: u16 = 4;
+
+/// Modes of macvlan links.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MacvlanMode {
+  #[default]
+  Bridge,
+  Private,
+  Vepa,
+  Passthru,
+}
+
+impl MacvlanMode {
+  fn value(self) -> u32 {
+    match self {
+      MacvlanMode::Bridge => MACVLAN_MODE_BRIDGE,
+      MacvlanMode::Private => MACVLAN_MODE_PRIVATE,
+      MacvlanMode::Vepa => MACVLAN_MODE_VEPA,
+      MacvlanMode::Passthru => MACVLAN_MODE_PASSTHRU,
+    }
+  }
+}
+
+/// Modes of ipvlan links.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpvlanMode {
+  #[default]
+  L2,
+  L3,
+  L3s,
+}
+
+impl IpvlanMode {
+  fn value(self) -> u16 {
+    match self {
+      IpvlanMode::L2 => 0,
+      IpvlanMode::L3 => 1,
+      IpvlanMode::L3s => 2,
+    }
+  }
+}
+
+/// A network interface, as read back from the kernel.
+#[derive(Clone, Debug)]
+pub struct Link {
+  pub index:    u32,
+  pub name:     String,
+  pub kind:     Option<InfoKind>,
+  pub mac:      Option<MacAddress>,
+  pub mtu:      Option<u32>,
+  /// Index of the bridge or bond the link is enslaved to.
+  pub master:   Option<u32>,
+  /// Index of the link this one stands on, like the parent of a macvlan or the peer of a veth, in
+  /// the namespace of that link.
+  pub parent:   Option<u32>,
+  pub up:       bool,
+  pub promisc:  bool,
+  pub allmulti: bool,
+}
+
+impl Link {
+  /// The link as reported in results, with `sandbox` being the `CNI_NETNS` of the container it is
+  /// in, if any.
+  pub fn interface(&self, sandbox: Option<&Path>) -> Interface {
+    Interface {
+      name:    self.name.clone(),
+      mac:     self.mac.clone(),
+      sandbox: sandbox.map(Path::to_path_buf),
+    }
+  }
+
+  fn from_message(message: LinkMessage) -> Self {
+    let mut link = Link {
+      index:    message.header.index,
+      name:     String::new(),
+      kind:     None,
+      mac:      None,
+      mtu:      None,
+      master:   None,
+      parent:   None,
+      up:       message.header.flags & IFF_UP != 0,
+      promisc:  message.header.flags & IFF_PROMISC != 0,
+      allmulti: message.header.flags & IFF_ALLMULTI != 0,
+    };
+    for nla in message.nlas {
+      match nla {
+        LinkNla::IfName(name) => link.name = name,
+        LinkNla::Address(bytes) => link.mac = mac_address(&bytes),
+        LinkNla::Mtu(mtu) => link.mtu = Some(mtu),
+        LinkNla::Master(master) => link.master = Some(master),
+        LinkNla::Link(parent) => link.parent = Some(parent),
+        LinkNla::Info(infos) => {
+          link.kind = infos.into_iter().find_map(|info| match info {
+            Info::Kind(kind) => Some(kind),
+            _ => None,
+          })
+        }
+        _ => {}
+      }
+    }
+    link
+  }
+}
+
+/// An rtnetlink connection to a network namespace.
+#[derive(Clone)]
+pub struct Netlink {
+  handle: Handle,
+}
+
+impl Netlink {
+  /// Connects to the network namespace of the calling thread.
+  ///
+  /// Panics outside of a tokio runtime.
+  pub fn new() -> CniResult<Self> {
+    let (connection, handle, _) = rtnetlink::new_connection().map_err(CniErrorCode::IOFailure)?;
+    tokio::spawn(connection);
+    Ok(Self { handle })
+  }
+
+  /// Connects to network namespace `netns`.
+  ///
+  /// Panics outside of a tokio runtime.
+  pub fn open(netns: &NetNs) -> CniResult<Self> {
+    let runtime = tokio::runtime::Handle::current();
+    let (connection, handle, _) = netns
+      .run(|| {
+        let _guard = runtime.enter();
+        rtnetlink::new_connection()
+      })?
+      .map_err(CniErrorCode::IOFailure)?;
+    tokio::spawn(connection);
+    Ok(Self { handle })
+  }
+
+  /// The link named `name`, if there is one.
+  pub async fn link(&self, name: &str) -> CniResult<Option<Link>> {
+    let request = self.handle.link().get().match_name(name.to_string());
+    match request.execute().try_next().await {
+      Ok(message) => Ok(message.map(Link::from_message)),
+      Err(e) if errno(&e) == Some(nix::libc::ENODEV) => Ok(None),
+      Err(e) => Err(netlink_error(e)),
+    }
+  }
+
+  /// The link at `index`.
+  pub async fn link_by_index(&self, index: u32) -> CniResult<Link> {
+    self
+      .handle
+      .link()
+      .get()
+      .match_index(index)
+      .execute()
+      .try_next()
+      .await
+      .map_err(netlink_error)?
+      .map(Link::from_message)
+      .ok_or_else(|| CniErrorCode::Internal(format!("link {} not found", index).into()))
+  }
+
+  /// Creates a veth pair with end `name` in this namespace, and end `peer_name` in `peer_netns`.
+  /// Only the end in this namespace is brought up.
+  pub async fn add_veth(
+    &self,
+    name: &str,
+    peer_name: &str,
+    peer_netns: &NetNs,
+    mtu: Option<u32>,
+  ) -> CniResult<Link> {
+    let mut request = self
+      .handle
+      .link()
+      .add()
+      .veth(peer_name.to_string(), name.to_string());
+    let message = request.message_mut();
+    for nla in message.nlas.iter_mut() {
+      if let LinkNla::Info(infos) = nla {
+        for info in infos.iter_mut() {
+          if let Info::Data(InfoData::Veth(VethInfo::Peer(peer))) = info {
+            peer
+              .nlas
+              .push(LinkNla::NetNsFd(peer_netns.as_fd().as_raw_fd()));
+            peer.nlas.extend(mtu.map(LinkNla::Mtu));
+          }
+        }
+      }
+    }
+    message.nlas.extend(mtu.map(LinkNla::Mtu));
+    request.execute().await.map_err(netlink_error)?;
+    self.require_link(name).await
+  }
+
+  /// The bridge named `name`, created and brought up unless it exists already. VLAN filtering
+  /// only applies to bridges created here.
+  pub async fn ensure_bridge(
+    &self,
+    name: &str,
+    mtu: Option<u32>,
+    vlan_filtering: bool,
+  ) -> CniResult<Link> {
+    if let Some(link) = self.link(name).await? {
+      if link.kind != Some(InfoKind::Bridge) {
+        return Err(CniErrorCode::Internal(
+          format!("{} already exists but is not a bridge", name).into(),
+        ));
+      }
+      return Ok(link);
+    }
+
+    let mut request = self.handle.link().add();
+    let message = request.message_mut();
+    message.nlas.push(LinkNla::IfName(name.to_string()));
+    let mut infos = vec![Info::Kind(InfoKind::Bridge)];
+    if vlan_filtering {
+      infos.push(Info::Data(InfoData::Bridge(vec![
+        InfoBridge::VlanFiltering(1),
+      ])));
+    }
+    message.nlas.push(LinkNla::Info(infos));
+    message.nlas.extend(mtu.map(LinkNla::Mtu));
+    match request.execute().await {
+      // Created concurrently, by another invocation.
+      Err(e) if errno(&e) == Some(nix::libc::EEXIST) => {}
+      result => result.map_err(netlink_error)?,
+    }
+    let link = self.require_link(name).await?;
+    self.set_up(link.index).await?;
+    self.link_by_index(link.index).await
+  }
+
+  /// Creates macvlan link `name` on top of link `parent` of this namespace, directly in `netns`.
+  pub async fn add_macvlan(
+    &self,
+    name: &str,
+    parent: u32,
+    mode: MacvlanMode,
+    mtu: Option<u32>,
+    netns: &NetNs,
+  ) -> CniResult<()> {
+    let mut request = self
+      .handle
+      .link()
+      .add()
+      .macvlan(name.to_string(), parent, mode.value());
+    let message = request.message_mut();
+    message.nlas.extend(mtu.map(LinkNla::Mtu));
+    message
+      .nlas
+      .push(LinkNla::NetNsFd(netns.as_fd().as_raw_fd()));
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// Creates ipvlan link `name` on top of link `parent` of this namespace, directly in `netns`.
+  pub async fn add_ipvlan(
+    &self,
+    name: &str,
+    parent: u32,
+    mode: IpvlanMode,
+    mtu: Option<u32>,
+    netns: &NetNs,
+  ) -> CniResult<()> {
+    let mut request = self.handle.link().add();
+    let message = request.message_mut();
+    message.nlas.push(LinkNla::IfName(name.to_string()));
+    message.nlas.push(LinkNla::Link(parent));
+    message.nlas.push(LinkNla::Info(vec![
+      Info::Kind(InfoKind::IpVlan),
+      Info::Data(InfoData::IpVlan(vec![InfoIpVlan::Mode(mode.value())])),
+    ]));
+    message.nlas.extend(mtu.map(LinkNla::Mtu));
+    message
+      .nlas
+      .push(LinkNla::NetNsFd(netns.as_fd().as_raw_fd()));
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// Creates an IFB device, to which the traffic of other links can be redirected, and brings it
+  /// up.
+  pub async fn add_ifb(&self, name: &str, mtu: Option<u32>) -> CniResult<Link> {
+    let mut request = self.handle.link().add();
+    let message = request.message_mut();
+    message.nlas.push(LinkNla::IfName(name.to_string()));
+    message
+      .nlas
+      .push(LinkNla::Info(vec![Info::Kind(InfoKind::Ifb)]));
+    message.nlas.extend(mtu.map(LinkNla::Mtu));
+    request.execute().await.map_err(netlink_error)?;
+
+    let link = self.require_link(name).await?;
+    self.set_up(link.index).await?;
+    self.link_by_index(link.index).await
+  }
+
+  pub async fn del_link(&self, index: u32) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .del(index)
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  pub async fn set_up(&self, index: u32) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .up()
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  pub async fn set_down(&self, index: u32) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .down()
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  /// Enslaves link `index` to bridge or bond `master`.
+  pub async fn set_master(&self, index: u32, master: u32) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .master(master)
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  pub async fn set_mac(&self, index: u32, mac: &MacAddress) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .address(mac.0.as_bytes().to_vec())
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  pub async fn set_mtu(&self, index: u32, mtu: u32) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .mtu(mtu)
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  pub async fn set_promiscuous(&self, index: u32, enable: bool) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .promiscuous(enable)
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  /// Makes link `index` receive all multicast frames, not only those of the groups it joined.
+  pub async fn set_allmulticast(&self, index: u32, enable: bool) -> CniResult<()> {
+    let mut request = self.handle.link().set(index);
+    let header = &mut request.message_mut().header;
+    if enable {
+      header.flags |= IFF_ALLMULTI;
+    } else {
+      header.flags &= !IFF_ALLMULTI;
+    }
+    header.change_mask |= IFF_ALLMULTI;
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// Lets bridge port `index` send frames back out the port they came in on.
+  pub async fn set_hairpin(&self, index: u32, enable: bool) -> CniResult<()> {
+    // Only the nested form of `IFLA_PROTINFO` carries port attributes.
+    let mut protinfo = Vec::with_capacity(8);
+    protinfo.extend_from_slice(&5u16.to_ne_bytes());
+    protinfo.extend_from_slice(&IFLA_BRPORT_MODE.to_ne_bytes());
+    protinfo.extend_from_slice(&[enable as u8, 0, 0, 0]);
+
+    let mut request = self.handle.link().set(index);
+    let message = request.message_mut();
+    message.header.interface_family = AF_BRIDGE as u8;
+    message.nlas.push(LinkNla::Other(DefaultNla::new(
+      IFLA_PROTINFO | NLA_F_NESTED,
+      protinfo,
+    )));
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// Makes `vlan` the untagged PVID of bridge port `index`, on a VLAN filtering bridge.
+  pub async fn add_bridge_vlan(&self, index: u32, vlan: u16) -> CniResult<()> {
+    let mut info = BridgeVlanInfo::default();
+    info.flags = BRIDGE_VLAN_INFO_PVID | BRIDGE_VLAN_INFO_UNTAGGED;
+    info.vid = vlan;
+
+    let mut request = self.handle.link().set(index);
+    let message = request.message_mut();
+    message.header.interface_family = AF_BRIDGE as u8;
+    message
+      .nlas
+      .push(LinkNla::AfSpecBridge(vec![AfSpecBridge::VlanInfo(info)]));
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// Renames link `index`, which must be down.
+  pub async fn rename(&self, index: u32, name: &str) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .name(name.to_string())
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  /// Moves link `index` to `netns`, where it is down.
+  pub async fn set_netns(&self, index: u32, netns: &NetNs) -> CniResult<()> {
+    self
+      .handle
+      .link()
+      .set(index)
+      .setns_by_fd(netns.as_fd().as_raw_fd())
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  /// Assigns `address`, in CIDR notation, to link `index`.
+  pub async fn add_address(&self, index: u32, address: IpNetwork) -> CniResult<()> {
+    self
+      .handle
+      .address()
+      .add(index, address.ip(), address.prefix())
+      .execute()
+      .await
+      .map_err(netlink_error)
+  }
+
+  /// Removes `address` from link `index`.
+  pub async fn del_address(&self, index: u32, address: IpNetwork) -> CniResult<()> {
+    let messages = self
+      .handle
+      .address()
+      .get()
+      .set_link_index_filter(index)
+      .set_address_filter(address.ip())
+      .set_prefix_length_filter(address.prefix())
+      .execute()
+      .try_collect::<Vec<_>>()
+      .await
+      .map_err(netlink_error)?;
+    for message in messages {
+      self
+        .handle
+        .address()
+        .del(message)
+        .execute()
+        .await
+        .map_err(netlink_error)?;
+    }
+    Ok(())
+  }
+
+  /// The addresses of link `index`, link-local ones excepted.
+  pub async fn addresses(&self, index: u32) -> CniResult<Vec<IpNetwork>> {
+    let messages = self
+      .handle
+      .address()
+      .get()
+      .set_link_index_filter(index)
+      .execute()
+      .try_collect::<Vec<_>>()
+      .await
+      .map_err(netlink_error)?;
+
+    Ok(
+      messages
+        .into_iter()
+        .filter(|message| message.header.scope != RT_SCOPE_LINK)
+        .filter_map(|message| {
+          let prefix = message.header.prefix_len;
+          message
+            .nlas
+            .into_iter()
+            .find_map(|nla| match nla {
+              AddressNla::Address(bytes) => ip_address(&bytes),
+              _ => None,
+            })
+            .and_then(|ip| IpNetwork::new(ip, prefix).ok())
+        })
+        .collect(),
+    )
+  }
+
+  /// Installs `route` through link `index`. Routes without a gateway are scoped to the link.
+  pub async fn add_route(&self, index: u32, route: &Route) -> CniResult<()> {
+    let mut request = self.handle.route().add().output_interface(index);
+    let message = request.message_mut();
+    message.header.address_family = family(route.dst.ip());
+    message.header.destination_prefix_length = route.dst.prefix();
+    message
+      .nlas
+      .push(RouteNla::Destination(ip_bytes(route.dst.network())));
+    match route.gw {
+      Some(gw) => message.nlas.push(RouteNla::Gateway(ip_bytes(gw))),
+      None => message.header.scope = RT_SCOPE_LINK,
+    }
+    if let Some(priority) = route.priority {
+      message.nlas.push(RouteNla::Priority(priority as u32));
+    }
+    let metrics = [(RTAX_MTU, route.mtu), (RTAX_ADVMSS, route.advmss)]
+      .into_iter()
+      .filter_map(|(kind, value)| value.map(|value| (kind, value as u32)))
+      .collect::<Vec<_>>();
+    if !metrics.is_empty() {
+      message.nlas.push(RouteNla::Metrics(emit_metrics(&metrics)));
+    }
+    request.execute().await.map_err(netlink_error)
+  }
+
+  /// The routes of the main table through link `index`, those installed by the kernel for its
+  /// addresses excepted.
+  pub async fn routes(&self, index: u32) -> CniResult<Vec<Route>> {
+    let mut routes = Vec::new();
+    for version in [IpVersion::V4, IpVersion::V6] {
+      let messages = self
+        .handle
+        .route()
+        .get(version)
+        .execute()
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(netlink_error)?;
+
+      routes.extend(
+        messages
+          .into_iter()
+          .filter(|message| {
+            message.header.table == RT_TABLE_MAIN
+              && message.header.kind == RTN_UNICAST
+              && message.header.protocol != RTPROT_KERNEL
+              && message.nlas.contains(&RouteNla::Oif(index))
+          })
+          .filter_map(route_from_message),
+      );
+    }
+    Ok(routes)
+  }
+
+  /// The link of the IPv4 default route of the main table, if any.
+  pub async fn default_route_link(&self) -> CniResult<Option<Link>> {
+    let messages = self
+      .handle
+      .route()
+      .get(IpVersion::V4)
+      .execute()
+      .try_collect::<Vec<_>>()
+      .await
+      .map_err(netlink_error)?;
+
+    let index = messages.into_iter().find_map(|message| {
+      if message.header.table != RT_TABLE_MAIN || message.header.destination_prefix_length != 0 {
+        return None;
+      }
+      message.nlas.into_iter().find_map(|nla| match nla {
+        RouteNla::Oif(index) => Some(index),
+        _ => None,
+      })
+    });
+    match index {
+      Some(index) => self.link_by_index(index).await.map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// Removes the routes of the main table to `dst` through link `index`, including those installed
+  /// by the kernel for its addresses.
+  pub async fn del_route(&self, index: u32, dst: IpNetwork) -> CniResult<()> {
+    let version = match dst {
+      IpNetwork::V4(_) => IpVersion::V4,
+      IpNetwork::V6(_) => IpVersion::V6,
+    };
+    let messages = self
+      .handle
+      .route()
+      .get(version)
+      .execute()
+      .try_collect::<Vec<_>>()
+      .await
+      .map_err(netlink_error)?;
+
+    for message in messages {
+      let matching = message.header.table == RT_TABLE_MAIN
+        && message.nlas.contains(&RouteNla::Oif(index))
+        && route_from_message(message.clone()).is_some_and(|route| route.dst == dst);
+      if matching {
+        self
+          .handle
+          .route()
+          .del(message)
+          .execute()
+          .await
+          .map_err(netlink_error)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Brings link `index` up and applies the IPs of `result` for its interface `interface`, then
+  /// the routes of `result`. Routes without a gateway go through the gateway of the first IP of
+  /// their family, if any.
+  pub async fn configure(&self, index: u32, result: &AddReply, interface: usize) -> CniResult<()> {
+    self.set_up(index).await?;
+
+    let mut gateways = (None, None);
+    for ips in result.ips.iter() {
+      if ips.interface != Some(interface) {
+        continue;
+      }
+      let address = ips.address.parse::<IpNetwork>().map_err(|e| {
+        CniErrorCode::Internal(format!("invalid address {}: {}", ips.address, e).into())
+      })?;
+      self.add_address(index, address).await?;
+      match address {
+        IpNetwork::V4(_) => gateways.0 = gateways.0.or(ips.gateway),
+        IpNetwork::V6(_) => gateways.1 = gateways.1.or(ips.gateway),
+      }
+    }
+
+    for route in result.routes.iter() {
+      let mut route = route.clone();
+      if route.gw.is_none() {
+        route.gw = match route.dst {
+          IpNetwork::V4(_) => gateways.0,
+          IpNetwork::V6(_) => gateways.1,
+        };
+      }
+      self.add_route(index, &route).await?;
+    }
+    Ok(())
+  }
+
+  /// The state of link `index` as a result, with its addresses and routes. `sandbox` is the
+  /// `CNI_NETNS` of the container the link is in, if any.
+  pub async fn describe(
+    &self,
+    cni_version: semver::Version,
+    index: u32,
+    sandbox: Option<&Path>,
+  ) -> CniResult<AddReply> {
+    let link = self.link_by_index(index).await?;
+    let ips = self
+      .addresses(index)
+      .await?
+      .into_iter()
+      .map(|address| Ips {
+        address:   address.to_string(),
+        gateway:   None,
+        interface: Some(0),
+      })
+      .collect();
+
+    Ok(AddReply {
+      cni_version,
+      interfaces: vec![link.interface(sandbox)],
+      ips,
+      routes: self.routes(index).await?,
+      dns: Default::default(),
+    })
+  }
+
+  async fn require_link(&self, name: &str) -> CniResult<Link> {
+    self
+      .link(name)
+      .await?
+      .ok_or_else(|| CniErrorCode::Internal(format!("link {} not found", name).into()))
+  }
+}
+
+/// A name for the host end of a veth pair, unlikely to be taken.
+pub fn random_veth_name() -> CniResult<String> {
+  let mut bytes = [0u8; 4];
+  std::fs::File::open("/dev/urandom")
+    .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+    .map_err(CniErrorCode::IOFailure)?;
+  Ok(format!("veth{:08x}", u32::from_be_bytes(bytes)))
+}
+
+fn netlink_error(error: rtnetlink::Error) -> CniErrorCode {
+  match error {
+    rtnetlink::Error::NetlinkError(message) => CniErrorCode::IOFailure(message.to_io()),
+    error => CniErrorCode::Internal(error.to_string().into()),
+  }
+}
+
+fn errno(error: &rtnetlink::Error) -> Option<i32> {
+  match error {
+    rtnetlink::Error::NetlinkError(message) => message.to_io().raw_os_error(),
+    _ => None,
+  }
+}
+
+fn family(ip: IpAddr) -> u8 {
+  match ip {
+    IpAddr::V4(_) => AF_INET as u8,
+    IpAddr::V6(_) => AF_INET6 as u8,
+  }
+}
+
+fn ip_bytes(ip: IpAddr) -> Vec<u8> {
+  match ip {
+    IpAddr::V4(ip) => ip.octets().to_vec(),
+    IpAddr::V6(ip) => ip.octets().to_vec(),
+  }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+  match bytes.len() {
+    4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+    16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+    _ => None,
+  }
+}
+
+fn mac_address(bytes: &[u8]) -> Option<MacAddress> {
+  match bytes.len() {
+    6 => <[u8; 6]>::try_from(bytes)
+      .ok()
+      .map(|bytes| MacAddress(macaddr::MacAddr6::from(bytes).into())),
+    8 => <[u8; 8]>::try_from(bytes)
+      .ok()
+      .map(|bytes| MacAddress(macaddr::MacAddr8::from(bytes).into())),
+    _ => None,
+  }
+}
+
+fn route_from_message(message: RouteMessage) -> Option<Route> {
+  let mut destination = None;
+  let mut route = Route {
+    dst:      IpNetwork::new(
+      if message.header.address_family == AF_INET6 as u8 {
+        IpAddr::from([0u8; 16])
+      } else {
+        IpAddr::from([0u8; 4])
+      },
+      0,
+    )
+    .ok()?,
+    gw:       None,
+    mtu:      None,
+    advmss:   None,
+    priority: None,
+  };
+  for nla in message.nlas {
+    match nla {
+      RouteNla::Destination(bytes) => destination = ip_address(&bytes),
+      RouteNla::Gateway(bytes) => route.gw = ip_address(&bytes),
+      RouteNla::Priority(priority) => route.priority = Some(priority as usize),
+      RouteNla::Metrics(bytes) => {
+        for (kind, value) in parse_metrics(&bytes) {
+          match kind {
+            RTAX_MTU => route.mtu = Some(value as usize),
+            RTAX_ADVMSS => route.advmss = Some(value as usize),
+            _ => {}
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  if let Some(destination) = destination {
+    route.dst = IpNetwork::new(destination, message.header.destination_prefix_length).ok()?;
+  }
+  Some(route)
+}
+
+/// Encodes route metrics as the nested `RTAX_*` attributes of `RTA_METRICS`.
+fn emit_metrics(metrics: &[(u16, u32)]) -> Vec<u8> {
+  let mut bytes = Vec::with_capacity(metrics.len() * 8);
+  for (kind, value) in metrics {
+    bytes.extend_from_slice(&8u16.to_ne_bytes());
+    bytes.extend_from_slice(&kind.to_ne_bytes());
+    bytes.extend_from_slice(&value.to_ne_bytes());
+  }
+  bytes
+}
+
+/// Decodes the `u32` metrics among the nested attributes of `RTA_METRICS`.
+fn parse_metrics(mut bytes: &[u8]) -> Vec<(u16, u32)> {
+  let mut metrics = Vec::new();
+  while bytes.len() >= 4 {
+    let length = u16::from_ne_bytes([bytes[0], bytes[1]]) as usize;
+    let kind = u16::from_ne_bytes([bytes[2], bytes[3]]);
+    if length < 4 || length > bytes.len() {
+      break;
+    }
+    if length == 8 {
+      metrics.push((
+        kind,
+        u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+      ));
+    }
+    // Attributes are aligned to 4 bytes.
+    bytes = &bytes[((length + 3) & !3).min(bytes.len())..];
+  }
+  metrics
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::block_on;
+
+  /// A namespace created for a single test, deleted once it is done.
+  struct TestNs {
+    name:  String,
+    netns: NetNs,
+  }
+
+  impl TestNs {
+    fn create(test: &str) -> Self {
+      let name = format!("libcni-{}-{}", test, std::process::id());
+      let netns = NetNs::create(&name).unwrap();
+      Self { name, netns }
+    }
+  }
+
+  impl Drop for TestNs {
+    fn drop(&mut self) {
+      let _ = NetNs::delete(&self.name);
+    }
+  }
+
+  #[test]
+  fn metrics() {
+    let metrics = [(RTAX_MTU, 1400), (RTAX_ADVMSS, 1360)];
+    let bytes = emit_metrics(&metrics);
+    assert_eq!(bytes.len(), 16);
+    assert_eq!(parse_metrics(&bytes), metrics);
+
+    // Attributes other than `u32` ones are skipped, and truncated ones ignored.
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&6u16.to_ne_bytes());
+    bytes.extend_from_slice(&99u16.to_ne_bytes());
+    bytes.extend_from_slice(&[1, 2, 0, 0]);
+    bytes.extend(emit_metrics(&metrics[..1]));
+    bytes.extend_from_slice(&8u16.to_ne_bytes());
+    assert_eq!(parse_metrics(&bytes), &metrics[..1]);
+  }
+
+  #[test]
+  fn veth() {
+    let host = TestNs::create("veth-host");
+    let container = TestNs::create("veth-container");
+    block_on(async {
+      let netlink = Netlink::open(&host.netns).unwrap();
+      let link = netlink
+        .add_veth("veth0", "eth0", &container.netns, Some(1400))
+        .await
+        .unwrap();
+      assert_eq!(link.name, "veth0");
+      assert_eq!(link.kind, Some(InfoKind::Veth));
+      assert_eq!(link.mtu, Some(1400));
+      assert!(netlink.link("eth0").await.unwrap().is_none());
+
+      let peer = Netlink::open(&container.netns)
+        .unwrap()
+        .link("eth0")
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(peer.kind, Some(InfoKind::Veth));
+      assert_eq!(peer.mtu, Some(1400));
+      assert_eq!(peer.parent, Some(link.index));
+      assert!(!peer.up);
+
+      netlink.del_link(link.index).await.unwrap();
+      assert!(netlink.link("veth0").await.unwrap().is_none());
+      Ok(())
+    })
+    .unwrap();
+  }
+
+  #[test]
+  fn bridge() {
+    let host = TestNs::create("bridge-host");
+    let container = TestNs::create("bridge-container");
+    block_on(async {
+      let netlink = Netlink::open(&host.netns).unwrap();
+      let bridge = netlink
+        .ensure_bridge("cni0", Some(1400), false)
+        .await
+        .unwrap();
+      assert_eq!(bridge.kind, Some(InfoKind::Bridge));
+      assert_eq!(bridge.mtu, Some(1400));
+      assert!(bridge.up);
+      let again = netlink.ensure_bridge("cni0", None, false).await.unwrap();
+      assert_eq!(again.index, bridge.index);
+
+      let link = netlink
+        .add_veth("veth0", "eth0", &container.netns, None)
+        .await
+        .unwrap();
+      netlink.set_master(link.index, bridge.index).await.unwrap();
+      let link = netlink.link_by_index(link.index).await.unwrap();
+      assert_eq!(link.master, Some(bridge.index));
+      assert!(netlink.ensure_bridge("veth0", None, false).await.is_err());
+      Ok(())
+    })
+    .unwrap();
+  }
+
+  #[test]
+  fn configure() {
+    let host = TestNs::create("configure-host");
+    let container = TestNs::create("configure-container");
+    block_on(async {
+      let netlink = Netlink::open(&host.netns).unwrap();
+      let link = netlink
+        .add_veth("veth0", "eth0", &container.netns, None)
+        .await
+        .unwrap();
+      let result = serde_json::from_value::<AddReply>(serde_json::json!({
+        "cniVersion": "1.1.0",
+        "ips": [
+          { "address": "10.1.0.5/24", "gateway": "10.1.0.1", "interface": 0 },
+          { "address": "10.9.0.5/24", "interface": 1 }
+        ],
+        "routes": [
+          { "dst": "10.2.0.0/16", "mtu": 1400, "advmss": 1360, "priority": 100 },
+          { "dst": "10.3.0.0/16", "gw": "10.1.0.254" }
+        ]
+      }))
+      .unwrap();
+      netlink.configure(link.index, &result, 0).await.unwrap();
+
+      assert!(netlink.link_by_index(link.index).await.unwrap().up);
+      assert_eq!(
+        netlink.addresses(link.index).await.unwrap(),
+        vec!["10.1.0.5/24".parse::<IpNetwork>().unwrap()]
+      );
+      let mut routes = netlink.routes(link.index).await.unwrap();
+      routes.sort_by_key(|route| route.dst);
+      assert_eq!(
+        serde_json::to_value(&routes).unwrap(),
+        serde_json::json!([
+          {
+            "dst": "10.2.0.0/16",
+            "gw": "10.1.0.1",
+            "mtu": 1400,
+            "advmss": 1360,
+            "priority": 100
+          },
+          { "dst": "10.3.0.0/16", "gw": "10.1.0.254" }
+        ])
+      );
+
+      let sandbox = Path::new("/var/run/netns/blue");
+      let described = netlink
+        .describe(semver::Version::new(1, 1, 0), link.index, Some(sandbox))
+        .await
+        .unwrap();
+      assert_eq!(described.interfaces.len(), 1);
+      assert_eq!(described.interfaces[0].name, "veth0");
+      assert_eq!(
+        described.interfaces[0].mac.as_ref().map(|mac| mac.0),
+        link.mac.map(|mac| mac.0)
+      );
+      assert_eq!(described.interfaces[0].sandbox.as_deref(), Some(sandbox));
+      assert_eq!(described.ips.len(), 1);
+      assert_eq!(described.ips[0].address, "10.1.0.5/24");
+      assert_eq!(described.ips[0].interface, Some(0));
+      assert_eq!(described.routes.len(), 2);
+
+      let dst = "10.2.0.0/16".parse().unwrap();
+      netlink.del_route(link.index, dst).await.unwrap();
+      assert_eq!(netlink.routes(link.index).await.unwrap().len(), 1);
+      let address = "10.1.0.5/24".parse().unwrap();
+      netlink.del_address(link.index, address).await.unwrap();
+      assert!(netlink.addresses(link.index).await.unwrap().is_empty());
+      Ok(())
+    })
+    .unwrap();
+  }
+}
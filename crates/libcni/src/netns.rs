@@ -0,0 +1,134 @@
+/*
+Network namespaces, as referenced by `CNI_NETNS`.
+
+Namespaces are a property of threads, so a namespace is only ever entered on a thread spawned for
+that purpose, which is discarded afterwards. Other threads of the plugin, like those of an async
+runtime, stay in the namespace of the plugin.
+ */
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::os::fd::AsFd;
+use std::os::fd::BorrowedFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use nix::mount::MntFlags;
+use nix::mount::MsFlags;
+use nix::sched::CloneFlags;
+
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+
+/// Where named network namespaces are bind mounted, as by `ip netns`.
+pub const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+/// A network namespace, held open by a file descriptor.
+#[derive(Debug)]
+pub struct NetNs {
+  file: File,
+  path: PathBuf,
+}
+
+impl NetNs {
+  /// Opens the network namespace at `path`, like `/var/run/netns/<name>` or
+  /// `/proc/<pid>/ns/net`.
+  pub fn open<P>(path: P) -> CniResult<Self>
+  where
+    P: AsRef<Path>,
+  {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(CniErrorCode::IOFailure)?;
+    Ok(Self {
+      file,
+      path: path.to_path_buf(),
+    })
+  }
+
+  /// The network namespace of the calling thread.
+  pub fn current() -> CniResult<Self> {
+    Self::open(thread_netns_path())
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Whether both refer to the same namespace, even through different paths.
+  pub fn is_same(&self, other: &NetNs) -> CniResult<bool> {
+    let this = self.file.metadata().map_err(CniErrorCode::IOFailure)?;
+    let other = other.file.metadata().map_err(CniErrorCode::IOFailure)?;
+    Ok(this.dev() == other.dev() && this.ino() == other.ino())
+  }
+
+  /// Runs `f` inside the namespace, on a dedicated thread, and returns its result.
+  pub fn run<F, T>(&self, f: F) -> CniResult<T>
+  where
+    F: FnOnce() -> T + Send,
+    T: Send,
+  {
+    std::thread::scope(|scope| {
+      scope
+        .spawn(|| {
+          nix::sched::setns(self.file.as_fd(), CloneFlags::CLONE_NEWNET)?;
+          Ok(f())
+        })
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+    .map_err(|errno: nix::Error| CniErrorCode::IOFailure(errno.into()))
+  }
+
+  /// Creates a new network namespace, bind mounted to `/var/run/netns/<name>` so that it outlives
+  /// the thread creating it.
+  pub fn create(name: &str) -> CniResult<Self> {
+    std::fs::create_dir_all(NETNS_RUN_DIR).map_err(CniErrorCode::IOFailure)?;
+    let path = Path::new(NETNS_RUN_DIR).join(name);
+    OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&path)
+      .map_err(CniErrorCode::IOFailure)?;
+
+    let mounted = std::thread::scope(|scope| {
+      scope
+        .spawn(|| {
+          nix::sched::unshare(CloneFlags::CLONE_NEWNET)?;
+          nix::mount::mount(
+            Some(&thread_netns_path()),
+            &path,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+          )
+        })
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+    if let Err(errno) = mounted {
+      let _ = std::fs::remove_file(&path);
+      return Err(CniErrorCode::IOFailure(errno.into()));
+    }
+
+    Self::open(path)
+  }
+
+  /// Unmounts and removes the namespace created as `name`. The namespace itself goes away once
+  /// nothing refers to it anymore.
+  pub fn delete(name: &str) -> CniResult<()> {
+    let path = Path::new(NETNS_RUN_DIR).join(name);
+    nix::mount::umount2(&path, MntFlags::MNT_DETACH)
+      .map_err(|errno| CniErrorCode::IOFailure(errno.into()))?;
+    std::fs::remove_file(&path).map_err(CniErrorCode::IOFailure)
+  }
+}
+
+impl AsFd for NetNs {
+  fn as_fd(&self) -> BorrowedFd<'_> {
+    self.file.as_fd()
+  }
+}
+
+fn thread_netns_path() -> PathBuf {
+  PathBuf::from(format!("/proc/self/task/{}/ns/net", nix::unistd::gettid()))
+}
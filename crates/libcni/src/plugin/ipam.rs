@@ -0,0 +1,57 @@
+/*
+[Delegated plugins (IPAM)](https://github.com/containernetworking/cni/blob/main/SPEC.md#delegated-plugins-ipam)
+ */
+use crate::api::invoke::Invoker;
+use crate::api::invoke::RawExec;
+use crate::schema::args::CniAddContext;
+use crate::schema::args::CniCheckContext;
+use crate::schema::args::CniGcContext;
+use crate::schema::args::CniStatusContext;
+use crate::schema::args::CNI_COMMAND;
+use crate::schema::config::NetworkConfig;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::reply::AddReply;
+use crate::schema::CniCommand;
+use crate::schema::CniPath;
+
+/// Allocates addresses with the IPAM plugin of the network configuration.
+pub fn exec_add(args: &CniAddContext) -> CniResult<AddReply> {
+  let stdout = delegate(CniCommand::Add, &args.config, &args.path)?;
+  AddReply::from_slice(&stdout)
+}
+
+/// Checks the allocation of the IPAM plugin of the network configuration.
+pub fn exec_check(args: &CniCheckContext) -> CniResult<()> {
+  delegate(CniCommand::Check, &args.config, &args.path).map(|_| ())
+}
+
+/// Releases addresses with the IPAM plugin of the network configuration, on `DEL` or for a plugin
+/// failing to apply the result of [`exec_add`].
+pub fn exec_del(config: &NetworkConfig, path: &CniPath) -> CniResult<()> {
+  delegate(CniCommand::Del, config, path).map(|_| ())
+}
+
+/// Releases the addresses of stale attachments with the IPAM plugin of the network configuration,
+/// which receives `cni.dev/valid-attachments` as passed to this plugin.
+pub fn exec_gc(args: &CniGcContext) -> CniResult<()> {
+  delegate(CniCommand::GC, &args.config, &args.path).map(|_| ())
+}
+
+/// Checks whether the IPAM plugin of the network configuration is ready to allocate addresses.
+pub fn exec_status(args: &CniStatusContext) -> CniResult<()> {
+  delegate(CniCommand::Status, &args.config, &args.path).map(|_| ())
+}
+
+/// Executes the IPAM plugin with the environment of this plugin, except for `CNI_COMMAND`, and the
+/// full network configuration on stdin.
+fn delegate(command: CniCommand, config: &NetworkConfig, path: &CniPath) -> CniResult<Vec<u8>> {
+  let ipam = config.plugin.ipam.as_ref().ok_or_else(|| {
+    CniErrorCode::InvalidNetworkConfig("missing IPAM configuration in network config".into())
+  })?;
+
+  let plugin = path.find(&ipam.type_)?;
+  let stdin = serde_json::to_vec(config)
+    .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))?;
+  RawExec.exec_plugin(&plugin, &stdin, &[(CNI_COMMAND, command.to_string())])
+}
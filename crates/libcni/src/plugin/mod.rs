@@ -1 +1 @@
-
+pub mod ipam;
@@ -2,19 +2,39 @@
 [Parameters](https://github.com/containernetworking/cni/blob/main/SPEC.md#parameters)
  */
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::str::FromStr;
 
-use crate::schema::config::CniNetworkConfig;
+use ipnetwork::IpNetwork;
+use serde::de::value;
+use serde::de::value::MapDeserializer;
+use serde::de::value::SeqDeserializer;
+use serde::de::DeserializeOwned;
+use serde::de::Error;
+use serde::de::IntoDeserializer;
+use serde::de::Visitor;
+use serde::forward_to_deserialize_any;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+use crate::schema::config::NetworkConfig;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::reply::MacAddress;
+use crate::schema::CniPath;
 use crate::schema::ContainerID;
 use crate::schema::IfName;
 use crate::schema::NetNS;
 
-/// `CNI_COMMAND`: indicates the desired operation; `ADD`, `DEL`, `CHECK`, `GC`, or `VERSION`.
+/// `CNI_COMMAND`: indicates the desired operation; `ADD`, `DEL`, `CHECK`, `GC`, `STATUS`, or
+/// `VERSION`.
 pub const CNI_COMMAND: &str = "CNI_COMMAND";
 /// Container ID. A unique plaintext identifier for a container, allocated by the runtime.
 pub const CNI_CONTAINERID: &str = "CNI_CONTAINERID";
 /// A reference to the container's "isolation domain".
-pub const CNI_NSNAME: &str = "CNI_NSNAME";
+pub const CNI_NETNS: &str = "CNI_NETNS";
 /// Name of the interface to create inside the container.
 pub const CNI_IFNAME: &str = "CNI_IFNAME";
 /// Extra arguments passed in by the user at invocation time.
@@ -22,22 +42,160 @@ pub const CNI_ARGS: &str = "CNI_ARGS";
 /// List of paths to search for CNI plugin executables.
 pub const CNI_PATH: &str = "CNI_PATH";
 
-#[derive(Default)]
+/// Makes plugins ignore the keys of `CNI_ARGS` they do not take, instead of failing, if `1` or
+/// `true`.
+pub const IGNORE_UNKNOWN: &str = "IgnoreUnknown";
+/// Name of the Kubernetes pod of the container.
+pub const K8S_POD_NAME: &str = "K8S_POD_NAME";
+/// Namespace of the Kubernetes pod of the container.
+pub const K8S_POD_NAMESPACE: &str = "K8S_POD_NAMESPACE";
+/// ID of the infrastructure container of the Kubernetes pod, which holds its namespaces.
+pub const K8S_POD_INFRA_CONTAINER_ID: &str = "K8S_POD_INFRA_CONTAINER_ID";
+/// UID of the Kubernetes pod of the container.
+pub const K8S_POD_UID: &str = "K8S_POD_UID";
+/// Addresses requested for the container, in CIDR notation or alone, separated by commas.
+pub const IP: &str = "IP";
+/// MAC address requested for the container interface.
+pub const MAC: &str = "MAC";
+
+/// Keys runtimes pass to every plugin, which are never unknown.
+const WELL_KNOWN: [&str; 5] = [
+  IGNORE_UNKNOWN,
+  K8S_POD_NAME,
+  K8S_POD_NAMESPACE,
+  K8S_POD_INFRA_CONTAINER_ID,
+  K8S_POD_UID,
+];
+
+/// `CNI_ARGS`: `KEY=VALUE` pairs separated by semicolons.
+#[derive(Clone, Debug, Default)]
 pub struct CniArgs {
   args: HashMap<String, String>,
 }
 
+impl CniArgs {
+  /// The value of argument `key`, if passed.
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self.args.get(key).map(String::as_str)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self
+      .args
+      .iter()
+      .map(|(key, value)| (key.as_str(), value.as_str()))
+  }
+
+  /// Decodes the arguments into `T`, which derives `Deserialize` with a field for each key it
+  /// takes. Values are parsed into the types of the fields, and sequences are separated by commas.
+  /// Keys without a field fail, unless well-known or `IgnoreUnknown` is set.
+  pub fn parse<T>(&self) -> CniResult<T>
+  where
+    T: DeserializeOwned,
+  {
+    let ignore_unknown = self.ignore_unknown();
+    let entries = self.iter().map(|(key, value)| {
+      (
+        key,
+        ArgDeserializer {
+          key,
+          value,
+          ignore_unknown,
+        },
+      )
+    });
+    T::deserialize(MapDeserializer::new(entries)).map_err(invalid)
+  }
+
+  /// Whether keys unknown to the plugin are to be ignored.
+  pub fn ignore_unknown(&self) -> bool {
+    self
+      .get(IGNORE_UNKNOWN)
+      .is_some_and(|value| parse_bool(value) == Some(true))
+  }
+
+  pub fn k8s_pod_name(&self) -> Option<&str> {
+    self.get(K8S_POD_NAME)
+  }
+
+  pub fn k8s_pod_namespace(&self) -> Option<&str> {
+    self.get(K8S_POD_NAMESPACE)
+  }
+
+  pub fn k8s_pod_infra_container_id(&self) -> Option<&str> {
+    self.get(K8S_POD_INFRA_CONTAINER_ID)
+  }
+
+  pub fn k8s_pod_uid(&self) -> Option<&str> {
+    self.get(K8S_POD_UID)
+  }
+
+  /// The addresses requested with the `IP` argument, with the prefix length of their network if
+  /// given, like `10.1.0.5/16`.
+  pub fn ip(&self) -> CniResult<Vec<IpNetwork>> {
+    self.get(IP).map_or(Ok(Vec::new()), |value| {
+      Vec::deserialize(ArgDeserializer {
+        key: IP,
+        value,
+        ignore_unknown: true,
+      })
+      .map_err(invalid)
+    })
+  }
+
+  /// The MAC address requested with the `MAC` argument.
+  pub fn mac(&self) -> CniResult<Option<MacAddress>> {
+    self
+      .get(MAC)
+      .map(MacAddress::from_str)
+      .transpose()
+      .map_err(invalid)
+  }
+}
+
+impl FromIterator<(String, String)> for CniArgs {
+  fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+    Self {
+      args: iter.into_iter().collect(),
+    }
+  }
+}
+
 impl FromStr for CniArgs {
-  type Err = std::convert::Infallible;
+  type Err = InvalidArgsEntry;
 
   fn from_str(str: &str) -> Result<Self, Self::Err> {
-    let mut args = HashMap::new();
+    str
+      .split(';')
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| match entry.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(InvalidArgsEntry(entry.to_string())),
+      })
+      .collect()
+  }
+}
 
-    str.split(';').filter(|s| !s.is_empty()).for_each(|entry| {
-      let parts: Vec<String> = entry.splitn(2, '=').map(|s| s.to_string()).collect();
-      args.insert(parts[0].to_string(), parts[1].to_string());
-    });
-    Ok(Self { args })
+/// An entry of `CNI_ARGS` which is not a `KEY=VALUE` pair.
+#[derive(Debug)]
+pub struct InvalidArgsEntry(pub String);
+
+impl Display for InvalidArgsEntry {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid entry {:?}, expected KEY=VALUE", self.0)
+  }
+}
+
+impl std::error::Error for InvalidArgsEntry {}
+
+impl Display for CniArgs {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let args = self
+      .args
+      .iter()
+      .map(|(key, value)| format!("{}={}", key, value))
+      .collect::<Vec<_>>();
+    f.write_str(&args.join(";"))
   }
 }
 
@@ -51,31 +209,374 @@ pub struct CniAddContext {
   pub container_id: ContainerID,
   pub netns:        NetNS,
   pub if_name:      IfName,
-  pub config:       CniNetworkConfig,
+  pub config:       NetworkConfig,
   pub args:         CniArgs,
-  // path:         Vec<PathBuf>,
+  /// Paths to search for plugin executables, like delegated IPAM plugins.
+  pub path:         CniPath,
 }
 
 pub struct CniDelContext {
   pub container_id: ContainerID,
   pub if_name:      IfName,
   pub netns:        Option<NetNS>,
-  pub config:       CniNetworkConfig,
+  pub config:       NetworkConfig,
   pub args:         CniArgs,
-  // path:         Vec<PathBuf>,
+  /// Paths to search for plugin executables, like delegated IPAM plugins.
+  pub path:         CniPath,
 }
 
 pub struct CniCheckContext {
   pub container_id: ContainerID,
   pub netns:        NetNS,
   pub if_name:      IfName,
-  pub config:       CniNetworkConfig,
+  pub config:       NetworkConfig,
   pub args:         CniArgs,
-  // path:         Vec<PathBuf>,
+  /// Paths to search for plugin executables, like delegated IPAM plugins.
+  pub path:         CniPath,
 }
 
-pub struct CniStatusArgs {}
+pub struct CniStatusContext {
+  pub config: NetworkConfig,
+  /// Paths to search for plugin executables, like delegated IPAM plugins.
+  pub path:   CniPath,
+}
 
 pub struct CniGcContext {
-  // path: CniPath,
+  pub config:            NetworkConfig,
+  /// Paths to search for plugin executables, like delegated IPAM plugins.
+  pub path:              CniPath,
+  /// Attachments the runtime still knows about, passed as `cni.dev/valid-attachments`.
+  pub valid_attachments: Vec<GcAttachment>,
+}
+
+impl CniGcContext {
+  pub fn is_valid_attachment(&self, container_id: &str, if_name: &str) -> bool {
+    self
+      .valid_attachments
+      .iter()
+      .any(|attachment| attachment.container_id == container_id && attachment.if_name == if_name)
+  }
+
+  /// Filters the plugin's own state down to the attachments the runtime no longer knows about,
+  /// which are to be released. `attachment` gives the container ID and interface name of an entry.
+  pub fn stale_attachments<T, F>(&self, state: impl IntoIterator<Item = T>, attachment: F) -> Vec<T>
+  where
+    F: Fn(&T) -> (&str, &str),
+  {
+    state
+      .into_iter()
+      .filter(|entry| {
+        let (container_id, if_name) = attachment(entry);
+        !self.is_valid_attachment(container_id, if_name)
+      })
+      .collect()
+  }
+}
+
+/// Deserializes the value of an argument, parsing it into the type asked for.
+struct ArgDeserializer<'a> {
+  key:            &'a str,
+  value:          &'a str,
+  ignore_unknown: bool,
+}
+
+impl ArgDeserializer<'_> {
+  fn parse<T>(&self) -> Result<T, value::Error>
+  where
+    T: FromStr,
+    T::Err: Display,
+  {
+    self
+      .value
+      .parse()
+      .map_err(|e| value::Error::custom(format!("{}={}: {}", self.key, self.value, e)))
+  }
+}
+
+macro_rules! deserialize_parsed {
+  ($($method:ident => $visit:ident,)*) => {
+    $(
+      fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+      where
+        V: Visitor<'de>,
+      {
+        visitor.$visit(self.parse()?)
+      }
+    )*
+  };
+}
+
+impl<'de> Deserializer<'de> for ArgDeserializer<'_> {
+  type Error = value::Error;
+
+  deserialize_parsed! {
+    deserialize_i8 => visit_i8,
+    deserialize_i16 => visit_i16,
+    deserialize_i32 => visit_i32,
+    deserialize_i64 => visit_i64,
+    deserialize_u8 => visit_u8,
+    deserialize_u16 => visit_u16,
+    deserialize_u32 => visit_u32,
+    deserialize_u64 => visit_u64,
+    deserialize_f32 => visit_f32,
+    deserialize_f64 => visit_f64,
+  }
+
+  forward_to_deserialize_any! {
+    char str string bytes byte_buf unit unit_struct newtype_struct tuple tuple_struct map struct
+    identifier
+  }
+
+  fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_str(self.value)
+  }
+
+  fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    let value = parse_bool(self.value)
+      .ok_or_else(|| value::Error::custom(format!("{}={}: not a boolean", self.key, self.value)))?;
+    visitor.visit_bool(value)
+  }
+
+  fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_some(self)
+  }
+
+  /// Splits the value on commas.
+  fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    let items = self
+      .value
+      .split(',')
+      .map(str::trim)
+      .filter(|item| !item.is_empty())
+      .map(|item| ArgDeserializer {
+        key:            self.key,
+        value:          item,
+        ignore_unknown: self.ignore_unknown,
+      });
+    visitor.visit_seq(SeqDeserializer::new(items))
+  }
+
+  fn deserialize_enum<V>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_enum(self.value.into_deserializer())
+  }
+
+  /// Values are ignored for keys without a field.
+  fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    if self.ignore_unknown || WELL_KNOWN.contains(&self.key) {
+      visitor.visit_unit()
+    } else {
+      Err(value::Error::custom(format!(
+        "unknown key {}, set {}=1 to ignore it",
+        self.key, IGNORE_UNKNOWN
+      )))
+    }
+  }
+}
+
+impl<'de> IntoDeserializer<'de, value::Error> for ArgDeserializer<'_> {
+  type Deserializer = Self;
+
+  fn into_deserializer(self) -> Self {
+    self
+  }
+}
+
+/// Booleans of arguments, as parsed by the reference implementation.
+fn parse_bool(value: &str) -> Option<bool> {
+  match value.to_ascii_lowercase().as_str() {
+    "1" | "true" => Some(true),
+    "0" | "false" => Some(false),
+    _ => None,
+  }
+}
+
+fn invalid(error: impl std::error::Error + 'static) -> CniErrorCode {
+  CniErrorCode::InvalidEnvironmentVariable {
+    var:   CNI_ARGS,
+    error: Box::new(error),
+  }
+}
+
+/// An attachment of a container to the network, identified by container ID and interface name.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct GcAttachment {
+  #[serde(rename = "containerID")]
+  pub container_id: ContainerID,
+  #[serde(rename = "ifname")]
+  pub if_name:      IfName,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::schema::error::CniError;
+
+  #[derive(Debug, Deserialize)]
+  struct Args {
+    #[serde(default)]
+    #[serde(rename = "IP")]
+    ip:    Vec<IpNetwork>,
+    #[serde(default)]
+    #[serde(rename = "DEBUG")]
+    debug: Option<bool>,
+    #[serde(default)]
+    #[serde(rename = "VLAN")]
+    vlan:  u32,
+  }
+
+  /// The error `args` fail to parse into [`Args`] with.
+  fn parse_error(args: &str) -> CniErrorCode {
+    args
+      .parse::<CniArgs>()
+      .unwrap()
+      .parse::<Args>()
+      .unwrap_err()
+  }
+
+  #[test]
+  fn from_str() {
+    let args = "FOO=bar;;EMPTY=;EQUALS=a=b;".parse::<CniArgs>().unwrap();
+    assert_eq!(args.get("FOO"), Some("bar"));
+    assert_eq!(args.get("EMPTY"), Some(""));
+    assert_eq!(args.get("EQUALS"), Some("a=b"));
+    assert_eq!(args.iter().count(), 3);
+    assert!("".parse::<CniArgs>().unwrap().get("FOO").is_none());
+
+    let error = "FOO=bar;BAZ".parse::<CniArgs>().unwrap_err();
+    assert_eq!(error.0, "BAZ");
+    assert_eq!(
+      error.to_string(),
+      "invalid entry \"BAZ\", expected KEY=VALUE"
+    );
+    assert_eq!("=bar".parse::<CniArgs>().unwrap_err().0, "=bar");
+  }
+
+  #[test]
+  fn parse() {
+    let args = "IP=10.1.0.5/16,fd00::5/64;DEBUG=true;VLAN=100"
+      .parse::<CniArgs>()
+      .unwrap()
+      .parse::<Args>()
+      .unwrap();
+    assert_eq!(
+      args.ip,
+      vec![
+        "10.1.0.5/16".parse::<IpNetwork>().unwrap(),
+        "fd00::5/64".parse().unwrap()
+      ]
+    );
+    assert_eq!(args.debug, Some(true));
+    assert_eq!(args.vlan, 100);
+
+    let args = "DEBUG=0"
+      .parse::<CniArgs>()
+      .unwrap()
+      .parse::<Args>()
+      .unwrap();
+    assert!(args.ip.is_empty());
+    assert_eq!(args.debug, Some(false));
+    assert_eq!(args.vlan, 0);
+  }
+
+  #[test]
+  fn parse_invalid() {
+    for args in ["DEBUG=yes", "VLAN=-1", "VLAN=vlan", "IP=10.1.0.5/16,nope"] {
+      let error = parse_error(args);
+      assert!(
+        matches!(
+          error,
+          CniErrorCode::InvalidEnvironmentVariable { var: CNI_ARGS, .. }
+        ),
+        "{}",
+        args
+      );
+      assert_eq!(CniError::from(error).code, 4);
+    }
+  }
+
+  #[test]
+  fn unknown_keys() {
+    let error = CniError::from(parse_error("FOO=bar"));
+    assert_eq!(error.code, 4);
+    assert!(error
+      .details
+      .unwrap()
+      .contains("unknown key FOO, set IgnoreUnknown=1 to ignore it"));
+
+    for ignore_unknown in ["1", "true", "TRUE"] {
+      let args = format!("FOO=bar;IgnoreUnknown={};VLAN=7", ignore_unknown)
+        .parse::<CniArgs>()
+        .unwrap();
+      assert!(args.ignore_unknown());
+      assert_eq!(args.parse::<Args>().unwrap().vlan, 7);
+    }
+    let args = "FOO=bar;IgnoreUnknown=0".parse::<CniArgs>().unwrap();
+    assert!(!args.ignore_unknown());
+    assert!(args.parse::<Args>().is_err());
+  }
+
+  #[test]
+  fn well_known() {
+    let args = "K8S_POD_NAME=web;K8S_POD_NAMESPACE=default;K8S_POD_INFRA_CONTAINER_ID=abc;\
+                K8S_POD_UID=0c4a;IgnoreUnknown=0;VLAN=7"
+      .parse::<CniArgs>()
+      .unwrap();
+    assert_eq!(args.parse::<Args>().unwrap().vlan, 7);
+    assert_eq!(args.k8s_pod_name(), Some("web"));
+    assert_eq!(args.k8s_pod_namespace(), Some("default"));
+    assert_eq!(args.k8s_pod_infra_container_id(), Some("abc"));
+    assert_eq!(args.k8s_pod_uid(), Some("0c4a"));
+  }
+
+  #[test]
+  fn ip_and_mac() {
+    let args = "IP=10.1.0.5,fd00::5;MAC=02:00:00:00:00:01"
+      .parse::<CniArgs>()
+      .unwrap();
+    assert_eq!(
+      args.ip().unwrap(),
+      vec![
+        "10.1.0.5/32".parse::<IpNetwork>().unwrap(),
+        "fd00::5/128".parse().unwrap()
+      ]
+    );
+    let networks = "IP=10.1.0.5/16,fd00::5/64".parse::<CniArgs>().unwrap();
+    assert_eq!(
+      networks.ip().unwrap(),
+      vec![
+        "10.1.0.5/16".parse::<IpNetwork>().unwrap(),
+        "fd00::5/64".parse().unwrap()
+      ]
+    );
+    assert_eq!(
+      args.mac().unwrap().map(|mac| mac.0.to_string()),
+      Some("02:00:00:00:00:01".to_string())
+    );
+    assert!(CniArgs::default().ip().unwrap().is_empty());
+    assert!(CniArgs::default().mac().unwrap().is_none());
+    assert!("IP=10.1.0.5/33".parse::<CniArgs>().unwrap().ip().is_err());
+  }
 }
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
 
+use ipnetwork::IpNetwork;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
@@ -8,42 +11,160 @@ use support::semver::deserialize_versions;
 use support::semver::serialize_version;
 use support::semver::serialize_versions;
 
-/// [Configuration format](https://github.com/containernetworking/cni/blob/main/SPEC.md#container-network-interface-cni-specification)
-#[derive(Debug, Deserialize, Serialize)]
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::legacy::deserialize_reply;
+use crate::schema::legacy::serialize_reply;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::MacAddress;
+
+/// Attachments still known to the runtime, passed to plugins with `GC`.
+pub const VALID_ATTACHMENTS: &str = "cni.dev/valid-attachments";
+
+/// Capability arguments passed by the runtime for the capabilities the plugin declares.
+///
+/// [Well-known capabilities](https://github.com/containernetworking/cni/blob/main/CONVENTIONS.md#well-known-capabilities)
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct CniNetworkConfig {
-  /// Semantic Version 2.0 of CNI specification to which this configuration list and all the
-  /// individual configurations conform.
+pub struct RuntimeConfig {
+  /// Pass a mapping from ports on the host to ports in the container network namespace.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub port_mappings:   Option<Vec<PortMapping>>,
+  /// Dynamically configure the IP range(s) for address allocation, as a list of range sets.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ip_ranges:       Option<Vec<Vec<IPRange>>>,
+  /// Dynamically configure interface bandwidth limits.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bandwidth:       Option<BandwidthEntry>,
+  /// Dynamically configure dns according to runtime needs.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dns:             Option<DNSCapability>,
+  /// Dynamically allocate IPs for container interface.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ips:             Option<Vec<IpNetwork>>,
+  /// Dynamically assign MAC address for the container interface.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub mac:             Option<MacAddress>,
+  /// Dynamically assign the InfiniBand GUID of the container interface.
+  #[serde(default)]
+  #[serde(rename = "infinibandGUID")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub infiniband_guid: Option<String>,
+  /// Provide a device identifier, like a PCI address, associated with the interface.
+  #[serde(default)]
+  #[serde(rename = "deviceID")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub device_id:       Option<String>,
+  /// Provide a list of names that will be mapped to the IP addresses assigned to the interface.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub aliases:         Option<Vec<String>>,
+  /// Provide the cgroup path of the pod.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cgroup_path:     Option<String>,
+  /// Extra values...
+  #[serde(flatten)]
+  pub extra_values:    HashMap<String, Value>,
+}
+
+/// The `portMappings` capability.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortMapping {
+  pub host_port:      u16,
+  pub container_port: u16,
+  /// Either `tcp`, `udp` or `sctp`.
+  #[serde(default = "default_protocol")]
+  pub protocol:       String,
+  #[serde(default)]
+  #[serde(rename = "hostIP")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub host_ip:        Option<IpAddr>,
+}
+
+fn default_protocol() -> String {
+  "tcp".to_string()
+}
+
+/// A range of the `ipRanges` capability, also used by IPAM plugins to describe their pools.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IPRange {
+  pub subnet:      IpNetwork,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub range_start: Option<IpAddr>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub range_end:   Option<IpAddr>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gateway:     Option<IpAddr>,
+}
+
+/// The `bandwidth` capability. Rates are in bits per second and bursts in bits.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthEntry {
+  #[serde(default)]
+  pub ingress_rate:  u64,
+  #[serde(default)]
+  pub ingress_burst: u64,
+  #[serde(default)]
+  pub egress_rate:   u64,
+  #[serde(default)]
+  pub egress_burst:  u64,
+}
+
+/// The `dns` capability.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DNSCapability {
+  #[serde(default)]
+  pub servers:  Vec<String>,
+  #[serde(default)]
+  pub searches: Vec<String>,
+  #[serde(default)]
+  pub options:  Vec<String>,
+}
+
+/// The network configuration of a single plugin, as found in a `.conf` file or written to the
+/// plugin's stdin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkConfig {
+  /// Semantic Version 2.0 of CNI specification to which this configuration conforms.
   #[serde(
     deserialize_with = "deserialize_version",
     serialize_with = "serialize_version"
   )]
   pub cni_version: semver::Version,
-  #[serde(rename = "type")]
+  /// Network name.
+  pub name:        String,
+  /// Plugin configuration object.
+  #[serde(flatten)]
+  pub plugin:      PluginConfig,
+  /// The result of the previous plugin in the chain, injected by the runtime.
+  #[serde(default)]
   #[serde(
-    deserialize_with = "deserialize_versions",
-    serialize_with = "serialize_versions"
+    deserialize_with = "deserialize_reply",
+    serialize_with = "serialize_reply",
+    skip_serializing_if = "Option::is_none"
   )]
-  /// List of all CNI versions which this configuration supports.
-  pub cni_versions: Vec<semver::Version>,
-  /// Network name. This should be unique across all network configurations on a host
-  /// (or other administrative domain).
-  pub name: String,
-  /// Either true or false.
-  pub disable_check: bool,
-  /// Either true or false.
-  #[serde(rename = "disableGC")]
-  pub disable_gc: bool,
-  /// Either true or false.
-  pub load_only_inlined_plugins: bool,
-  /// A list of CNI plugins and their configuration, which is a list of plugin configuration
-  /// objects.
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub plugins: Option<Vec<PluginConfig>>,
-  /*
-  Plugin configuration objects:
-  */
+  pub prev_result: Option<AddReply>,
+}
 
+/// [Plugin configuration object](https://github.com/containernetworking/cni/blob/main/SPEC.md#plugin-configuration-objects)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginConfig {
   /*
   Required keys:
    */
@@ -53,32 +174,38 @@ pub struct CniNetworkConfig {
   /*
   Optional keys, used by the protocol:
    */
-  ///
+  /// Capabilities the plugin supports, for which the runtime passes `runtimeConfig`.
   #[serde(default)]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub capabilities:   Option<HashMap<String, bool>>,
   /*
   Reserved keys, used by the protocol:
    */
+  /// Capability arguments, injected by the runtime.
   #[serde(default)]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub runtime_config: Option<RuntimeConfig>,
-  ///
-  pub args:           Vec<String>,
-  /// Any keys starting with `cni.dev/`
-  pub attributes:     Option<HashMap<String, String>>,
+  /// Arbitrary arguments the runtime passes to the plugin.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub args:           Option<HashMap<String, Value>>,
+  /// Any keys starting with `cni.dev/`.
+  #[serde(flatten)]
+  #[serde(deserialize_with = "deserialize_attributes")]
+  pub attributes:     HashMap<String, Value>,
   /*
   Optional keys, well-known:
   These keys are not used by the protocol, but have a standard meaning to plugins.
-  */
+   */
   /// If supported by the plugin, sets up an IP masquerade on the host for this network.
   #[serde(default)]
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
   pub ip_masq:        bool,
-  /// Dictionary with IPAM (IP Address Management) specific values
+  /// Dictionary with IPAM (IP Address Management) specific values.
   #[serde(default)]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub ipam:           Option<IPAMConfig>,
-  /// Dictionary with DNS specific values
+  /// Dictionary with DNS specific values.
   #[serde(default)]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub dns:            Option<DNSConfig>,
@@ -88,57 +215,372 @@ pub struct CniNetworkConfig {
 
   /// Extra values...
   #[serde(flatten)]
+  #[serde(deserialize_with = "deserialize_extra_values")]
   pub extra_values: HashMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct RuntimeConfig {}
+impl PluginConfig {
+  /// Deserializes the plugin-specific configuration into `T`.
+  pub fn parse<T>(&self) -> CniResult<T>
+  where
+    T: DeserializeOwned,
+  {
+    serde_json::to_value(self)
+      .and_then(serde_json::from_value)
+      .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))
+  }
+}
+
+/// Prefix of the keys reserved for attributes of the protocol.
+const ATTRIBUTE_PREFIX: &str = "cni.dev/";
+
+/// Both flattened maps receive every unknown key, so each keeps its share of them.
+fn deserialize_attributes<'de, D>(deserializer: D) -> Result<HashMap<String, Value>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  HashMap::<String, Value>::deserialize(deserializer).map(|values| {
+    values
+      .into_iter()
+      .filter(|(key, _)| key.starts_with(ATTRIBUTE_PREFIX))
+      .collect()
+  })
+}
+
+fn deserialize_extra_values<'de, D>(deserializer: D) -> Result<HashMap<String, Value>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  HashMap::<String, Value>::deserialize(deserializer).map(|values| {
+    values
+      .into_iter()
+      .filter(|(key, _)| !key.starts_with(ATTRIBUTE_PREFIX))
+      .collect()
+  })
+}
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Dictionary with IPAM (IP Address Management) specific values, which are passed on to the IPAM
+/// plugin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PluginConfig {
-  /// Matches the name of the CNI plugin binary on disk.
+pub struct IPAMConfig {
+  /// Matches the name of the IPAM plugin binary on disk.
   #[serde(rename = "type")]
-  pub type_:          String,
-  ///
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub capabilities:   Option<HashMap<String, bool>>,
-  ///
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub runtime_config: Option<RuntimeConfig>,
-  ///
-  pub args:           Vec<String>,
-  /*
-  Other keys
-   */
-
+  pub type_:        String,
   /// Extra values...
   #[serde(flatten)]
   pub extra_values: HashMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct IPAMConfig {
-  #[serde(rename = "type")]
-  pub type_: String,
+impl IPAMConfig {
+  /// Deserializes the plugin-specific IPAM configuration into `T`.
+  pub fn parse<T>(&self) -> CniResult<T>
+  where
+    T: DeserializeOwned,
+  {
+    serde_json::to_value(self)
+      .and_then(serde_json::from_value)
+      .map_err(|e| CniErrorCode::InvalidNetworkConfig(e.to_string().into()))
+  }
 }
 
 /// # DNS configuration
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DNSConfig {
   /// list of a priority-ordered list of DNS nameservers that this network is aware of. Each entry
   /// in the list is a string containing either an IPv4 or an IPv6 address.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
   pub nameservers: Vec<String>,
   /// the local domain used for short hostname lookups.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "String::is_empty")]
   pub domain:      String,
   /// list of priority ordered search domains for short hostname lookups. Will be preferred over
   /// `domain` by most resolvers.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
   pub search:      Vec<String>,
   /// list of options that can be passed to the resolver.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
   pub options:     Vec<String>,
 }
 
-pub struct NetworkConfigList {}
+impl DNSConfig {
+  pub fn is_empty(&self) -> bool {
+    self.nameservers.is_empty()
+      && self.domain.is_empty()
+      && self.search.is_empty()
+      && self.options.is_empty()
+  }
+}
+
+/// [Network configuration list](https://github.com/containernetworking/cni/blob/main/SPEC.md#configuration-format),
+/// as found in a `.conflist` file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkConfigList {
+  /// Semantic Version 2.0 of CNI specification to which this configuration list and all the
+  /// individual configurations conform.
+  #[serde(
+    deserialize_with = "deserialize_version",
+    serialize_with = "serialize_version"
+  )]
+  pub cni_version: semver::Version,
+  /// List of all CNI versions which this configuration supports.
+  #[serde(default)]
+  #[serde(
+    deserialize_with = "deserialize_versions",
+    serialize_with = "serialize_versions",
+    skip_serializing_if = "Vec::is_empty"
+  )]
+  pub cni_versions: Vec<semver::Version>,
+  /// Network name. This should be unique across all network configurations on a host
+  /// (or other administrative domain).
+  pub name: String,
+  /// Either true or false.
+  #[serde(default)]
+  pub disable_check: bool,
+  /// Either true or false.
+  #[serde(default)]
+  #[serde(rename = "disableGC")]
+  pub disable_gc: bool,
+  /// Either true or false.
+  #[serde(default)]
+  pub load_only_inlined_plugins: bool,
+  /// A list of CNI plugins and their configuration, which is a list of plugin configuration
+  /// objects.
+  pub plugins: Vec<PluginConfig>,
+}
+
+impl NetworkConfigList {
+  /// The version the plugins are invoked with: the highest of `cniVersions` known to this
+  /// library, or `cniVersion` otherwise.
+  pub fn version(&self) -> semver::Version {
+    let current = crate::version::current();
+    self
+      .cni_versions
+      .iter()
+      .filter(|version| *version <= &current)
+      .max()
+      .unwrap_or(&self.cni_version)
+      .clone()
+  }
+
+  /// The network configuration of `plugin` within this list, chained after `prev_result`, which
+  /// is passed in the format of the list's version.
+  pub fn network_config(
+    &self,
+    plugin: &PluginConfig,
+    prev_result: Option<AddReply>,
+  ) -> NetworkConfig {
+    let version = self.version();
+    let prev_result = prev_result.map(|mut prev_result| {
+      prev_result.cni_version = version.clone();
+      prev_result
+    });
+
+    NetworkConfig {
+      cni_version: version,
+      name: self.name.clone(),
+      plugin: plugin.clone(),
+      prev_result,
+    }
+  }
+}
+
+impl From<NetworkConfig> for NetworkConfigList {
+  fn from(value: NetworkConfig) -> Self {
+    Self {
+      cni_version: value.cni_version,
+      cni_versions: Vec::new(),
+      name: value.name,
+      disable_check: false,
+      disable_gc: false,
+      load_only_inlined_plugins: false,
+      plugins: Vec::from([value.plugin]),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  /// The example configuration list of the specification.
+  fn conflist() -> Value {
+    json!({
+      "cniVersion": "1.1.0",
+      "cniVersions": ["0.3.1", "0.4.0", "1.0.0", "1.1.0"],
+      "name": "dbnet",
+      "plugins": [
+        {
+          "type": "bridge",
+          "bridge": "cni0",
+          "keyA": ["some more", "plugin specific", "configuration"],
+          "ipam": {
+            "type": "host-local",
+            "subnet": "10.1.0.0/16",
+            "gateway": "10.1.0.1",
+            "routes": [{ "dst": "0.0.0.0/0" }]
+          },
+          "dns": { "nameservers": ["10.1.0.1"] }
+        },
+        {
+          "type": "tuning",
+          "capabilities": { "mac": true },
+          "sysctl": { "net.core.somaxconn": "500" }
+        },
+        {
+          "type": "portmap",
+          "capabilities": { "portMappings": true }
+        }
+      ]
+    })
+  }
+
+  #[test]
+  fn conflist_round_trip() {
+    let list = serde_json::from_value::<NetworkConfigList>(conflist()).unwrap();
+    assert!(!list.disable_check);
+    assert!(!list.disable_gc);
+    assert!(!list.load_only_inlined_plugins);
+    assert_eq!(list.version(), semver::Version::new(1, 1, 0));
+    assert_eq!(list.plugins[0].type_, "bridge");
+    assert_eq!(list.plugins[0].ipam.as_ref().unwrap().type_, "host-local");
+    assert_eq!(list.plugins[0].extra_values["bridge"], "cni0");
+    assert!(list.plugins[1].capabilities.as_ref().unwrap()["mac"]);
+
+    let mut expected = conflist();
+    expected["disableCheck"] = json!(false);
+    expected["disableGC"] = json!(false);
+    expected["loadOnlyInlinedPlugins"] = json!(false);
+    assert_eq!(serde_json::to_value(&list).unwrap(), expected);
+  }
+
+  #[test]
+  fn conflist_flags() {
+    let mut value = conflist();
+    value["disableCheck"] = json!(true);
+    value["disableGC"] = json!(true);
+    value["loadOnlyInlinedPlugins"] = json!(true);
+    let list = serde_json::from_value::<NetworkConfigList>(value.clone()).unwrap();
+    assert!(list.disable_check);
+    assert!(list.disable_gc);
+    assert!(list.load_only_inlined_plugins);
+    assert_eq!(serde_json::to_value(&list).unwrap(), value);
+  }
+
+  #[test]
+  fn conf_round_trip() {
+    let value = json!({
+      "cniVersion": "0.2.0",
+      "name": "mynet",
+      "type": "bridge",
+      "bridge": "cni0",
+      "isGateway": true,
+      "ipMasq": true,
+      "ipam": {
+        "type": "host-local",
+        "subnet": "10.22.0.0/16",
+        "routes": [{ "dst": "0.0.0.0/0" }]
+      }
+    });
+    let config = serde_json::from_value::<NetworkConfig>(value.clone()).unwrap();
+    assert_eq!(config.cni_version, semver::Version::new(0, 2, 0));
+    assert!(config.plugin.ip_masq);
+    assert!(config.prev_result.is_none());
+    assert_eq!(serde_json::to_value(&config).unwrap(), value);
+
+    let list = NetworkConfigList::from(config);
+    assert!(!list.disable_check);
+    assert!(!list.disable_gc);
+    assert!(!list.load_only_inlined_plugins);
+    assert_eq!(list.plugins.len(), 1);
+  }
+
+  #[test]
+  fn attributes() {
+    let value = json!({
+      "cniVersion": "1.1.0",
+      "name": "dbnet",
+      "type": "bridge",
+      "cni.dev/debug": true,
+      "bridge": "cni0"
+    });
+    let config = serde_json::from_value::<NetworkConfig>(value.clone()).unwrap();
+    assert_eq!(
+      config.plugin.attributes,
+      HashMap::from([("cni.dev/debug".to_string(), json!(true))])
+    );
+    assert_eq!(
+      config.plugin.extra_values,
+      HashMap::from([("bridge".to_string(), json!("cni0"))])
+    );
+    assert_eq!(serde_json::to_value(&config).unwrap(), value);
+  }
+
+  /// The configuration passed to the second plugin of the example list.
+  #[test]
+  fn prev_result() {
+    let value = json!({
+      "cniVersion": "1.1.0",
+      "name": "dbnet",
+      "type": "tuning",
+      "sysctl": { "net.core.somaxconn": "500" },
+      "runtimeConfig": { "mac": "00:11:22:33:44:66" },
+      "prevResult": {
+        "cniVersion": "1.1.0",
+        "ips": [{ "address": "10.1.0.5/16", "gateway": "10.1.0.1", "interface": 2 }],
+        "routes": [{ "dst": "0.0.0.0/0" }],
+        "interfaces": [
+          { "name": "cni0", "mac": "00:11:22:33:44:55" },
+          { "name": "veth3243", "mac": "55:44:33:22:11:11" },
+          { "name": "eth0", "mac": "99:88:77:66:55:44", "sandbox": "/var/run/netns/blue" }
+        ],
+        "dns": { "nameservers": ["10.1.0.1"] }
+      }
+    });
+    let config = serde_json::from_value::<NetworkConfig>(value.clone()).unwrap();
+    let prev_result = config.prev_result.as_ref().unwrap();
+    assert_eq!(prev_result.ips[0].address, "10.1.0.5/16");
+    assert_eq!(prev_result.interfaces[2].name, "eth0");
+    assert_eq!(
+      config
+        .plugin
+        .runtime_config
+        .as_ref()
+        .and_then(|config| config.mac.as_ref())
+        .map(|mac| mac.0.to_string()),
+      Some("00:11:22:33:44:66".to_string())
+    );
+    assert_eq!(serde_json::to_value(&config).unwrap(), value);
+  }
+
+  /// A list chains the previous result in the format of its own version.
+  #[test]
+  fn network_config() {
+    let list = serde_json::from_value::<NetworkConfigList>(conflist()).unwrap();
+    let prev_result = serde_json::from_value::<AddReply>(json!({
+      "cniVersion": "0.4.0",
+      "ips": [{ "version": "4", "address": "10.1.0.5/16" }]
+    }))
+    .unwrap();
+    let config = list.network_config(&list.plugins[1], Some(prev_result));
+    assert_eq!(config.cni_version, semver::Version::new(1, 1, 0));
+    assert_eq!(config.name, "dbnet");
+    assert_eq!(
+      serde_json::to_value(&config).unwrap()["prevResult"],
+      json!({
+        "cniVersion": "1.1.0",
+        "interfaces": [],
+        "ips": [{ "address": "10.1.0.5/16" }],
+        "routes": []
+      })
+    );
+  }
+}
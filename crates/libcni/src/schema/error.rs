@@ -9,12 +9,15 @@ use serde::Serialize;
 use support::semver::deserialize_version;
 use support::semver::serialize_version;
 
-///
+use crate::schema::ContainerID;
+
+/// Result of CNI operations, failing with a well-known error code.
 pub type CniResult<T, E = CniErrorCode> = Result<T, E>;
 
 /// # CNI Error
 /// [Error](https://github.com/containernetworking/cni/blob/main/SPEC.md#error)
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CniError {
   /// The protocol version in use - "1.1.0"
   #[serde(
@@ -51,14 +54,20 @@ impl std::error::Error for CniError {}
 #[derive(Debug)]
 pub enum CniErrorCode {
   /// Incompatible CNI version
-  IncompatibleVersion(semver::Version),
+  IncompatibleVersion {
+    version:   semver::Version,
+    supported: Vec<semver::Version>,
+  },
   /// Unsupported field in network configuration.
   /// The error message must contain the key and value of the unsupported field.
-  UnsupportedField,
+  UnsupportedField {
+    key:   String,
+    value: String,
+  },
   /// Container unknown or does not exist.
   /// This error implies the runtime does not need to perform any container network cleanup
   /// (for example, calling the DEL action on the container).
-  UnknownContainer,
+  UnknownContainer(ContainerID),
   /// Invalid necessary environment variables, like `CNI_COMMAND`, `CNI_CONTAINERID`, etc.
   /// The error message must contain the names of invalid variables.
   InvalidEnvironmentVariable {
@@ -69,13 +78,18 @@ pub enum CniErrorCode {
   IOFailure(std::io::Error),
   /// Failed to decode content. For example, failed to unmarshal network config from bytes or
   /// failed to decode version info from string.
-  DecodeContentFailure,
+  DecodeContentFailure(Cow<'static, str>),
   /// Invalid network config. If some validations on network configs do not pass,
   /// this error will be raised.
-  InvalidNetworkConfig,
+  InvalidNetworkConfig(Cow<'static, str>),
   /// Try again later. If the plugin detects some transient condition that should clear up,
   /// it can use this code to notify the runtime it should re-try the operation later.
-  TryAgainLater,
+  TryAgainLater(Cow<'static, str>),
+  /// Plugin is not available, i.e. cannot service `ADD` requests.
+  PluginNotAvailable(Cow<'static, str>),
+  /// Plugin is not available, and existing containers in the network may have limited
+  /// connectivity.
+  PluginNotAvailableLimitedConnectivity(Cow<'static, str>),
 
   // Predefined by this library
   MissingEnvironmentVariable {
@@ -83,44 +97,124 @@ pub enum CniErrorCode {
     error: VarError,
   },
   MissingInput,
-  UnknownCommand,
+  UnknownCommand(String),
+  /// An error reported by an executed plugin.
+  Plugin(CniError),
+  /// Any other failure of the plugin, reported with code 999 like the reference plugins do.
+  Internal(Cow<'static, str>),
   //
   Other {
     code:    usize,
-    message: &'static str,
-    details: &'static str,
+    message: Cow<'static, str>,
+    details: Cow<'static, str>,
   },
 }
 
 impl CniErrorCode {
   pub fn code(&self) -> usize {
     match self {
-      CniErrorCode::IncompatibleVersion(_) => 1,
-      CniErrorCode::UnsupportedField => 2,
-      CniErrorCode::UnknownContainer => 3,
+      CniErrorCode::IncompatibleVersion { .. } => 1,
+      CniErrorCode::UnsupportedField { .. } => 2,
+      CniErrorCode::UnknownContainer(_) => 3,
       CniErrorCode::InvalidEnvironmentVariable { .. } => 4,
       CniErrorCode::IOFailure(_) => 5,
-      CniErrorCode::DecodeContentFailure => 6,
-      CniErrorCode::InvalidNetworkConfig => 7,
-      CniErrorCode::TryAgainLater => 11,
+      CniErrorCode::DecodeContentFailure(_) => 6,
+      CniErrorCode::InvalidNetworkConfig(_) => 7,
+      CniErrorCode::TryAgainLater(_) => 11,
+      CniErrorCode::PluginNotAvailable(_) => 50,
+      CniErrorCode::PluginNotAvailableLimitedConnectivity(_) => 51,
       CniErrorCode::MissingEnvironmentVariable { .. } => 12,
       CniErrorCode::MissingInput => 13,
-      CniErrorCode::UnknownCommand => 14,
-      CniErrorCode::Other { code, .. } => code.clone(),
+      CniErrorCode::UnknownCommand(_) => 14,
+      CniErrorCode::Plugin(error) => error.code(),
+      CniErrorCode::Internal(_) => 999,
+      CniErrorCode::Other { code, .. } => *code,
+    }
+  }
+}
+
+impl From<CniErrorCode> for CniError {
+  fn from(value: CniErrorCode) -> Self {
+    let code = value.code();
+    let (message, details): (Cow<'static, str>, Option<Cow<'static, str>>) = match value {
+      CniErrorCode::IncompatibleVersion { version, supported } => (
+        "incompatible CNI versions".into(),
+        Some(
+          format!(
+            "config is \"{}\", plugin supports [{}]",
+            version,
+            supported
+              .iter()
+              .map(|version| format!("\"{}\"", version))
+              .collect::<Vec<_>>()
+              .join(", ")
+          )
+          .into(),
+        ),
+      ),
+      CniErrorCode::UnsupportedField { key, value } => (
+        format!("unsupported field in network configuration: {}", key).into(),
+        Some(format!("{} = {}", key, value).into()),
+      ),
+      CniErrorCode::UnknownContainer(container_id) => (
+        "unknown container".into(),
+        Some(format!("container {} does not exist", container_id).into()),
+      ),
+      CniErrorCode::InvalidEnvironmentVariable { var, error } => (
+        format!("invalid environment variable {}", var).into(),
+        Some(format!("{}: {}", var, error).into()),
+      ),
+      CniErrorCode::IOFailure(error) => ("I/O failure".into(), Some(error.to_string().into())),
+      CniErrorCode::DecodeContentFailure(details) => {
+        ("failed to decode content".into(), Some(details))
+      }
+      CniErrorCode::InvalidNetworkConfig(details) => {
+        ("invalid network config".into(), Some(details))
+      }
+      CniErrorCode::TryAgainLater(details) => ("try again later".into(), Some(details)),
+      CniErrorCode::PluginNotAvailable(details) => ("plugin not available".into(), Some(details)),
+      CniErrorCode::PluginNotAvailableLimitedConnectivity(details) => (
+        "plugin not available, limited connectivity".into(),
+        Some(details),
+      ),
+      CniErrorCode::MissingEnvironmentVariable { var, error } => (
+        format!("missing environment variable {}", var).into(),
+        Some(format!("{}: {}", var, error).into()),
+      ),
+      CniErrorCode::MissingInput => (
+        "missing input".into(),
+        Some("no network configuration on stdin".into()),
+      ),
+      CniErrorCode::UnknownCommand(command) => (
+        "unknown command".into(),
+        Some(format!("unknown CNI_COMMAND \"{}\"", command).into()),
+      ),
+      CniErrorCode::Plugin(error) => return error,
+      CniErrorCode::Internal(message) => (message, None),
+      CniErrorCode::Other {
+        message, details, ..
+      } => (message, Some(details)),
+    };
+
+    Self {
+      cni_version: crate::version::current(),
+      code,
+      message,
+      details: details.filter(|details| !details.is_empty()),
     }
   }
 }
 
-pub struct UnknownCommandError;
+pub struct UnknownCommandError(pub String);
 
 impl Debug for UnknownCommandError {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    f.write_str("UnknownCommandError")
+    f.debug_tuple("UnknownCommandError").field(&self.0).finish()
   }
 }
 
 impl Display for UnknownCommandError {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    f.write_str("Unknown command error")
+    write!(f, "Unknown command \"{}\"", self.0)
   }
 }
@@ -23,8 +23,14 @@ impl<'de> Deserialize<'de> for MacAddress {
   {
     use serde::de::Error;
     let str = String::deserialize(deserializer)?;
-    macaddr::MacAddr::from_str(&str)
-      .map(Self)
-      .map_err(Error::custom)
+    MacAddress::from_str(&str).map_err(Error::custom)
+  }
+}
+
+impl FromStr for MacAddress {
+  type Err = macaddr::ParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    macaddr::MacAddr::from_str(s).map(Self)
   }
 }
@@ -0,0 +1,532 @@
+/*
+Result formats of specification versions before `1.0.0`, and conversions between them and
+`AddReply`.
+
+[Version considerations](https://github.com/containernetworking/cni/blob/main/SPEC.md#version-considerations)
+ */
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::Value;
+use support::semver::deserialize_version;
+use support::semver::serialize_version;
+
+use crate::schema::config::DNSConfig;
+use crate::schema::error::CniError;
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
+use crate::schema::reply::AddReply;
+use crate::schema::reply::Interface;
+use crate::schema::reply::Ips;
+use crate::schema::reply::Route;
+use crate::version::All;
+use crate::version::PluginInfo;
+
+/// `ADD` result of specification versions `0.1.0` and `0.2.0`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddReply020 {
+  #[serde(
+    deserialize_with = "deserialize_version",
+    serialize_with = "serialize_version"
+  )]
+  pub cni_version: semver::Version,
+  /// The IPv4 configuration of the interface.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ip4:         Option<IPConfig020>,
+  /// The IPv6 configuration of the interface.
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ip6:         Option<IPConfig020>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "DNSConfig::is_empty")]
+  pub dns:         DNSConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IPConfig020 {
+  /// An IP address in CIDR notation.
+  pub ip:      IpNetwork,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gateway: Option<IpAddr>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub routes:  Vec<LegacyRoute>,
+}
+
+/// `ADD` result of specification versions `0.3.0`, `0.3.1` and `0.4.0`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddReply040 {
+  #[serde(
+    deserialize_with = "deserialize_version",
+    serialize_with = "serialize_version"
+  )]
+  pub cni_version: semver::Version,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub interfaces:  Vec<Interface>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub ips:         Vec<Ips040>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub routes:      Vec<LegacyRoute>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "DNSConfig::is_empty")]
+  pub dns:         DNSConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ips040 {
+  /// The IP version, either "4" or "6".
+  pub version:   String,
+  /// An IP address in CIDR notation.
+  pub address:   String,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gateway:   Option<IpAddr>,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub interface: Option<usize>,
+}
+
+/// Routes before `1.0.0` only know of a destination and a gateway.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyRoute {
+  pub dst: IpNetwork,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gw:  Option<IpAddr>,
+}
+
+/// An `ADD` result in the format of its `cniVersion`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum VersionedAddReply {
+  V020(AddReply020),
+  V040(AddReply040),
+  V100(AddReply),
+}
+
+impl VersionedAddReply {
+  /// Converts `reply` to the result format of specification version `version`.
+  pub fn convert(reply: &AddReply, version: &semver::Version) -> CniResult<Self> {
+    let mut reply = reply.clone();
+    reply.cni_version = version.clone();
+
+    if version < &semver::Version::new(0, 3, 0) {
+      AddReply020::try_from(AddReply040::try_from(reply)?).map(Self::V020)
+    } else if version < &semver::Version::new(1, 0, 0) {
+      AddReply040::try_from(reply).map(Self::V040)
+    } else {
+      Ok(Self::V100(reply))
+    }
+  }
+
+  /// Parses a result in the format of the `cniVersion` it declares, `0.1.0` if none.
+  pub fn parse(mut value: Value) -> CniResult<Self> {
+    let version = value
+      .get("cniVersion")
+      .and_then(Value::as_str)
+      .map(semver::Version::parse)
+      .transpose()
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?
+      .unwrap_or_else(|| semver::Version::new(0, 1, 0));
+    if let Some(object) = value.as_object_mut() {
+      object
+        .entry("cniVersion")
+        .or_insert_with(|| Value::from(version.to_string()));
+    }
+
+    if version < semver::Version::new(0, 3, 0) {
+      serde_json::from_value(value).map(Self::V020)
+    } else if version < semver::Version::new(1, 0, 0) {
+      serde_json::from_value(value).map(Self::V040)
+    } else {
+      serde_json::from_value(value).map(Self::V100)
+    }
+    .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+  }
+}
+
+impl From<VersionedAddReply> for AddReply {
+  fn from(value: VersionedAddReply) -> Self {
+    match value {
+      VersionedAddReply::V020(reply) => AddReply::from(AddReply040::from(reply)),
+      VersionedAddReply::V040(reply) => AddReply::from(reply),
+      VersionedAddReply::V100(reply) => reply,
+    }
+  }
+}
+
+impl From<AddReply020> for AddReply040 {
+  fn from(value: AddReply020) -> Self {
+    let mut ips = Vec::new();
+    let mut routes = Vec::new();
+    for (version, config) in [("4", value.ip4), ("6", value.ip6)] {
+      if let Some(config) = config {
+        ips.push(Ips040 {
+          version:   version.to_string(),
+          address:   config.ip.to_string(),
+          gateway:   config.gateway,
+          interface: None,
+        });
+        routes.extend(config.routes);
+      }
+    }
+
+    Self {
+      cni_version: value.cni_version,
+      interfaces: Vec::new(),
+      ips,
+      routes,
+      dns: value.dns,
+    }
+  }
+}
+
+impl TryFrom<AddReply040> for AddReply020 {
+  type Error = CniErrorCode;
+
+  /// Only the first address of each IP version is kept, as there is no way to express more.
+  fn try_from(value: AddReply040) -> Result<Self, Self::Error> {
+    let mut ip4: Option<IPConfig020> = None;
+    let mut ip6: Option<IPConfig020> = None;
+    for ips in value.ips {
+      let ip = parse_address(&ips.address)?;
+      let config = if ip.is_ipv4() { &mut ip4 } else { &mut ip6 };
+      if config.is_none() {
+        *config = Some(IPConfig020 {
+          ip,
+          gateway: ips.gateway,
+          routes: Vec::new(),
+        });
+      }
+    }
+
+    for route in value.routes {
+      let config = if route.dst.is_ipv4() {
+        ip4.as_mut()
+      } else {
+        ip6.as_mut()
+      };
+      if let Some(config) = config {
+        config.routes.push(route);
+      }
+    }
+
+    // Results before 0.3.0 require at least one IP address.
+    if ip4.is_none() && ip6.is_none() {
+      return Err(CniErrorCode::IncompatibleVersion {
+        version:   value.cni_version,
+        supported: All
+          .supported_versions()
+          .into_iter()
+          .filter(|version| version >= &semver::Version::new(0, 3, 0))
+          .collect(),
+      });
+    }
+
+    Ok(Self {
+      cni_version: value.cni_version,
+      ip4,
+      ip6,
+      dns: value.dns,
+    })
+  }
+}
+
+impl From<AddReply040> for AddReply {
+  fn from(value: AddReply040) -> Self {
+    Self {
+      cni_version: value.cni_version,
+      interfaces:  value.interfaces,
+      ips:         value
+        .ips
+        .into_iter()
+        .map(|ips| Ips {
+          address:   ips.address,
+          gateway:   ips.gateway,
+          interface: ips.interface,
+        })
+        .collect(),
+      routes:      value
+        .routes
+        .into_iter()
+        .map(|route| Route {
+          dst:      route.dst,
+          gw:       route.gw,
+          mtu:      None,
+          advmss:   None,
+          priority: None,
+        })
+        .collect(),
+      dns:         value.dns,
+    }
+  }
+}
+
+impl TryFrom<AddReply> for AddReply040 {
+  type Error = CniErrorCode;
+
+  /// Route attributes introduced by `1.0.0` are dropped.
+  fn try_from(value: AddReply) -> Result<Self, Self::Error> {
+    let ips = value
+      .ips
+      .into_iter()
+      .map(|ips| {
+        let version = if parse_address(&ips.address)?.is_ipv4() {
+          "4"
+        } else {
+          "6"
+        };
+        Ok(Ips040 {
+          version:   version.to_string(),
+          address:   ips.address,
+          gateway:   ips.gateway,
+          interface: ips.interface,
+        })
+      })
+      .collect::<CniResult<Vec<_>>>()?;
+
+    Ok(Self {
+      cni_version: value.cni_version,
+      interfaces: value.interfaces,
+      ips,
+      routes: value
+        .routes
+        .into_iter()
+        .map(|route| LegacyRoute {
+          dst: route.dst,
+          gw:  route.gw,
+        })
+        .collect(),
+      dns: value.dns,
+    })
+  }
+}
+
+fn parse_address(address: &str) -> CniResult<IpNetwork> {
+  address
+    .parse::<IpNetwork>()
+    .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+}
+
+/// Serializes a result in the format of its `cniVersion`.
+pub fn serialize_reply<S>(reply: &Option<AddReply>, serializer: S) -> Result<S::Ok, S::Error>
+where
+  S: Serializer,
+{
+  use serde::ser::Error;
+  reply
+    .as_ref()
+    .map(|reply| VersionedAddReply::convert(reply, &reply.cni_version))
+    .transpose()
+    .map_err(|e| Error::custom(CniError::from(e)))?
+    .serialize(serializer)
+}
+
+/// Deserializes a result in the format of the `cniVersion` it declares.
+pub fn deserialize_reply<'de, D>(deserializer: D) -> Result<Option<AddReply>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  use serde::de::Error;
+  Option::<Value>::deserialize(deserializer)?
+    .map(VersionedAddReply::parse)
+    .transpose()
+    .map(|reply| reply.map(AddReply::from))
+    .map_err(|e| Error::custom(CniError::from(e)))
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  /// The example result of the specification, with a second address of each IP version.
+  fn result() -> AddReply {
+    serde_json::from_value(json!({
+      "cniVersion": "1.1.0",
+      "interfaces": [
+        { "name": "cni0", "mac": "00:11:22:33:44:55" },
+        { "name": "veth3243", "mac": "55:44:33:22:11:11" },
+        { "name": "eth0", "mac": "99:88:77:66:55:44", "sandbox": "/var/run/netns/blue" }
+      ],
+      "ips": [
+        { "address": "10.1.0.5/16", "gateway": "10.1.0.1", "interface": 2 },
+        { "address": "10.2.0.5/16", "interface": 2 },
+        { "address": "fd00::5/64", "gateway": "fd00::1", "interface": 2 },
+        { "address": "fd01::5/64", "interface": 2 }
+      ],
+      "routes": [
+        { "dst": "0.0.0.0/0", "mtu": 1400 },
+        { "dst": "::/0", "gw": "fd00::1" }
+      ],
+      "dns": { "nameservers": ["10.1.0.1"] }
+    }))
+    .unwrap()
+  }
+
+  #[test]
+  fn convert() {
+    let cases = [
+      (
+        "1.1.0",
+        serde_json::to_value(AddReply {
+          cni_version: semver::Version::new(1, 1, 0),
+          ..result()
+        })
+        .unwrap(),
+      ),
+      (
+        "0.4.0",
+        json!({
+          "cniVersion": "0.4.0",
+          "interfaces": [
+            { "name": "cni0", "mac": "00:11:22:33:44:55" },
+            { "name": "veth3243", "mac": "55:44:33:22:11:11" },
+            { "name": "eth0", "mac": "99:88:77:66:55:44", "sandbox": "/var/run/netns/blue" }
+          ],
+          "ips": [
+            { "version": "4", "address": "10.1.0.5/16", "gateway": "10.1.0.1", "interface": 2 },
+            { "version": "4", "address": "10.2.0.5/16", "interface": 2 },
+            { "version": "6", "address": "fd00::5/64", "gateway": "fd00::1", "interface": 2 },
+            { "version": "6", "address": "fd01::5/64", "interface": 2 }
+          ],
+          "routes": [
+            { "dst": "0.0.0.0/0" },
+            { "dst": "::/0", "gw": "fd00::1" }
+          ],
+          "dns": { "nameservers": ["10.1.0.1"] }
+        }),
+      ),
+      (
+        "0.2.0",
+        json!({
+          "cniVersion": "0.2.0",
+          "ip4": {
+            "ip": "10.1.0.5/16",
+            "gateway": "10.1.0.1",
+            "routes": [{ "dst": "0.0.0.0/0" }]
+          },
+          "ip6": {
+            "ip": "fd00::5/64",
+            "gateway": "fd00::1",
+            "routes": [{ "dst": "::/0", "gw": "fd00::1" }]
+          },
+          "dns": { "nameservers": ["10.1.0.1"] }
+        }),
+      ),
+    ];
+
+    for (version, expected) in cases {
+      let version = semver::Version::parse(version).unwrap();
+      let converted = VersionedAddReply::convert(&result(), &version).unwrap();
+      let mut actual = serde_json::to_value(converted).unwrap();
+      // MAC addresses are written in upper case.
+      let interfaces = actual.get_mut("interfaces").and_then(Value::as_array_mut);
+      for interface in interfaces.into_iter().flatten() {
+        interface["mac"] = json!(interface["mac"].as_str().unwrap().to_lowercase());
+      }
+      assert_eq!(actual, expected, "cniVersion {}", version);
+    }
+  }
+
+  #[test]
+  fn convert_without_ips_to_020() {
+    let reply = AddReply {
+      ips: Vec::new(),
+      ..result()
+    };
+    let error = VersionedAddReply::convert(&reply, &semver::Version::new(0, 2, 0)).unwrap_err();
+    assert!(matches!(
+      error,
+      CniErrorCode::IncompatibleVersion { ref version, .. } if version == &semver::Version::new(0, 2, 0)
+    ));
+    assert_eq!(error.code(), 1);
+  }
+
+  #[test]
+  fn parse() {
+    let cases = [
+      (
+        json!({
+          "cniVersion": "0.2.0",
+          "ip4": {
+            "ip": "10.1.0.5/16",
+            "gateway": "10.1.0.1",
+            "routes": [{ "dst": "0.0.0.0/0" }]
+          },
+          "dns": { "nameservers": ["10.1.0.1"] }
+        }),
+        json!({
+          "cniVersion": "0.2.0",
+          "interfaces": [],
+          "ips": [{ "address": "10.1.0.5/16", "gateway": "10.1.0.1" }],
+          "routes": [{ "dst": "0.0.0.0/0" }],
+          "dns": { "nameservers": ["10.1.0.1"] }
+        }),
+      ),
+      // Results without a version are of 0.1.0, in the format of 0.2.0.
+      (
+        json!({ "ip6": { "ip": "fd00::5/64" } }),
+        json!({
+          "cniVersion": "0.1.0",
+          "interfaces": [],
+          "ips": [{ "address": "fd00::5/64" }],
+          "routes": []
+        }),
+      ),
+      (
+        json!({
+          "cniVersion": "0.4.0",
+          "interfaces": [{ "name": "eth0", "sandbox": "/var/run/netns/blue" }],
+          "ips": [{ "version": "4", "address": "10.1.0.5/16", "gateway": "10.1.0.1", "interface": 0 }],
+          "routes": [{ "dst": "0.0.0.0/0" }]
+        }),
+        json!({
+          "cniVersion": "0.4.0",
+          "interfaces": [{ "name": "eth0", "sandbox": "/var/run/netns/blue" }],
+          "ips": [{ "address": "10.1.0.5/16", "gateway": "10.1.0.1", "interface": 0 }],
+          "routes": [{ "dst": "0.0.0.0/0" }]
+        }),
+      ),
+      (
+        json!({
+          "cniVersion": "1.0.0",
+          "ips": [{ "address": "10.1.0.5/16" }],
+          "routes": [{ "dst": "0.0.0.0/0", "mtu": 1400, "advmss": 1360, "priority": 10 }]
+        }),
+        json!({
+          "cniVersion": "1.0.0",
+          "interfaces": [],
+          "ips": [{ "address": "10.1.0.5/16" }],
+          "routes": [{ "dst": "0.0.0.0/0", "mtu": 1400, "advmss": 1360, "priority": 10 }]
+        }),
+      ),
+    ];
+
+    for (input, expected) in cases {
+      let reply = AddReply::from(VersionedAddReply::parse(input.clone()).unwrap());
+      assert_eq!(serde_json::to_value(&reply).unwrap(), expected, "{}", input);
+    }
+  }
+
+  #[test]
+  fn parse_invalid_version() {
+    let error = VersionedAddReply::parse(json!({ "cniVersion": "one" })).unwrap_err();
+    assert_eq!(error.code(), 6);
+  }
+}
@@ -4,12 +4,15 @@ use std::fmt::Formatter;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::schema::error::CniErrorCode;
+use crate::schema::error::CniResult;
 use crate::schema::error::UnknownCommandError;
 
 pub mod args;
 pub mod config;
 pub mod error;
 pub mod ext;
+pub mod legacy;
 pub mod reply;
 pub mod runtime;
 
@@ -26,11 +29,39 @@ const PATH_SEPARATOR: char = ';';
 #[cfg(not(target_os = "windows"))]
 const PATH_SEPARATOR: char = ':';
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CniPath {
   paths: Vec<PathBuf>,
 }
 
+impl CniPath {
+  /// Finds the executable of plugin `plugin` in the first path containing it.
+  pub fn find(&self, plugin: &str) -> CniResult<PathBuf> {
+    self
+      .paths
+      .iter()
+      .map(|path| path.join(plugin))
+      .find(|path| path.is_file())
+      .ok_or_else(|| {
+        CniErrorCode::IOFailure(std::io::Error::new(
+          std::io::ErrorKind::NotFound,
+          format!("failed to find plugin \"{}\" in path [{}]", plugin, self),
+        ))
+      })
+  }
+}
+
+impl Display for CniPath {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let paths = self
+      .paths
+      .iter()
+      .map(|path| path.to_string_lossy())
+      .collect::<Vec<_>>();
+    f.write_str(&paths.join(&PATH_SEPARATOR.to_string()))
+  }
+}
+
 impl FromStr for CniPath {
   type Err = std::convert::Infallible;
 
@@ -46,14 +77,42 @@ impl From<CniPath> for Vec<PathBuf> {
   }
 }
 
+impl From<Vec<PathBuf>> for CniPath {
+  fn from(paths: Vec<PathBuf>) -> Self {
+    Self { paths }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub enum CniCommand {
   Add,
   Del,
   Check,
   GC,
+  Status,
   Version,
 }
 
+impl CniCommand {
+  /// The value of `CNI_COMMAND` passed to plugins.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CniCommand::Add => "ADD",
+      CniCommand::Del => "DEL",
+      CniCommand::Check => "CHECK",
+      CniCommand::GC => "GC",
+      CniCommand::Status => "STATUS",
+      CniCommand::Version => "VERSION",
+    }
+  }
+}
+
+impl Display for CniCommand {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
 impl std::error::Error for UnknownCommandError {}
 
 impl FromStr for CniCommand {
@@ -61,12 +120,13 @@ impl FromStr for CniCommand {
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
-      "add" => Ok(CniCommand::Add),
-      "del" => Ok(CniCommand::Del),
-      "check" => Ok(CniCommand::Check),
-      "gc" => Ok(CniCommand::GC),
-      "version" => Ok(CniCommand::Version),
-      _ => Err(UnknownCommandError),
+      "ADD" => Ok(CniCommand::Add),
+      "DEL" => Ok(CniCommand::Del),
+      "CHECK" => Ok(CniCommand::Check),
+      "GC" => Ok(CniCommand::GC),
+      "STATUS" => Ok(CniCommand::Status),
+      "VERSION" => Ok(CniCommand::Version),
+      _ => Err(UnknownCommandError(s.to_string())),
     }
   }
 }
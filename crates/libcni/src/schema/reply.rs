@@ -5,37 +5,61 @@ use ipnetwork::IpNetwork;
 use semver::Version;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
 use support::semver::deserialize_version;
 use support::semver::deserialize_versions;
 use support::semver::serialize_version;
 use support::semver::serialize_versions;
 
 use crate::schema::config::DNSConfig;
+use crate::schema::error::CniError;
+use crate::schema::error::CniErrorCode;
 use crate::schema::error::CniResult;
+use crate::schema::legacy::VersionedAddReply;
 
 pub trait ReplyPayload<'de>: std::fmt::Debug + serde::Serialize + serde::Deserialize<'de> {
   fn code(&self) -> usize {
     0
   }
+
+  /// The payload as written to stdout.
+  fn to_value(&self) -> CniResult<Value> {
+    serde_json::to_value(self).map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+  }
 }
 
-pub fn reply<'de, T>(result: CniResult<T>) -> !
+/// Writes the payload of `result` to stdout, or its error object for requests of `cni_version`,
+/// and exits.
+pub fn reply<'de, T>(result: CniResult<T>, cni_version: Option<&semver::Version>) -> !
 where
   T: ReplyPayload<'de>,
 {
   match result {
-    Ok(reply) => {
-      serde_json::to_writer_pretty(std::io::stdout(), &reply).expect("Failed to serialize reply");
-      std::process::exit(0);
-    }
-    Err(err) => {
-      std::process::exit(err.code() as i32);
-    }
+    Ok(reply) => match reply.to_value() {
+      Ok(value) => {
+        serde_json::to_writer_pretty(std::io::stdout(), &value).expect("Failed to serialize reply");
+        std::process::exit(0);
+      }
+      Err(err) => reply_error(err, cni_version),
+    },
+    Err(err) => reply_error(err, cni_version),
   }
 }
 
+/// Writes the [error](https://github.com/containernetworking/cni/blob/main/SPEC.md#error) object
+/// to stdout and exits. The object is of the version of the request, the current one if unknown.
+pub fn reply_error(error: CniErrorCode, cni_version: Option<&semver::Version>) -> ! {
+  let mut error = CniError::from(error);
+  if let Some(cni_version) = cni_version {
+    error.cni_version = cni_version.clone();
+  }
+  serde_json::to_writer_pretty(std::io::stdout(), &error).expect("Failed to serialize error");
+  // The error code is carried by the error object, as exit codes cannot represent all of them.
+  std::process::exit(1);
+}
+
 /// Plugins must output a JSON object with the following keys upon a successful `ADD` operation
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AddReply {
   /// The same version supplied on input - the string "1.1.0"
@@ -54,10 +78,29 @@ pub struct AddReply {
   #[serde(default)]
   pub routes:      Vec<Route>,
   /// A dictionary consisting of DNS configuration information
+  #[serde(default)]
+  #[serde(skip_serializing_if = "DNSConfig::is_empty")]
   pub dns:         DNSConfig,
 }
 
-impl<'de> ReplyPayload<'de> for AddReply {}
+impl AddReply {
+  /// Parses a result in the format of the `cniVersion` it declares, converting results of
+  /// versions before `1.0.0`.
+  pub fn from_slice(bytes: &[u8]) -> CniResult<Self> {
+    let value = serde_json::from_slice::<Value>(bytes)
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))?;
+    VersionedAddReply::parse(value).map(AddReply::from)
+  }
+}
+
+impl<'de> ReplyPayload<'de> for AddReply {
+  /// Results are written in the format of their `cniVersion`.
+  fn to_value(&self) -> CniResult<Value> {
+    let reply = VersionedAddReply::convert(self, &self.cni_version)?;
+    serde_json::to_value(reply)
+      .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
+  }
+}
 
 /// [](https://github.com/containernetworking/cni/blob/main/SPEC.md#version-success)
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -90,7 +133,9 @@ pub struct Interface {
   /// The isolation domain reference (e.g. path to network namespace) for the interface, or empty
   /// if on the host. For interfaces created inside the container, this should be the value passed
   /// via `CNI_NETNS`.
-  pub sandbox: PathBuf,
+  #[serde(default)]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sandbox: Option<PathBuf>,
 }
 
 /// IPs assigned by this attachment. Plugins may include IPs assigned external to the container.
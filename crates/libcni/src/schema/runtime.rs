@@ -1,11 +1,20 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
 use crate::schema::args::CniArgs;
 use crate::schema::ContainerID;
 use crate::schema::IfName;
 use crate::schema::NetNS;
 
+/// Runtime configuration of an attachment, passed to plugins through the environment and
+/// `runtimeConfig`.
+#[derive(Clone, Debug, Default)]
 pub struct RuntimeConfig {
-  pub container_id: ContainerID,
-  pub netns:        NetNS,
-  pub if_name:      IfName,
-  pub args:         CniArgs,
+  pub container_id:    ContainerID,
+  pub netns:           NetNS,
+  pub if_name:         IfName,
+  pub args:            CniArgs,
+  /// Arguments for capabilities, keyed by capability name.
+  pub capability_args: HashMap<String, Value>,
 }
@@ -1,8 +1,74 @@
+use serde::Deserialize;
+use support::semver::deserialize_version;
+
+use crate::schema::error::CniErrorCode;
 use crate::schema::error::CniResult;
+use crate::schema::CniCommand;
+
+/// Checks whether the plugin is able to answer `command` for a configuration declaring `version`.
+/// Commands introduced by a later specification, like `CHECK` or `GC`, are refused for
+/// configurations conforming to an earlier one.
+///
+/// [Version considerations](https://github.com/containernetworking/cni/blob/main/SPEC.md#version-considerations)
+pub fn check_version(
+  command: &CniCommand,
+  version: &semver::Version,
+  supported: Vec<semver::Version>,
+) -> CniResult<()> {
+  let minimum = minimum_version(command);
+  let supported = supported
+    .into_iter()
+    .filter(|supported| {
+      minimum
+        .as_ref()
+        .map_or(true, |minimum| supported >= minimum)
+    })
+    .collect::<Vec<_>>();
+
+  if supported.contains(version) {
+    Ok(())
+  } else {
+    Err(CniErrorCode::IncompatibleVersion {
+      version: version.clone(),
+      supported,
+    })
+  }
+}
+
+/// The first specification version defining `command`, if it has not been there since `0.1.0`.
+fn minimum_version(command: &CniCommand) -> Option<semver::Version> {
+  match command {
+    CniCommand::Check => Some(semver::Version::new(0, 4, 0)),
+    CniCommand::GC | CniCommand::Status => Some(semver::Version::new(1, 1, 0)),
+    CniCommand::Add | CniCommand::Del | CniCommand::Version => None,
+  }
+}
+
+/// Decodes the `cniVersion` of a network configuration, which defaults to `0.1.0` when absent.
+pub fn decode_config_version(bytes: &[u8]) -> CniResult<semver::Version> {
+  #[derive(Deserialize)]
+  #[serde(rename_all = "camelCase")]
+  struct ConfigVersion {
+    #[serde(default, deserialize_with = "deserialize_optional_version")]
+    cni_version: Option<semver::Version>,
+  }
+
+  fn deserialize_optional_version<'de, D>(
+    deserializer: D,
+  ) -> Result<Option<semver::Version>, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    deserialize_version(deserializer).map(Some)
+  }
 
-pub fn check_version(version: &semver::Version, supported: Vec<semver::Version>) -> CniResult<()> {
-  // TODO: Unimplemented
-  Ok(())
+  serde_json::from_slice::<ConfigVersion>(bytes)
+    .map(|config| {
+      config
+        .cni_version
+        .unwrap_or_else(|| semver::Version::new(0, 1, 0))
+    })
+    .map_err(|e| CniErrorCode::DecodeContentFailure(e.to_string().into()))
 }
 
 pub fn current() -> semver::Version {
@@ -39,3 +105,67 @@ impl PluginInfo for All {
     ])
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn v(version: &str) -> semver::Version {
+    semver::Version::parse(version).unwrap()
+  }
+
+  #[test]
+  fn minimum_versions() {
+    let cases = [
+      (CniCommand::Add, "0.1.0", true),
+      (CniCommand::Del, "0.2.0", true),
+      (CniCommand::Version, "1.1.0", true),
+      (CniCommand::Check, "0.3.1", false),
+      (CniCommand::Check, "0.4.0", true),
+      (CniCommand::Check, "1.1.0", true),
+      (CniCommand::GC, "1.0.0", false),
+      (CniCommand::GC, "1.1.0", true),
+      (CniCommand::Status, "0.4.0", false),
+      (CniCommand::Status, "1.1.0", true),
+    ];
+    for (command, config_version, accepted) in cases {
+      let result = check_version(&command, &v(config_version), All.supported_versions());
+      assert_eq!(result.is_ok(), accepted, "{:?} {}", command, config_version);
+    }
+  }
+
+  #[test]
+  fn unsupported_version() {
+    let error =
+      check_version(&CniCommand::Add, &v("0.3.1"), Legacy.supported_versions()).unwrap_err();
+    let CniErrorCode::IncompatibleVersion { version, supported } = error else {
+      panic!("unexpected error {:?}", error);
+    };
+    assert_eq!(version, v("0.3.1"));
+    assert_eq!(supported, Legacy.supported_versions());
+
+    // Only the versions able to answer the command are reported.
+    let error =
+      check_version(&CniCommand::Check, &v("0.3.1"), All.supported_versions()).unwrap_err();
+    let CniErrorCode::IncompatibleVersion { supported, .. } = error else {
+      panic!("unexpected error {:?}", error);
+    };
+    assert_eq!(supported, [v("0.4.0"), v("1.0.0"), v("1.1.0")]);
+  }
+
+  #[test]
+  fn config_version() {
+    let cases = [
+      (r#"{ "cniVersion": "0.4.0", "name": "net" }"#, "0.4.0"),
+      (r#"{ "name": "net" }"#, "0.1.0"),
+    ];
+    for (config, expected) in cases {
+      assert_eq!(
+        decode_config_version(config.as_bytes()).unwrap(),
+        v(expected)
+      );
+    }
+    assert!(decode_config_version(b"{ \"cniVersion\": \"one\" }").is_err());
+    assert!(decode_config_version(b"garbage").is_err());
+  }
+}